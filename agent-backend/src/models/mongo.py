@@ -122,8 +122,14 @@ class TimeWeightedRetrieverConfig(BaseModel):
     timeWeightField: Optional[str] = Field(default="last_accessed_at")
 
 
+class ExpansionRetrieverConfig(BaseModel):
+    # Match on small chunks but return their whole section or a window of neighbouring chunks
+    expand: Optional[Literal['section', 'window']] = None
+    window_size: Optional[int] = Field(default=1)
+
+
 # Allows me to be lazy in the webapp and include retriever_config keys from multiple types
-class CombinedRetrieverConfig(SelfQueryRetrieverConfig, TimeWeightedRetrieverConfig):
+class CombinedRetrieverConfig(SelfQueryRetrieverConfig, TimeWeightedRetrieverConfig, ExpansionRetrieverConfig):
     pass
 
 
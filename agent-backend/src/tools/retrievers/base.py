@@ -3,14 +3,18 @@ from abc import ABC
 
 from langchain_core.retrievers import BaseRetriever
 
+from langchain_core.vectorstores import VectorStore
+
 from models.mongo import Tool
 from tools.retrievers.callback_handler import RetrieverCallbackHandler
+from tools.retrievers.expansion import expand_results
 from tools.retrievers.filters import create_qdrant_filters, create_pinecone_filters
 
 class BaseToolRetriever(ABC):
     logger: logging.Logger
     tool: Tool
     retriever: BaseRetriever
+    vector_store: VectorStore = None
 
     def __init__(self):
         self.init_logger()
@@ -22,6 +26,7 @@ class BaseToolRetriever(ABC):
     def run(self, query):
         # Perform the query and get results
         results = self.perform_query(query)
+        results = self.expand_results(results)
         print(f"RAG query results: {results}")
         if not results or len(results) == 0:
             return f"NO RESULTS FOUND IN DATASOURCE \"{self.tool.name}\" FOR QUERY: \"{query}\""
@@ -37,6 +42,12 @@ class BaseToolRetriever(ABC):
             'tags': ['rag_retrieval'],
         })
 
+    def expand_results(self, results):
+        expand = self.tool.retriever_config.expand
+        if not expand or not results or self.vector_store is None:
+            return results
+        return expand_results(self.vector_store, results, expand, self.tool.retriever_config.window_size)
+
     def format_results(self, results):
         self.logger.debug(f"{self.__class__.__name__} results: {results}")
         # Note: multi query retriever doesn't have a top k, so we'll slice the array here instead (for now).
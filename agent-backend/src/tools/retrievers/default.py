@@ -10,6 +10,7 @@ from .similarity_search import SimilaritySearchRetriever
 class DefaultRetriever(BaseToolRetriever):
     def __init__(self, tool: Tool, embedding: Embeddings, vector_store: VectorStore):
         self.tool = tool
+        self.vector_store = vector_store
         self.retriever = SimilaritySearchRetriever(
             embedding=embedding,
             vector_store=vector_store,
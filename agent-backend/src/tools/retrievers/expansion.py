@@ -0,0 +1,113 @@
+import json
+import logging
+
+from langchain_community.vectorstores import Qdrant
+from langchain_community.vectorstores.pinecone import Pinecone
+from langchain_core.documents import Document
+from langchain_core.vectorstores import VectorStore
+
+# Payload fields the vector proxy links the chunks of a document with
+DOCUMENT_ID_FIELD = 'ac_document_id'
+SECTION_ID_FIELD = 'ac_section_id'
+CHUNK_INDEX_FIELD = 'ac_chunk_index'
+PREVIOUS_CHUNK_FIELD = 'ac_previous_chunk_id'
+NEXT_CHUNK_FIELD = 'ac_next_chunk_id'
+
+# Stops a section expansion pulling in a whole document that has no titles
+MAX_SECTION_CHUNKS = 50
+
+logger = logging.getLogger(__name__)
+
+
+def _pinecone_index(vector_store: VectorStore):
+    """The index behind a langchain Pinecone store, which it only keeps as a private attribute"""
+    index = getattr(vector_store, '_index', None)
+    return index if callable(getattr(index, 'fetch', None)) else None
+
+
+def _supports_expansion(vector_store: VectorStore) -> bool:
+    if isinstance(vector_store, Qdrant):
+        return True
+    return isinstance(vector_store, Pinecone) and _pinecone_index(vector_store) is not None
+
+
+def _fetch_payloads(vector_store: VectorStore, ids: list[str]) -> dict:
+    if isinstance(vector_store, Qdrant):
+        points = vector_store.client.retrieve(
+            collection_name=vector_store.collection_name,
+            ids=ids,
+            with_payload=True,
+            with_vectors=False,
+        )
+        return {str(point.id): point.payload or {} for point in points}
+    if isinstance(vector_store, Pinecone):
+        index = _pinecone_index(vector_store)
+        if index is None:
+            return {}
+        # The vector proxy writes Pinecone vectors under the JSON encoding of their point ID
+        point_ids = {json.dumps(id): id for id in ids}
+        response = index.fetch(ids=list(point_ids), namespace=getattr(vector_store, '_namespace', None))
+        return {point_ids.get(vector_id, vector_id): vector.metadata or {}
+                for vector_id, vector in response.vectors.items()}
+    return {}
+
+
+def _page_content(payload: dict) -> str:
+    return payload.get('page_content') or payload.get('text') or ''
+
+
+def _expand_document(vector_store: VectorStore, document: Document, mode: str, window_size: int):
+    """Widens a matched chunk to its section or to the chunks either side of it, walking the
+    previous and next chunk links one hop at a time"""
+    metadata = document.metadata
+    section_id = metadata.get(SECTION_ID_FIELD)
+    chunks = {metadata[CHUNK_INDEX_FIELD]: document.page_content}
+    hops = window_size if mode == 'window' else MAX_SECTION_CHUNKS
+    frontier = [(metadata.get(PREVIOUS_CHUNK_FIELD), PREVIOUS_CHUNK_FIELD),
+                (metadata.get(NEXT_CHUNK_FIELD), NEXT_CHUNK_FIELD)]
+    for _ in range(hops):
+        frontier = [(id, direction) for id, direction in frontier if id]
+        if not frontier or len(chunks) >= MAX_SECTION_CHUNKS:
+            break
+        payloads = _fetch_payloads(vector_store, [id for id, _ in frontier])
+        next_frontier = []
+        for id, direction in frontier:
+            payload = payloads.get(id)
+            if payload is None or payload.get(CHUNK_INDEX_FIELD) is None:
+                continue
+            if mode == 'section' and payload.get(SECTION_ID_FIELD) != section_id:
+                continue
+            chunks[payload[CHUNK_INDEX_FIELD]] = _page_content(payload)
+            next_frontier.append((payload.get(direction), direction))
+        frontier = next_frontier
+    chunk_indexes = sorted(chunks)
+    return Document(
+        page_content='\n'.join(chunks[i] for i in chunk_indexes),
+        metadata={**metadata, 'expanded_chunk_indexes': chunk_indexes},
+    )
+
+
+def expand_results(vector_store: VectorStore, results: list, mode: str, window_size: int = 1):
+    """Replaces each matched chunk with its expanded text. A match already covered by the
+    expansion of a better scored one is dropped, so a section is only returned once."""
+    if not _supports_expansion(vector_store):
+        logger.debug(f"Result expansion is not supported for {type(vector_store).__name__}, returning matched chunks")
+        return results
+    covered = set()
+    expanded = []
+    for result in results:
+        document, score = result if isinstance(result, tuple) else (result, None)
+        if not isinstance(document, Document) or document.metadata.get(CHUNK_INDEX_FIELD) is None:
+            # Points written before chunks were linked are returned as they are
+            expanded.append(result)
+            continue
+        document_id = document.metadata.get(DOCUMENT_ID_FIELD)
+        if (document_id, document.metadata[CHUNK_INDEX_FIELD]) in covered:
+            continue
+        try:
+            document = _expand_document(vector_store, document, mode, max(window_size or 1, 1))
+        except Exception as e:
+            logger.warning(f"Could not expand result, returning the matched chunk only: {e}")
+        covered.update((document_id, i) for i in document.metadata.get('expanded_chunk_indexes', []))
+        expanded.append((document, score) if isinstance(result, tuple) else document)
+    return expanded
@@ -13,6 +13,7 @@ from models.mongo import Tool
 class MultiQueryRetriever(BaseToolRetriever):
     def __init__(self, tool: Tool, llm: BaseLanguageModel, vector_store: VectorStore):
         self.tool = tool
+        self.vector_store = vector_store
 
         # Note: this prompt is from https://github.com/langchain-ai/langchain/blob/0c6a3fdd6bc8082ec09db63f65a8d3d0d1173e43/libs/langchain/langchain/retrievers/multi_query.py#L31
         # with a small modification to template the top k from retriever_config
@@ -9,6 +9,7 @@ from .base import BaseToolRetriever
 class SelfQueryRetriever(BaseToolRetriever):
     def __init__(self, tool: Tool, embedding: Embeddings, llm: BaseLanguageModel, vector_store: VectorStore):
         self.tool = tool
+        self.vector_store = vector_store
         self.metadata_field_info = list(
             map(lambda x: AttributeInfo(**x.model_dump()), tool.retriever_config.metadata_field_info))
         self.retriever = LC_SelfQueryRetriever.from_llm(
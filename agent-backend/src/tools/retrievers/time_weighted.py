@@ -11,6 +11,7 @@ class TimeWeightedRetriever(BaseToolRetriever):
 
     def __init__(self, tool: Tool, vector_store: VectorStore):
         self.tool = tool
+        self.vector_store = vector_store
         self.retriever = CustomTimeWeightedVectorStoreRetriever(
             vectorstore=vector_store,
             time_weight_field_name=tool.retriever_config.timeWeightField,
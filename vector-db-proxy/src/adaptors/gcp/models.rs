@@ -1,9 +1,10 @@
 use crate::adaptors::gcp::pubsub::subscribe_to_topic;
-use crate::adaptors::mongo::models::DataSources;
 use crate::init::env_variables::GLOBAL_DATA;
-use crate::messages::models::{MessageQueueConnection, QueueConnectionTypes};
+use crate::init::shutdown::Shutdown;
+use crate::messages::envelope::IngestionEnvelope;
+use crate::messages::models::{AckHandle, MessageQueueConnection, QueueConnectionTypes};
+use crate::messages::scheduler::FairScheduler;
 use crate::messages::tasks::process_message;
-use crossbeam::channel::Sender;
 use futures::StreamExt;
 use google_cloud_pubsub::subscription::MessageStream;
 use mongodb::Database;
@@ -27,8 +28,8 @@ impl MessageQueueConnection for PubSubConnect {
     async fn connect(&self) -> Option<QueueConnectionTypes> {
         let global_data = GLOBAL_DATA.read().await;
         let pubsub_connection = PubSubConnect {
-            topic: global_data.rabbitmq_stream.clone(),
-            subscription: global_data.rabbitmq_stream.clone(),
+            topic: global_data.pubsub_topic.clone(),
+            subscription: global_data.pubsub_subscription.clone(),
         };
         if let Ok(message_stream) = subscribe_to_topic(pubsub_connection).await {
             let stream = Arc::new(Mutex::new(message_stream));
@@ -39,60 +40,56 @@ impl MessageQueueConnection for PubSubConnect {
     }
 }
 
+/// Hands messages from the subscription to up to `PUBSUB_CONCURRENT_HANDLERS` handlers at once.
+/// Handlers only look up the datasource and pass the task to the scheduler, the embedding work
+/// itself happens on the worker pool.
 pub async fn pubsub_consume(
     stream: &Arc<Mutex<MessageStream>>,
     //vector_database_client: Arc<RwLock<dyn VectorDatabase>>,
     mongo_client: Arc<RwLock<Database>>,
-    sender: Sender<(DataSources, Option<String>, String)>,
+    scheduler: Arc<FairScheduler>,
+    shutdown: Shutdown,
 ) {
-    if let Ok(mut stream) = stream.try_lock() {
-        while let Some(message) = stream.next().await {
-            let cloned_message = message.message.clone();
-            let message_attributes = cloned_message.attributes;
-            println!("Message attributes: {:?}", message_attributes);
-            if let Ok(message_string) = String::from_utf8(cloned_message.data) {
-                match message_attributes.get("_stream") {
-                    Some(stream) => {
-                        println!("Stream contents: {}", stream);
-                        let stream_string: String = stream.to_string();
-                        let (datasource_id, stream_config_key, stream_type) =
-                            match message_attributes.get("type") {
-                                Some(stream_type_field_value) => {
-                                    let stream_split: Vec<&str> =
-                                        stream_string.split('_').collect();
-                                    let datasource_id = stream_split[0];
-                                    let stream_type = Some(stream_type_field_value.to_string());
-                                    (datasource_id, None, stream_type)
-                                }
-                                None => {
-                                    let (datasource_id, stream_config_key) = stream_string.split_once('_')
-                                        .expect("Expected string to contain an underscore for splitting");
-                                    (datasource_id, Some(stream_config_key.to_string()), None)
-                                }
-                            };
+    let (concurrent_handlers, ack_deadline_seconds) = {
+        let global_data = GLOBAL_DATA.read().await;
+        (
+            global_data.pubsub_concurrent_handlers.max(1),
+            global_data.pubsub_ack_deadline_secs.clamp(10, 600),
+        )
+    };
+    let mut stream = stream.lock().await;
+    (&mut *stream)
+        .take_until(shutdown.wait())
+        .for_each_concurrent(concurrent_handlers, |message| {
+            let mongo_client = Arc::clone(&mongo_client);
+            let scheduler = Arc::clone(&scheduler);
+            async move {
+                let message_attributes = message.message.attributes.clone();
+                println!("Message attributes: {:?}", message_attributes);
+                let envelope = IngestionEnvelope::parse(&message_attributes, &message.message.data);
+                // Messages are acked once the worker has finished with them so anything still in
+                // flight at shutdown is redelivered
+                let ack = AckHandle::pubsub(message, ack_deadline_seconds);
+                match envelope {
+                    Ok(envelope) => {
                         //let qdrant_client = Arc::clone(&vector_database_client);
-                        let mongo_client = Arc::clone(&mongo_client);
-                        let sender = sender.clone();
-                        println!("Datasource ID: {}", datasource_id);
                         process_message(
-                            message_string,
-                            stream_type,
-                            datasource_id,
-                            stream_config_key,
+                            envelope,
+                            ack,
                             //qdrant_client,
                             mongo_client,
-                            sender,
+                            scheduler,
                         )
                         .await;
                     }
-                    None => {
-                        log::warn!("No stream ID present in message. Can not proceed")
+                    Err(e) => {
+                        log::warn!("Rejecting message from PubSub. Reason: {}", e);
+                        ack.ack().await;
                     }
                 }
-            } else {
-                log::warn!("Could not get message content from PubSub")
             }
-            let _ = message.ack().await;
-        }
-    }
+        })
+        .await;
+    // Nack anything that was pulled but not yet handed to a handler
+    stream.dispose().await;
 }
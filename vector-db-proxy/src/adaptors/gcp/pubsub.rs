@@ -1,14 +1,19 @@
 use anyhow::Result;
 use google_cloud_pubsub::client::{Client, ClientConfig};
-use google_cloud_pubsub::subscription::{MessageStream, SubscriptionConfig};
+use google_cloud_pubsub::subscriber::SubscriberConfig;
+use google_cloud_pubsub::subscription::{MessageStream, SubscribeConfig, SubscriptionConfig};
 use google_cloud_pubsub::topic::TopicConfig;
 
 use crate::adaptors::gcp::models::PubSubConnect;
+use crate::init::env_variables::GLOBAL_DATA;
 
 pub async fn subscribe_to_topic(connection_details: PubSubConnect) -> Result<MessageStream> {
     // Create pubsub client.
     let client_config = ClientConfig::default().with_auth().await?;
     let client = Client::new(client_config).await?;
+    let global_data = GLOBAL_DATA.read().await;
+    // PubSub only accepts ack deadlines between 10 seconds and 10 minutes
+    let ack_deadline_seconds = global_data.pubsub_ack_deadline_secs.clamp(10, 600);
 
     // Get the topic to subscribe to.
     if !client
@@ -32,7 +37,7 @@ pub async fn subscribe_to_topic(connection_details: PubSubConnect) -> Result<Mes
     let topic = client.topic(connection_details.topic.as_str());
 
     // Create subscription
-    let subscription = client.subscription(connection_details.topic.as_str());
+    let subscription = client.subscription(connection_details.subscription.as_str());
     if !subscription.exists(None).await? {
         println!(
             "Subscription: {} does not exist. Creating it now...",
@@ -41,7 +46,10 @@ pub async fn subscribe_to_topic(connection_details: PubSubConnect) -> Result<Mes
         subscription
             .create(
                 topic.fully_qualified_name(),
-                SubscriptionConfig::default(),
+                SubscriptionConfig {
+                    ack_deadline_seconds,
+                    ..Default::default()
+                },
                 None,
             )
             .await?;
@@ -55,6 +63,13 @@ pub async fn subscribe_to_topic(connection_details: PubSubConnect) -> Result<Mes
         subscription.fully_qualified_name()
     );
 
-    let stream = subscription.subscribe(None).await?;
+    // Flow control stops the server sending more messages while this many are waiting to be acked
+    let subscribe_config = SubscribeConfig::default().with_subscriber_config(SubscriberConfig {
+        stream_ack_deadline_seconds: ack_deadline_seconds,
+        max_outstanding_messages: global_data.pubsub_max_outstanding_messages,
+        max_outstanding_bytes: global_data.pubsub_max_outstanding_bytes,
+        ..Default::default()
+    });
+    let stream = subscription.subscribe(Some(subscribe_config)).await?;
     Ok(stream)
 }
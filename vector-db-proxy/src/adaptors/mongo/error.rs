@@ -1,7 +1,7 @@
 use actix_web::http::StatusCode;
 use actix_web::{HttpResponse, ResponseError};
 use anyhow::Error;
-use mongodb::error::Error as MongoError;
+use mongodb::error::{Error as MongoError, ErrorKind, RETRYABLE_WRITE_ERROR};
 use thiserror::Error as ThisError;
 
 #[derive(Debug, ThisError)]
@@ -30,3 +30,17 @@ impl ResponseError for CustomMongoError {
 
 // Short hand alias, which allows you to use just Result<T>
 pub type Result<T> = std::result::Result<T, CustomMongoError>;
+
+/// Whether an error came from MongoDB being unreachable or briefly unable to serve the request,
+/// so the operation is worth trying again later
+pub fn is_transient(error: &Error) -> bool {
+    let Some(error) = error.downcast_ref::<MongoError>() else {
+        return false;
+    };
+    matches!(
+        *error.kind,
+        ErrorKind::Io(_)
+            | ErrorKind::ConnectionPoolCleared { .. }
+            | ErrorKind::ServerSelection { .. }
+    ) || error.contains_label(RETRYABLE_WRITE_ERROR)
+}
@@ -80,6 +80,17 @@ impl UnstructuredPartitioningStrategy {
     }
 }
 
+/// Where documents are partitioned and chunked
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkingBackend {
+    /// In process. Only used for formats it can read, anything else goes to Unstructured IO. PDFs
+    /// are only read in process when this is chosen explicitly.
+    Native,
+    /// The Unstructured IO server at `UNSTRUCTURED_API_URL`
+    Unstructured,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UnstructuredChunkingConfig {
     pub partitioning: UnstructuredPartitioningStrategy,
@@ -90,6 +101,26 @@ pub struct UnstructuredChunkingConfig {
     pub similarity_threshold: f64, // between 0.0 and 1.0
     pub overlap_all: bool,
     pub file_type: Option<FileType>,
+    /// Defaults to the native chunker for formats it can read, except PDFs
+    #[serde(default)]
+    pub backend: Option<ChunkingBackend>,
+}
+
+impl Default for UnstructuredChunkingConfig {
+    /// The settings Unstructured IO chunks with when none are given
+    fn default() -> Self {
+        UnstructuredChunkingConfig {
+            partitioning: UnstructuredPartitioningStrategy::Auto,
+            strategy: UnstructuredChunkingStrategy::Basic,
+            max_characters: 500,
+            new_after_n_chars: 500,
+            overlap: 0,
+            similarity_threshold: 0.5,
+            overlap_all: false,
+            file_type: None,
+            backend: None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -129,6 +160,19 @@ pub struct DataSources {
     pub extra_fields: bson::Document,
     pub region: Option<String>,
     pub cloud: Option<String>,
+    /// Collection an in-progress overwrite sync is writing to. It replaces the live collection
+    /// once the sync completes
+    #[serde(default)]
+    pub staging_collection: Option<String>,
+    /// What to do with text longer than the embedding model accepts. Defaults to truncating
+    #[serde(default)]
+    pub overflow_policy: Option<EmbeddingOverflowPolicy>,
+    /// How the rows of an uploaded CSV, spreadsheet or JSON Lines file are embedded
+    #[serde(default)]
+    pub structured_config: Option<StructuredFileConfig>,
+    /// Hash version every keyed point of the datasource has been migrated to
+    #[serde(default)]
+    pub point_id_hash_version: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -138,6 +182,12 @@ pub struct ModelConfig {
     pub base_url: Option<String>,
     pub cohere_api_key: Option<String>,
     pub groq_api_key: Option<String>,
+    /// Embedding requests per minute allowed on `api_key`, `EMBEDDING_RATE_LIMIT_RPM` if not set
+    #[serde(default)]
+    pub requests_per_minute: Option<u64>,
+    /// Embedding tokens per minute allowed on `api_key`, `EMBEDDING_RATE_LIMIT_TPM` if not set
+    #[serde(default)]
+    pub tokens_per_minute: Option<u64>,
     // Add more fields here if needed
 }
 
@@ -153,6 +203,24 @@ pub struct Model {
     pub config: ModelConfig,
 }
 
+/// Embedding usage summed over a time range, for one datasource and model
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingUsageSummary {
+    /// Not set for embeddings made outside of a datasource
+    #[serde(default)]
+    pub datasource_id: Option<String>,
+    pub model: String,
+    pub calls: i64,
+    /// Calls that returned no embeddings. Their tokens are only counted if they were billed.
+    #[serde(default)]
+    pub failed_calls: i64,
+    pub items: i64,
+    pub input_tokens: i64,
+    pub latency_ms: i64,
+    pub estimated_cost: f64,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct CredentialsObj {
     pub key: Option<String>,
@@ -169,6 +237,66 @@ pub struct Credentials {
     pub createdDate: Option<DateTime>,
     pub credentials: Option<CredentialsObj>,
 }
+/// How text that does not fit in the embedding model's context is embedded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmbeddingOverflowPolicy {
+    /// Embed only as much of the text as fits
+    #[default]
+    Truncate,
+    /// Embed every piece of the text and store the mean of their embeddings
+    Average,
+    /// Store each piece as its own point, linked to the others by a parent ID
+    Split,
+}
+
+/// A file ingested into a datasource and the points it was stored as
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentRecord {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub datasource_id: ObjectId,
+    pub team_id: ObjectId,
+    pub file_name: String,
+    /// Where the file was read from, as given in the ingestion message
+    pub source_location: Value,
+    /// SHA-256 of the file's content
+    pub checksum: String,
+    /// False if some of the file could not be embedded or written, so uploading it again has to
+    /// ingest it even though its checksum has not changed
+    #[serde(default)]
+    pub complete: bool,
+    /// The IDs themselves are kept in the `documentpoints` collection
+    #[serde(default)]
+    pub point_count: u64,
+    /// Point IDs of documents registered before they had a collection of their own
+    #[serde(default, rename = "pointIds", skip_serializing)]
+    pub legacy_point_ids: Vec<String>,
+    /// Embedding model the points were embedded with
+    pub model: String,
+    pub created_date: DateTime,
+    pub updated_date: DateTime,
+}
+
+/// Settings for files read row by row. Each row is stored as a point with its columns in the
+/// payload.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StructuredFileConfig {
+    /// Column whose value is embedded
+    #[serde(default)]
+    pub embedding_column: Option<String>,
+    /// Text embedded for each row, with `{column}` replaced by that column's value. Takes
+    /// precedence over `embedding_column`; with neither set every column is embedded
+    #[serde(default)]
+    pub embedding_template: Option<String>,
+    /// Columns identifying a row, so uploading a new version of the file replaces its rows
+    /// instead of adding to them
+    #[serde(default)]
+    pub primary_key: Vec<String>,
+}
+
 /// Enum representing the sync modes
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -223,6 +351,8 @@ pub struct EmbeddingConfig {
     pub embedding_key: Option<String>,
     pub primary_key: Option<Vec<String>>,
     pub chunking_strategy: Option<UnstructuredChunkingConfig>,
+    pub sync_mode: Option<SyncMode>,
+    pub cursor_field: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1,10 +1,15 @@
-use crate::adaptors::mongo::models::{DataSources, EmbeddingConfig, Model, VectorDbs};
+use crate::adaptors::mongo::models::{
+    DataSources, DocumentRecord, EmbeddingConfig, EmbeddingUsageSummary, Model, VectorDbs,
+};
+use crate::embeddings::usage::{period_start, UsageKey, UsageTotals};
 use anyhow::{anyhow, Result};
 use futures_util::StreamExt;
-use mongodb::bson::doc;
 use mongodb::bson::oid::ObjectId;
-use mongodb::options::FindOneOptions;
+use mongodb::bson::{doc, from_document, to_bson, DateTime, Document};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::{FindOneOptions, ReplaceOptions, UpdateOptions};
 use mongodb::{Collection, Database};
+use serde_json::Value;
 use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -45,14 +50,16 @@ pub async fn get_model(db: &Database, datasource_id: &str) -> Result<Option<Mode
                 Ok(model) => Ok(model), // Return the model if found (could be Some or None)
                 Err(e) => {
                     log::error!("Error: {}", e);
-                    Err(anyhow!("Failed to find model: {}", e))
+                    let message = format!("Failed to find model: {}", e);
+                    Err(anyhow::Error::new(e).context(message))
                 }
             }
         }
         Ok(None) => Ok(None), // Return None if no datasource is found (so there was no 'error' however there was no datasource model found)
         Err(e) => {
             log::error!("Error: {}", e);
-            Err(anyhow!("Failed to find datasource: {}", e))
+            let message = format!("Failed to find datasource: {}", e);
+            Err(anyhow::Error::new(e).context(message))
         }
     }
 }
@@ -73,7 +80,8 @@ pub async fn get_model_and_embedding_key(
         Ok(m) => m,
         Err(e) => {
             log::error!("Failed to find model: {}", e);
-            return Err(anyhow!("Failed to find model: {}", e));
+            let message = format!("Failed to find model: {}", e);
+            return Err(anyhow::Error::new(e).context(message));
         }
     };
 
@@ -86,6 +94,9 @@ pub async fn get_model_and_embedding_key(
         if let Some(config_key) = stream_config_key {
             if let Some(datasource_stream_config) = stream_config.get(config_key.as_str()) {
                 embedding_config.primary_key = Some(datasource_stream_config.primaryKey.clone());
+                embedding_config.sync_mode = Some(datasource_stream_config.syncMode.clone());
+                embedding_config.cursor_field = Some(datasource_stream_config.cursorField.clone())
+                    .filter(|cursor_field| !cursor_field.is_empty());
             }
         }
     }
@@ -94,12 +105,21 @@ pub async fn get_model_and_embedding_key(
 }
 
 pub async fn increment_by_one(db: &Database, datasource_id: &str, field_path: &str) -> Result<()> {
+    increment_by(db, datasource_id, field_path, 1).await
+}
+
+pub async fn increment_by(
+    db: &Database,
+    datasource_id: &str,
+    field_path: &str,
+    amount: i64,
+) -> Result<()> {
     let datasources_collection = db.collection::<DataSources>("datasources");
     let filter = doc! {"_id": ObjectId::from_str(datasource_id)?};
     let start = SystemTime::now();
     let current_unix_timestamp = start.duration_since(UNIX_EPOCH)?.as_millis() as i64;
     let update = doc! {
-        "$inc": { field_path: 1 },
+        "$inc": { field_path: amount },
         "$set": { "recordCount.lastUpdated": current_unix_timestamp }
     };
     let update_options = mongodb::options::UpdateOptions::default();
@@ -139,6 +159,8 @@ pub async fn set_record_count_total(db: &Database, datasource_id: &str, total: i
     }
 }
 
+/// Sets the datasource's status. A datasource in the `error` status keeps it, so an error reported
+/// by the sync is not hidden by rows that are still being embedded.
 pub async fn set_datasource_state(
     db: &Database,
     datasource: DataSources,
@@ -146,7 +168,7 @@ pub async fn set_datasource_state(
 ) -> Result<()> {
     let datasources_collection = db.collection::<DataSources>("datasources");
     let datasource_id = datasource.id;
-    let filter = doc! {"_id": datasource_id};
+    let filter = doc! {"_id": datasource_id, "status": {"$ne": "error"}};
     match datasources_collection
         .update_one(
             filter,
@@ -163,6 +185,202 @@ pub async fn set_datasource_state(
     }
 }
 
+/// Persists the latest checkpoint of a sync under `syncState.<key>` so a resumed sync can pick up
+/// from it.
+pub async fn save_sync_state(
+    db: &Database,
+    datasource_id: &str,
+    state_key: &str,
+    state: &Value,
+) -> Result<()> {
+    let datasources_collection = db.collection::<DataSources>("datasources");
+    let filter = doc! {"_id": ObjectId::from_str(datasource_id)?};
+    let current_unix_timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    // Stream names can contain dots, which Mongo would read as a nested path
+    let state_field = format!("syncState.{}", state_key.replace(['.', '$'], "_"));
+    let update = doc! {
+        "$set": {
+            state_field: to_bson(state)?,
+            "syncState.lastUpdated": current_unix_timestamp
+        }
+    };
+    match datasources_collection
+        .update_one(filter, update, None)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to save sync state. Error: {}", e))
+        }
+    }
+}
+
+/// Marks the datasource as errored with the reason reported by the source
+pub async fn set_datasource_error(
+    db: &Database,
+    datasource_id: &str,
+    message: &str,
+    failure_type: Option<&str>,
+) -> Result<()> {
+    let datasources_collection = db.collection::<DataSources>("datasources");
+    let filter = doc! {"_id": ObjectId::from_str(datasource_id)?};
+    let current_unix_timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let update = doc! {
+        "$set": {
+            "status": "error",
+            "syncError": {
+                "message": message,
+                "failureType": failure_type,
+                "date": current_unix_timestamp
+            }
+        }
+    };
+    match datasources_collection
+        .update_one(filter, update, None)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to set datasource error. Error: {}", e))
+        }
+    }
+}
+
+/// Stores the row estimate for one stream and sets `recordCount.total` to the sum of the estimates
+/// of every stream in the datasource. Both happen in a single update so concurrent estimates can
+/// not overwrite each other's total.
+pub async fn set_stream_row_estimate(
+    db: &Database,
+    datasource_id: &str,
+    stream: &str,
+    row_estimate: i64,
+) -> Result<()> {
+    let datasources_collection = db.collection::<DataSources>("datasources");
+    let filter = doc! {"_id": ObjectId::from_str(datasource_id)?};
+    let estimate_field = format!("recordCount.estimates.{}", stream.replace(['.', '$'], "_"));
+    let update = vec![
+        doc! {"$set": {estimate_field: row_estimate}},
+        doc! {"$set": {"recordCount.total": {"$sum": {"$map": {
+            "input": {"$objectToArray": "$recordCount.estimates"},
+            "in": "$$this.v"
+        }}}}},
+    ];
+    match datasources_collection
+        .update_one(filter, update, None)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to set row estimate. Error: {}", e))
+        }
+    }
+}
+
+/// Sets `recordCount.total` from an estimate covering the whole sync
+pub async fn set_sync_row_estimate(
+    db: &Database,
+    datasource_id: &str,
+    row_estimate: i64,
+) -> Result<()> {
+    let datasources_collection = db.collection::<DataSources>("datasources");
+    let filter = doc! {"_id": ObjectId::from_str(datasource_id)?};
+    let update = doc! {"$set": {"recordCount.total": row_estimate}};
+    match datasources_collection
+        .update_one(filter, update, None)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to set row estimate. Error: {}", e))
+        }
+    }
+}
+
+/// Records `staging_collection` as the collection the datasource's overwrite sync writes to, unless
+/// another worker got there first. Returns the staging collection that is in use.
+pub async fn claim_staging_collection(
+    db: &Database,
+    datasource_id: &str,
+    staging_collection: &str,
+) -> Result<String> {
+    let datasources_collection = db.collection::<DataSources>("datasources");
+    let object_id = ObjectId::from_str(datasource_id)?;
+    let filter = doc! {"_id": object_id, "stagingCollection": null};
+    let update = doc! {"$set": {"stagingCollection": staging_collection}};
+    let result = datasources_collection
+        .update_one(filter, update, None)
+        .await
+        .map_err(|e| anyhow!("Failed to set staging collection. Error: {}", e))?;
+    if result.modified_count == 1 {
+        return Ok(staging_collection.to_string());
+    }
+    get_datasource(db, datasource_id)
+        .await?
+        .and_then(|datasource| datasource.staging_collection)
+        .ok_or(anyhow!(
+            "Datasource: {} has no staging collection",
+            datasource_id
+        ))
+}
+
+/// Makes `collection_name` the collection the datasource is read from and written to
+pub async fn set_collection_name(
+    db: &Database,
+    datasource_id: &str,
+    collection_name: &str,
+) -> Result<()> {
+    let datasources_collection = db.collection::<DataSources>("datasources");
+    let filter = doc! {"_id": ObjectId::from_str(datasource_id)?};
+    let update = doc! {"$set": {"collectionName": collection_name}};
+    match datasources_collection
+        .update_one(filter, update, None)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to set collection name. Error: {}", e))
+        }
+    }
+}
+
+pub async fn clear_staging_collection(db: &Database, datasource_id: &str) -> Result<()> {
+    let datasources_collection = db.collection::<DataSources>("datasources");
+    let filter = doc! {"_id": ObjectId::from_str(datasource_id)?};
+    let update = doc! {"$unset": {"stagingCollection": ""}};
+    match datasources_collection
+        .update_one(filter, update, None)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            log::error!("Error: {}", e);
+            Err(anyhow!("Failed to clear staging collection. Error: {}", e))
+        }
+    }
+}
+
+pub async fn set_point_id_hash_version(
+    db: &Database,
+    datasource_id: &str,
+    version: u64,
+) -> Result<()> {
+    let datasources_collection = db.collection::<DataSources>("datasources");
+    let filter = doc! {"_id": ObjectId::from_str(datasource_id)?};
+    let update = doc! {"$set": {"pointIdHashVersion": version as i64}};
+    match datasources_collection
+        .update_one(filter, update, None)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!("Failed to set point ID hash version. Error: {}", e)),
+    }
+}
+
 pub async fn incremental_total_record_count(
     db: &Database,
     datasource_id: &str,
@@ -228,3 +446,288 @@ pub async fn get_vector_db_details(db: &Database, vector_db_id: ObjectId) -> Opt
         }
     }
 }
+
+/// Adds `totals` to the usage bucket for `key`, creating it if needed
+pub async fn increment_embedding_usage(
+    db: &Database,
+    key: &UsageKey,
+    totals: &UsageTotals,
+) -> Result<()> {
+    let usage_collection = db.collection::<Document>("embeddingusage");
+    let filter = doc! {
+        "teamId": key.team_id,
+        "orgId": key.org_id,
+        "datasourceId": key.datasource_id,
+        "model": &key.model,
+        "periodStart": DateTime::from_millis(key.period_start),
+    };
+    let update = doc! {
+        "$inc": {
+            "calls": totals.calls,
+            "failedCalls": totals.failed_calls,
+            "items": totals.items,
+            "inputTokens": totals.input_tokens,
+            "latencyMs": totals.latency_ms,
+            "estimatedCost": totals.estimated_cost,
+        },
+        "$set": { "lastUpdated": DateTime::now() }
+    };
+    let update_options = mongodb::options::UpdateOptions::builder()
+        .upsert(true)
+        .build();
+    match usage_collection
+        .update_one(filter, update, update_options)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!("Failed to record embedding usage. Error: {}", e)),
+    }
+}
+
+/// Embedding usage of a team between `from` and `to` (milliseconds since the epoch), summed per
+/// datasource and model
+pub async fn get_embedding_usage(
+    db: &Database,
+    team_id: &str,
+    from: Option<i64>,
+    to: Option<i64>,
+    datasource_id: Option<&str>,
+) -> Result<Vec<EmbeddingUsageSummary>> {
+    let usage_collection = db.collection::<Document>("embeddingusage");
+    let mut filter = doc! {"teamId": ObjectId::from_str(team_id)?};
+    let mut period = doc! {};
+    if let Some(from) = from {
+        // The bucket `from` falls in is included
+        period.insert("$gte", DateTime::from_millis(period_start(from)));
+    }
+    if let Some(to) = to {
+        period.insert("$lt", DateTime::from_millis(to));
+    }
+    if !period.is_empty() {
+        filter.insert("periodStart", period);
+    }
+    if let Some(datasource_id) = datasource_id {
+        filter.insert("datasourceId", ObjectId::from_str(datasource_id)?);
+    }
+    let pipeline = vec![
+        doc! {"$match": filter},
+        doc! {"$group": {
+            "_id": {"datasourceId": "$datasourceId", "model": "$model"},
+            "calls": {"$sum": "$calls"},
+            "failedCalls": {"$sum": "$failedCalls"},
+            "items": {"$sum": "$items"},
+            "inputTokens": {"$sum": "$inputTokens"},
+            "latencyMs": {"$sum": "$latencyMs"},
+            "estimatedCost": {"$sum": "$estimatedCost"},
+        }},
+        doc! {"$project": {
+            "_id": 0,
+            "datasourceId": {"$toString": "$_id.datasourceId"},
+            "model": "$_id.model",
+            "calls": 1,
+            "failedCalls": 1,
+            "items": 1,
+            "inputTokens": 1,
+            "latencyMs": 1,
+            "estimatedCost": 1,
+        }},
+        doc! {"$sort": {"datasourceId": 1, "model": 1}},
+    ];
+    let mut usage = vec![];
+    let mut cursor = usage_collection.aggregate(pipeline, None).await?;
+    while let Some(document) = cursor.next().await {
+        usage.push(from_document(document?)?);
+    }
+    Ok(usage)
+}
+
+/// The document registered for the file at `source_location` in a datasource
+pub async fn get_document(
+    db: &Database,
+    datasource_id: &str,
+    source_location: &Value,
+) -> Result<Option<DocumentRecord>> {
+    let documents_collection = db.collection::<DocumentRecord>("documents");
+    let filter = doc! {
+        "datasourceId": ObjectId::from_str(datasource_id)?,
+        "sourceLocation": to_bson(source_location)?,
+    };
+    documents_collection
+        .find_one(filter, None)
+        .await
+        .map_err(|e| anyhow!("Failed to get document. Error: {}", e))
+}
+
+pub async fn get_document_by_id(
+    db: &Database,
+    datasource_id: &str,
+    document_id: &str,
+) -> Result<Option<DocumentRecord>> {
+    let documents_collection = db.collection::<DocumentRecord>("documents");
+    let filter = doc! {
+        "_id": ObjectId::from_str(document_id)?,
+        "datasourceId": ObjectId::from_str(datasource_id)?,
+    };
+    documents_collection
+        .find_one(filter, None)
+        .await
+        .map_err(|e| anyhow!("Failed to get document. Error: {}", e))
+}
+
+pub async fn get_datasource_documents(
+    db: &Database,
+    datasource_id: &str,
+) -> Result<Vec<DocumentRecord>> {
+    let documents_collection = db.collection::<DocumentRecord>("documents");
+    let filter = doc! {"datasourceId": ObjectId::from_str(datasource_id)?};
+    let mut documents = vec![];
+    let mut cursor = documents_collection.find(filter, None).await?;
+    while let Some(document) = cursor.next().await {
+        documents.push(document?);
+    }
+    Ok(documents)
+}
+
+/// Inserts the document, or replaces the one with the same ID
+pub async fn save_document(db: &Database, document: &DocumentRecord) -> Result<()> {
+    let documents_collection = db.collection::<DocumentRecord>("documents");
+    let options = ReplaceOptions::builder().upsert(true).build();
+    match documents_collection
+        .replace_one(doc! {"_id": document.id}, document, options)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!("Failed to save document. Error: {}", e)),
+    }
+}
+
+pub async fn delete_document_record(db: &Database, document_id: ObjectId) -> Result<()> {
+    let documents_collection = db.collection::<DocumentRecord>("documents");
+    match documents_collection
+        .delete_one(doc! {"_id": document_id}, None)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!("Failed to delete document. Error: {}", e)),
+    }
+}
+
+/// Forgets every document of a datasource, for when its collection is deleted
+pub async fn delete_datasource_documents(db: &Database, datasource_id: &str) -> Result<()> {
+    let documents_collection = db.collection::<DocumentRecord>("documents");
+    let filter = doc! {"datasourceId": ObjectId::from_str(datasource_id)?};
+    if let Err(e) = documents_collection.delete_many(filter.clone(), None).await {
+        return Err(anyhow!("Failed to delete documents. Error: {}", e));
+    }
+    let points_collection = db.collection::<Document>("documentpoints");
+    match points_collection.delete_many(filter, None).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!("Failed to delete document points. Error: {}", e)),
+    }
+}
+
+/// IDs of the points a document was stored as
+pub async fn get_document_points(db: &Database, document_id: ObjectId) -> Result<Vec<String>> {
+    let points_collection = db.collection::<Document>("documentpoints");
+    let mut point_ids = vec![];
+    let mut cursor = points_collection
+        .find(doc! {"documentId": document_id}, None)
+        .await?;
+    while let Some(point) = cursor.next().await {
+        point_ids.push(point?.get_str("pointId")?.to_string());
+    }
+    Ok(point_ids)
+}
+
+/// Replaces the points registered for a document. They are stored one per entry rather than in
+/// the document, which a large file's IDs would push past Mongo's 16MB document limit.
+pub async fn set_document_points(
+    db: &Database,
+    datasource_id: ObjectId,
+    document_id: ObjectId,
+    point_ids: &[String],
+) -> Result<()> {
+    delete_document_points(db, document_id).await?;
+    if point_ids.is_empty() {
+        return Ok(());
+    }
+    let points_collection = db.collection::<Document>("documentpoints");
+    let points = point_ids.iter().map(|point_id| {
+        doc! {
+            "datasourceId": datasource_id,
+            "documentId": document_id,
+            "pointId": point_id,
+        }
+    });
+    match points_collection.insert_many(points, None).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!("Failed to save document points. Error: {}", e)),
+    }
+}
+
+pub async fn delete_document_points(db: &Database, document_id: ObjectId) -> Result<()> {
+    let points_collection = db.collection::<Document>("documentpoints");
+    match points_collection
+        .delete_many(doc! {"documentId": document_id}, None)
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!("Failed to delete document points. Error: {}", e)),
+    }
+}
+
+/// Stores `cursor` as the cursor of the newest version of a row written to the point, unless a
+/// newer version has been stored already. The check and the write are one update, so workers in
+/// different processes can not both claim the point. Returns false if a newer version was stored.
+/// Cursors are only compared with cursors of the same type, like `compare_cursors` does.
+pub async fn claim_point_cursor(
+    db: &Database,
+    datasource_id: &str,
+    point_id: &str,
+    cursor: &Value,
+) -> Result<bool> {
+    let cursor_type = match cursor {
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        _ => return Ok(true),
+    };
+    let point_cursors_collection = db.collection::<Document>("pointcursors");
+    let cursor = to_bson(cursor)?;
+    // A newer cursor makes the filter miss, and the upsert then fails on the existing ID
+    let filter = doc! {
+        "_id": format!("{}:{}", datasource_id, point_id),
+        "$or": [
+            {"cursor": {"$lte": &cursor}},
+            {"cursor": {"$not": {"$type": cursor_type}}},
+        ],
+    };
+    let update = doc! {
+        "$set": {"cursor": cursor, "datasourceId": ObjectId::from_str(datasource_id)?}
+    };
+    let options = UpdateOptions::builder().upsert(true).build();
+    match point_cursors_collection
+        .update_one(filter, update, options)
+        .await
+    {
+        Ok(_) => Ok(true),
+        Err(e) if is_duplicate_key_error(&e) => Ok(false),
+        Err(e) => Err(anyhow!("Failed to claim point cursor. Error: {}", e)),
+    }
+}
+
+fn is_duplicate_key_error(error: &mongodb::error::Error) -> bool {
+    matches!(
+        error.kind.as_ref(),
+        ErrorKind::Write(WriteFailure::WriteError(write_error)) if write_error.code == 11000
+    )
+}
+
+/// Forgets the cursors stored for the points of a datasource, for when its collection is deleted
+pub async fn delete_point_cursors(db: &Database, datasource_id: &str) -> Result<()> {
+    let point_cursors_collection = db.collection::<Document>("pointcursors");
+    let filter = doc! {"datasourceId": ObjectId::from_str(datasource_id)?};
+    match point_cursors_collection.delete_many(filter, None).await {
+        Ok(_) => Ok(()),
+        Err(e) => Err(anyhow!("Failed to delete point cursors. Error: {}", e)),
+    }
+}
@@ -1,4 +1,6 @@
-use crate::adaptors::pinecone::helpers::{get_index_model, get_indexes, upsert};
+use crate::adaptors::pinecone::helpers::{
+    get_index_model, get_indexes, point_id, upsert, vector_id,
+};
 use crate::vector_databases::error::VectorDatabaseError;
 use crate::vector_databases::models::{
     CollectionCreate, CollectionMetadata, CollectionsResult, Distance, Point, Region,
@@ -316,6 +318,129 @@ impl VectorDatabase for PineconeClient {
         ))
     }
 
+    async fn delete_points_by_ids(
+        &self,
+        search_request: SearchRequest,
+        ids: Vec<String>,
+    ) -> Result<VectorDatabaseStatus, VectorDatabaseError> {
+        let region = search_request.region.unwrap_or(Region::US_EAST_1);
+        let namespace = search_request
+            .clone()
+            .namespace
+            .map_or(search_request.clone().collection, |n| n);
+        let index_name = search_request
+            .byo_vector_db
+            .filter(|k| *k)
+            .map_or(Region::to_str(region), |_| {
+                search_request.collection.as_str()
+            })
+            .to_string();
+        let ids: Vec<String> = ids.iter().map(|id| vector_id(id)).collect();
+        let ids: Vec<&str> = ids.iter().map(|id| id.as_str()).collect();
+        match get_index_model(&self, index_name).await {
+            Ok(index_model) => match self.index(index_model.host.as_str()).await {
+                Ok(mut index) => match index.delete_by_id(&ids, &namespace.into()).await {
+                    Ok(_) => Ok(VectorDatabaseStatus::Ok),
+                    Err(e) => Err(VectorDatabaseError::PineconeError(Arc::new(e))),
+                },
+                Err(e) => Err(VectorDatabaseError::PineconeError(Arc::new(e))),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Pinecone merges the new metadata into the existing metadata, so fields that are no longer in
+    /// the payload are kept
+    async fn update_point_payload(
+        &self,
+        search_request: SearchRequest,
+        id: String,
+        payload: std::collections::HashMap<String, serde_json::Value>,
+    ) -> Result<VectorDatabaseStatus, VectorDatabaseError> {
+        let region = search_request.region.unwrap_or(Region::US_EAST_1);
+        let namespace = search_request
+            .clone()
+            .namespace
+            .map_or(search_request.clone().collection, |n| n);
+        let index_name = search_request
+            .byo_vector_db
+            .filter(|k| *k)
+            .map_or(Region::to_str(region), |_| {
+                search_request.collection.as_str()
+            })
+            .to_string();
+        let metadata = Metadata::from(Point::new(None, vec![], Some(payload)));
+        match get_index_model(&self, index_name).await {
+            Ok(index_model) => match self.index(index_model.host.as_str()).await {
+                // An empty list of values leaves the vector as it is
+                Ok(mut index) => match index
+                    .update(
+                        vector_id(&id).as_str(),
+                        vec![],
+                        None,
+                        Some(metadata),
+                        &namespace.into(),
+                    )
+                    .await
+                {
+                    Ok(_) => Ok(VectorDatabaseStatus::Ok),
+                    Err(e) => Err(VectorDatabaseError::PineconeError(Arc::new(e))),
+                },
+                Err(e) => Err(VectorDatabaseError::PineconeError(Arc::new(e))),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_point(
+        &self,
+        search_request: SearchRequest,
+        id: String,
+        with_vector: bool,
+    ) -> Result<Option<SearchResult>, VectorDatabaseError> {
+        let region = search_request.region.unwrap_or(Region::US_EAST_1);
+        let namespace = search_request
+            .clone()
+            .namespace
+            .map_or(search_request.clone().collection, |n| n);
+        let index_name = search_request
+            .byo_vector_db
+            .filter(|k| *k)
+            .map_or(Region::to_str(region), |_| {
+                search_request.collection.as_str()
+            })
+            .to_string();
+        let index_model = get_index_model(&self, index_name).await?;
+        let mut index = self
+            .index(index_model.host.as_str())
+            .await
+            .map_err(|e| VectorDatabaseError::PineconeError(Arc::new(e)))?;
+        let response = index
+            .fetch(&[vector_id(&id).as_str()], &namespace.into())
+            .await
+            .map_err(|e| VectorDatabaseError::PineconeError(Arc::new(e)))?;
+        Ok(response.vectors.into_values().next().map(|vector| SearchResult {
+            id: id.clone(),
+            score: None,
+            payload: vector.metadata.and_then(|m| Point::from(m).payload),
+            vector: Some(vector.values).filter(|_| with_vector),
+        }))
+    }
+
+    fn supports_collection_aliases(&self) -> bool {
+        false
+    }
+
+    async fn swap_collection_alias(
+        &self,
+        _search_request: SearchRequest,
+        _collection: String,
+    ) -> Result<String, VectorDatabaseError> {
+        Err(VectorDatabaseError::Other(
+            "Pinecone namespaces do not support aliases".to_string(),
+        ))
+    }
+
     async fn get_collection_info(
         &self,
         search_request: SearchRequest,
@@ -363,6 +488,60 @@ impl VectorDatabase for PineconeClient {
         todo!()
     }
 
+    /// Lists a page of vector IDs and fetches them. Listing is only available on serverless indexes
+    async fn scroll_page(
+        &self,
+        search_request: SearchRequest,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<(Vec<SearchResult>, Option<String>), VectorDatabaseError> {
+        let region = search_request.region.unwrap_or(Region::US_EAST_1);
+        let namespace: Namespace = search_request
+            .clone()
+            .namespace
+            .map_or(search_request.clone().collection, |n| n)
+            .into();
+        let index_name = search_request
+            .byo_vector_db
+            .filter(|k| *k)
+            .map_or(Region::to_str(region), |_| {
+                search_request.collection.as_str()
+            })
+            .to_string();
+        let index_model = get_index_model(&self, index_name).await?;
+        let mut index = self
+            .index(index_model.host.as_str())
+            .await
+            .map_err(|e| VectorDatabaseError::PineconeError(Arc::new(e)))?;
+        let listed = index
+            .list(&namespace, None, Some(limit), offset.as_deref())
+            .await
+            .map_err(|e| VectorDatabaseError::PineconeError(Arc::new(e)))?;
+        let next_offset = listed
+            .pagination
+            .map(|pagination| pagination.next)
+            .filter(|next| !next.is_empty());
+        if listed.vectors.is_empty() {
+            return Ok((vec![], next_offset));
+        }
+        let ids: Vec<&str> = listed.vectors.iter().map(|item| item.id.as_str()).collect();
+        let response = index
+            .fetch(&ids, &namespace)
+            .await
+            .map_err(|e| VectorDatabaseError::PineconeError(Arc::new(e)))?;
+        let points = response
+            .vectors
+            .into_iter()
+            .map(|(id, vector)| SearchResult {
+                id: point_id(&id),
+                score: None,
+                payload: vector.metadata.and_then(|m| Point::from(m).payload),
+                vector: Some(vector.values),
+            })
+            .collect();
+        Ok((points, next_offset))
+    }
+
     async fn similarity_search(
         &self,
         search_request: SearchRequest,
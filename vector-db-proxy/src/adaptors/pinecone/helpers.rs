@@ -3,6 +3,7 @@ use crate::vector_databases::models::VectorDatabaseStatus;
 use pinecone_sdk::models::{IndexModel, Namespace, Vector};
 use pinecone_sdk::pinecone::data::Index;
 use pinecone_sdk::pinecone::PineconeClient;
+use serde_json::Value;
 use std::sync::Arc;
 
 pub async fn get_namespaces_for_index(
@@ -61,3 +62,14 @@ pub async fn upsert(
         Err(e) => Err(VectorDatabaseError::PineconeError(Arc::new(e))),
     }
 }
+
+/// Vectors are written with the JSON representation of `Point.index` as their ID, so IDs need the
+/// same treatment when looking points up or deleting them
+pub fn vector_id(id: &str) -> String {
+    Value::String(id.to_string()).to_string()
+}
+
+/// Reverses `vector_id`. Vectors written with a bare ID are returned as they are
+pub fn point_id(vector_id: &str) -> String {
+    serde_json::from_str::<String>(vector_id).unwrap_or(vector_id.to_string())
+}
@@ -1,4 +1,6 @@
-use crate::adaptors::qdrant::helpers::{construct_point_struct, get_next_page, get_scroll_results};
+use crate::adaptors::qdrant::helpers::{
+    construct_point_struct, get_next_page, get_scroll_results, point_id_to_string, resolve_alias,
+};
 use crate::utils::conversions::convert_hashmap_to_qdrant_filters;
 use crate::vector_databases::error::VectorDatabaseError;
 use crate::vector_databases::models::{
@@ -13,18 +15,28 @@ use backoff::backoff::Backoff;
 use backoff::{exponential, ExponentialBackoff, SystemClock};
 use futures_util::stream::{self, StreamExt};
 use qdrant_client::prelude::point_id::PointIdOptions;
-use qdrant_client::prelude::{CreateCollection, PointStruct, QdrantClient, SearchPoints};
+use qdrant_client::prelude::{
+    CreateCollection, Payload, PointStruct, QdrantClient, SearchPoints,
+};
 use qdrant_client::qdrant::condition::ConditionOneOf::HasId;
 use qdrant_client::qdrant::points_selector::PointsSelectorOneOf;
+use qdrant_client::qdrant::vectors::VectorsOptions;
 use qdrant_client::qdrant::vectors_config::Config;
 use qdrant_client::qdrant::with_vectors_selector::SelectorOptions;
+use qdrant_client::qdrant::alias_operations::Action;
 use qdrant_client::qdrant::{
-    Condition, Filter, HasIdCondition, PointId, PointsSelector, ScrollPoints, VectorParams,
-    VectorParamsMap, VectorsConfig, WithVectorsSelector,
+    AliasOperations, ChangeAliases, Condition, CreateAlias, DeleteAlias, Filter, HasIdCondition,
+    PointId, PointsIdsList, PointsSelector, ScrollPoints, VectorParams, VectorParamsMap,
+    VectorsConfig, WithVectorsSelector,
 };
-use serde_json::to_value;
+use serde_json::{json, to_value, Value};
+use std::collections::HashMap;
 use std::time::Duration;
 
+/// Appended to a live collection's name to alias it when that name is taken by a regular
+/// collection, which happens on the first overwrite sync of a datasource
+const LIVE_ALIAS_SUFFIX: &str = "_live";
+
 #[async_trait]
 impl VectorDatabase for QdrantClient {
     async fn get_list_of_collections(&self) -> Result<Vec<String>, VectorDatabaseError> {
@@ -44,7 +56,14 @@ impl VectorDatabase for QdrantClient {
         log::debug!("Qdrant URI: {:?}", &self.cfg.uri);
         log::debug!("Qdrant API KEY: {:?}", &self.cfg.api_key);
         let collection_id = search_request.collection;
-        match self.collection_exists(collection_id.clone()).await {
+        // Collections swapped in by overwrite syncs are only reachable through an alias
+        let collection_exists = match self.collection_exists(collection_id.clone()).await {
+            Ok(false) => resolve_alias(self, &collection_id)
+                .await
+                .map(|collection| collection.is_some()),
+            result => result,
+        };
+        match collection_exists {
             Ok(collection_exists) => match collection_exists {
                 true => Ok(CollectionsResult {
                     status: VectorDatabaseStatus::Ok,
@@ -123,6 +142,14 @@ impl VectorDatabase for QdrantClient {
         match self.check_collection_exists(search_request).await {
             Ok(collection_result) => match collection_result.status {
                 VectorDatabaseStatus::Ok => {
+                    // If the name is an alias, remove the alias and the collection behind it
+                    let collection_id = match resolve_alias(self, &collection_id).await {
+                        Ok(Some(collection)) => {
+                            let _ = self.delete_alias(collection_id).await;
+                            collection
+                        }
+                        _ => collection_id,
+                    };
                     if let Ok(response) = &self.delete_collection(collection_id).await {
                         match response.result {
                             true => Ok(VectorDatabaseStatus::Ok),
@@ -318,6 +345,145 @@ impl VectorDatabase for QdrantClient {
         )))
     }
 
+    async fn delete_points_by_ids(
+        &self,
+        search_request: SearchRequest,
+        ids: Vec<String>,
+    ) -> Result<VectorDatabaseStatus, VectorDatabaseError> {
+        let collection_id = search_request.collection;
+        let points_selector = PointsSelector {
+            points_selector_one_of: Some(PointsSelectorOneOf::Points(PointsIdsList {
+                ids: ids.into_iter().map(PointId::from).collect(),
+            })),
+        };
+        match self
+            .delete_points_blocking(collection_id, None, &points_selector, None)
+            .await
+        {
+            Ok(_) => Ok(VectorDatabaseStatus::Ok),
+            Err(e) => Err(VectorDatabaseError::AnyhowError(e)),
+        }
+    }
+
+    async fn update_point_payload(
+        &self,
+        search_request: SearchRequest,
+        id: String,
+        payload: HashMap<String, Value>,
+    ) -> Result<VectorDatabaseStatus, VectorDatabaseError> {
+        let collection_id = search_request.collection;
+        let points_selector = PointsSelector {
+            points_selector_one_of: Some(PointsSelectorOneOf::Points(PointsIdsList {
+                ids: vec![PointId::from(id)],
+            })),
+        };
+        let payload = Payload::try_from(json!(payload))
+            .map_err(|e| VectorDatabaseError::Other(e.to_string()))?;
+        match self
+            .overwrite_payload_blocking(collection_id, None, &points_selector, payload, None, None)
+            .await
+        {
+            Ok(_) => Ok(VectorDatabaseStatus::Ok),
+            Err(e) => Err(VectorDatabaseError::AnyhowError(e)),
+        }
+    }
+
+    async fn get_point(
+        &self,
+        search_request: SearchRequest,
+        id: String,
+        with_vector: bool,
+    ) -> Result<Option<SearchResult>, VectorDatabaseError> {
+        let collection_id = search_request.collection;
+        let response = self
+            .get_points(
+                collection_id,
+                None,
+                &[PointId::from(id.clone())],
+                Some(with_vector),
+                Some(true),
+                None,
+            )
+            .await?;
+        Ok(response.result.into_iter().next().map(|point| SearchResult {
+            id,
+            score: None,
+            payload: Some(
+                point
+                    .payload
+                    .iter()
+                    .map(|(k, v)| (k.clone(), to_value(v).unwrap()))
+                    .collect(),
+            ),
+            vector: point
+                .vectors
+                .and_then(|vectors| vectors.vectors_options)
+                .and_then(|vectors| match vectors {
+                    VectorsOptions::Vector(vector) => Some(vector.data),
+                    VectorsOptions::Vectors(_) => None,
+                }),
+        }))
+    }
+
+    fn supports_collection_aliases(&self) -> bool {
+        true
+    }
+
+    async fn swap_collection_alias(
+        &self,
+        search_request: SearchRequest,
+        collection: String,
+    ) -> Result<String, VectorDatabaseError> {
+        let mut alias = search_request.collection;
+        let mut previous_collection = resolve_alias(self, &alias).await?;
+        if previous_collection.is_none() && self.collection_exists(alias.clone()).await? {
+            // Before the first swap the live name is a regular collection, and Qdrant can not give
+            // an alias the name of a collection. The new collection is served under an alias of
+            // its own instead, and the caller removes the original once readers have moved to it.
+            alias = format!("{}{}", alias, LIVE_ALIAS_SUFFIX);
+            previous_collection = resolve_alias(self, &alias).await?;
+        }
+        let mut actions = vec![];
+        if previous_collection.is_some() {
+            actions.push(AliasOperations {
+                action: Some(Action::DeleteAlias(DeleteAlias {
+                    alias_name: alias.clone(),
+                })),
+            });
+        }
+        actions.push(AliasOperations {
+            action: Some(Action::CreateAlias(CreateAlias {
+                collection_name: collection.clone(),
+                alias_name: alias.clone(),
+            })),
+        });
+        // Alias changes sent in one request are applied together, so readers move from the old
+        // collection to the new one without a gap
+        self.update_aliases(ChangeAliases {
+            actions,
+            timeout: None,
+        })
+        .await?;
+        let promoted_collection = resolve_alias(self, &alias).await?;
+        if promoted_collection.as_deref() != Some(collection.as_str()) {
+            return Err(VectorDatabaseError::Other(format!(
+                "Alias: {} points to {:?} rather than collection: {} after the swap",
+                alias, promoted_collection, collection
+            )));
+        }
+        if let Some(previous_collection) = previous_collection.filter(|c| *c != collection) {
+            if let Err(e) = self.delete_collection(previous_collection.clone()).await {
+                log::warn!(
+                    "Could not remove previous collection: {} behind alias: {}. Error: {}",
+                    previous_collection,
+                    alias,
+                    e
+                );
+            }
+        }
+        Ok(alias)
+    }
+
     async fn get_collection_info(
         &self,
         search_request: SearchRequest,
@@ -436,6 +602,56 @@ impl VectorDatabase for QdrantClient {
         }
         Ok(response)
     }
+    async fn scroll_page(
+        &self,
+        search_request: SearchRequest,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<(Vec<SearchResult>, Option<String>), VectorDatabaseError> {
+        let scroll_points = ScrollPoints {
+            collection_name: search_request.collection,
+            offset: offset.map(PointId::from),
+            limit: Some(limit),
+            with_payload: Some(true.into()),
+            with_vectors: Some(WithVectorsSelector {
+                selector_options: Some(SelectorOptions::Enable(true)),
+            }),
+            ..Default::default()
+        };
+        let response = self.scroll(&scroll_points).await?;
+        let points = response
+            .result
+            .into_iter()
+            .map(|point| SearchResult {
+                id: point
+                    .id
+                    .and_then(|id| id.point_id_options)
+                    .map(point_id_to_string)
+                    .unwrap_or_default(),
+                score: None,
+                payload: Some(
+                    point
+                        .payload
+                        .iter()
+                        .map(|(k, v)| (k.clone(), to_value(v).unwrap()))
+                        .collect(),
+                ),
+                vector: point
+                    .vectors
+                    .and_then(|vectors| vectors.vectors_options)
+                    .and_then(|vectors| match vectors {
+                        VectorsOptions::Vector(vector) => Some(vector.data),
+                        VectorsOptions::Vectors(_) => None,
+                    }),
+            })
+            .collect();
+        let next_offset = response
+            .next_page_offset
+            .and_then(|id| id.point_id_options)
+            .map(point_id_to_string);
+        Ok((points, next_offset))
+    }
+
     async fn similarity_search(
         &self,
         search_request: SearchRequest,
@@ -28,16 +28,20 @@ pub async fn get_next_page(
     Ok((result, offset))
 }
 
+pub fn point_id_to_string(point_id: PointIdOptions) -> String {
+    match point_id {
+        PointIdOptions::Num(num) => num.to_string(),
+        PointIdOptions::Uuid(uuid) => uuid,
+    }
+}
+
 pub fn get_scroll_results(result: ScrollResponse) -> Result<Vec<ScrollResults>> {
     let mut response: Vec<ScrollResults> = vec![];
     for result in result.result {
         let mut id = String::new();
         if let Some(point_id) = result.id {
             if let Some(id_option) = point_id.point_id_options {
-                id = match id_option {
-                    PointIdOptions::Num(num) => num.to_string(),
-                    PointIdOptions::Uuid(uuid) => uuid,
-                };
+                id = point_id_to_string(id_option);
             }
         }
         let vectors = result.vectors.unwrap().vectors_options.unwrap();
@@ -113,3 +117,13 @@ pub async fn construct_point_struct(
     }
     None
 }
+
+/// Returns the collection an alias points to, or `None` if there is no alias with that name
+pub async fn resolve_alias(client: &QdrantClient, alias: &str) -> Result<Option<String>> {
+    let aliases = client.list_aliases().await?;
+    Ok(aliases
+        .aliases
+        .into_iter()
+        .find(|a| a.alias_name == alias)
+        .map(|a| a.collection_name))
+}
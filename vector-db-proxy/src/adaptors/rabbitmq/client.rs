@@ -1,5 +1,6 @@
 use std::sync::Arc;
 use crate::adaptors::rabbitmq::models::RabbitConnect;
+use crate::init::env_variables::GLOBAL_DATA;
 use amqp_serde::types::{FieldTable, FieldValue, ShortStr};
 use amqprs::channel::{Channel, ExchangeDeclareArguments};
 use amqprs::{
@@ -90,10 +91,12 @@ pub async fn bind_queue_to_exchange(
         .exchange_declare(ExchangeDeclareArguments::new(exchange, "direct"))
         .await
         .unwrap();
-    // Setting up basic quality-of-service parameters for the channel to enable streaming queue
+    // Setting up basic quality-of-service parameters for the channel to enable streaming queue.
+    // The prefetch count bounds how many unprocessed messages can be held in memory at once
+    let prefetch_count = GLOBAL_DATA.read().await.rabbitmq_prefetch_count;
     match channel
         .basic_qos(BasicQosArguments {
-            prefetch_count: 10000,
+            prefetch_count,
             prefetch_size: 0,
             global: false,
         })
@@ -1,13 +1,14 @@
-use crate::adaptors::mongo::models::DataSources;
 use crate::adaptors::rabbitmq::client::bind_queue_to_exchange;
 use crate::init::env_variables::GLOBAL_DATA;
-use crate::messages::models::{MessageQueueConnection, QueueConnectionTypes};
+use crate::init::shutdown::Shutdown;
+use crate::messages::envelope::IngestionEnvelope;
+use crate::messages::models::{AckHandle, MessageQueueConnection, QueueConnectionTypes};
+use crate::messages::scheduler::FairScheduler;
 use crate::messages::tasks::process_message;
-use amqp_serde::types::ShortStr;
-use amqprs::channel::{BasicAckArguments, BasicConsumeArguments, Channel};
-use crossbeam::channel::Sender;
+use amqprs::channel::{BasicCancelArguments, BasicConsumeArguments, Channel};
 use log::{error, warn};
 use mongodb::Database;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -53,62 +54,71 @@ pub async fn rabbit_consume(
     streaming_queue: &Channel,
     //vector_database_client: Arc<RwLock<dyn VectorDatabase>>,
     mongo_client: Arc<RwLock<Database>>,
-    sender: Sender<(DataSources, Option<String>, String)>,
+    scheduler: Arc<FairScheduler>,
+    shutdown: Shutdown,
 ) {
     let global_data = GLOBAL_DATA.read().await;
     let queue_name = global_data.rabbitmq_stream.as_str();
     let args = BasicConsumeArguments::new(queue_name, "");
-    loop {
+    while !shutdown.is_triggered() {
         match streaming_queue.basic_consume_rx(args.clone()).await {
-            Ok((_, mut messages_rx)) => {
-                while let Some(message) = messages_rx.recv().await {
-                    let args =
-                        BasicAckArguments::new(message.deliver.unwrap().delivery_tag(), false);
-                    let _ = streaming_queue.basic_ack(args).await;
-                    let headers = message.basic_properties.unwrap().headers().unwrap().clone();
-                    match headers.get(&ShortStr::try_from("stream").unwrap()) {
-                        Some(stream) => {
-                            let stream_string: String = stream.to_string();
-                            // Determine stream type and extract datasource_id and stream_config_key
-                            let (datasource_id, stream_config_key, stream_type) = match headers
-                                .get(&ShortStr::try_from("type").unwrap())
-                            {
-                                Some(stream_type_field_value) => {
-                                    let stream_split: Vec<&str> =
-                                        stream_string.split('_').collect();
-                                    let datasource_id = stream_split[0];
-                                    let stream_type = Some(stream_type_field_value.to_string());
-                                    (datasource_id, None, stream_type)
-                                }
-                                None => {
-                                    let (datasource_id, stream_config_key) = stream_string.split_once('_')
-                                        .expect("Expected string to contain an underscore for splitting");
-                                    (datasource_id, Some(stream_config_key.to_string()), None)
-                                }
-                            };
-                            if let Some(msg) = message.content {
-                                if let Ok(message_string) = String::from_utf8(msg.clone().to_vec())
-                                {
-                                    let sender_clone = sender.clone();
-                                    let mongo_client = Arc::clone(&mongo_client);
-                                    process_message(
-                                        message_string,
-                                        stream_type,
-                                        datasource_id,
-                                        stream_config_key,
-                                        //vector_database_client,
-                                        mongo_client,
-                                        sender_clone,
-                                    )
-                                    .await;
-                                }
-                            }
+            Ok((consumer_tag, mut messages_rx)) => {
+                loop {
+                    let message = tokio::select! {
+                        message = messages_rx.recv() => message,
+                        _ = shutdown.wait() => None,
+                    };
+                    let Some(message) = message else { break };
+                    let Some(deliver) = message.deliver else {
+                        continue;
+                    };
+                    // Messages are acked once the worker has finished with them so anything still
+                    // in flight at shutdown is redelivered
+                    let ack = AckHandle::RabbitMQ {
+                        channel: streaming_queue.clone(),
+                        delivery_tag: deliver.delivery_tag(),
+                    };
+                    let headers: HashMap<String, String> = message
+                        .basic_properties
+                        .and_then(|properties| properties.headers().cloned())
+                        .map(|headers| {
+                            headers
+                                .as_ref()
+                                .iter()
+                                .map(|(k, v)| (k.to_string(), v.to_string()))
+                                .collect()
+                        })
+                        .unwrap_or_default();
+                    let body = message.content.unwrap_or_default();
+                    match IngestionEnvelope::parse(&headers, &body) {
+                        Ok(envelope) => {
+                            let scheduler = Arc::clone(&scheduler);
+                            let mongo_client = Arc::clone(&mongo_client);
+                            process_message(
+                                envelope,
+                                ack,
+                                //vector_database_client,
+                                mongo_client,
+                                scheduler,
+                            )
+                            .await;
                         }
-                        None => {
-                            warn!("There was no stream ID present in message headers...can not proceed!")
+                        Err(e) => {
+                            warn!("Rejecting message from RabbitMQ. Reason: {}", e);
+                            ack.ack().await;
                         }
                     }
                 }
+                if shutdown.is_triggered() {
+                    // Stop the broker from delivering any more messages to this consumer. Anything
+                    // delivered but not yet acked is requeued when the channel closes
+                    if let Err(e) = streaming_queue
+                        .basic_cancel(BasicCancelArguments::new(&consumer_tag))
+                        .await
+                    {
+                        error!("Could not cancel RabbitMQ consumer. Error: {}", e);
+                    }
+                }
             }
             Err(e) => {
                 error!(
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use mongodb::Database;
+use serde_json::Value;
+
+use crate::adaptors::mongo::models::DataSources;
+use crate::data::record_counts::increment_record_count;
+use crate::data::sync_modes::{live_collection, search_request_for};
+use crate::embeddings::utils::row_point_ids;
+use crate::init::env_variables::GLOBAL_DATA;
+use crate::vector_databases::helpers::check_byo_vector_database;
+use crate::vector_databases::models::VectorDatabaseStatus;
+use crate::vector_databases::vector_database::default_vector_db_client;
+
+/// Column Airbyte sets on rows that were deleted at the source when CDC is enabled
+pub const CDC_DELETED_AT_FIELD: &str = "_ab_cdc_deleted_at";
+
+/// CDC sources send deletions as a regular row with `_ab_cdc_deleted_at` set. Other rows carry the
+/// column with a null value.
+pub fn is_deleted_record(record: &HashMap<String, Value>) -> bool {
+    record
+        .get(CDC_DELETED_AT_FIELD)
+        .is_some_and(|deleted_at| !deleted_at.is_null())
+}
+
+/// Removes the points for a row that was deleted at the source: the row's own point and the chunks
+/// and parts it was split into. `point_id` is the primary-key hash the row was upserted with, so
+/// rows from streams without a primary key can not be removed. Successful deletions are counted in
+/// `recordCount.deleted`.
+pub async fn apply_cdc_delete(
+    mongo: &Database,
+    datasource: &DataSources,
+    point_id: Option<String>,
+) {
+    let datasource_id = datasource.id.to_string();
+    let Some(point_id) = point_id else {
+        log::warn!(
+            "Received a deleted record without a primary key for datasource: {}. Skipping",
+            datasource_id
+        );
+        increment_record_count(&datasource_id, "recordCount.failure");
+        return;
+    };
+    let vector_database_client = check_byo_vector_database(datasource.clone(), mongo)
+        .await
+        .unwrap_or(default_vector_db_client().await);
+    let search_request = search_request_for(datasource, live_collection(datasource));
+    let hashing_salt = GLOBAL_DATA.read().await.hashing_salt.clone();
+    let vector_database_client = vector_database_client.read().await;
+    let mut point_ids = row_point_ids(
+        &*vector_database_client,
+        &search_request,
+        &hashing_salt,
+        &point_id,
+        0,
+    )
+    .await;
+    if !point_ids.contains(&point_id) {
+        point_ids.push(point_id);
+    }
+    let result = vector_database_client
+        .delete_points_by_ids(search_request, point_ids)
+        .await;
+    match result {
+        Ok(VectorDatabaseStatus::Ok) => {
+            increment_record_count(&datasource_id, "recordCount.deleted")
+        }
+        Ok(status) => {
+            log::warn!(
+                "Could not delete record for datasource: {}. Status: {:?}",
+                datasource_id,
+                status
+            );
+            increment_record_count(&datasource_id, "recordCount.failure");
+        }
+        Err(e) => {
+            log::error!(
+                "Could not delete record for datasource: {}. Error: {}",
+                datasource_id,
+                e
+            );
+            increment_record_count(&datasource_id, "recordCount.failure");
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+use mongodb::Database;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+
+use crate::adaptors::mongo::queries::save_sync_state;
+use crate::messages::airbyte::AirbyteState;
+use crate::messages::models::AckHandle;
+
+struct PendingState {
+    /// Number of records received for the datasource before this state
+    sequence: u64,
+    state: AirbyteState,
+    /// The state message is only acked once it has been persisted
+    ack: AckHandle,
+}
+
+#[derive(Default)]
+struct DatasourceCheckpoints {
+    next_sequence: u64,
+    /// Records that have been received but not yet written to the vector database, with the key
+    /// of their content
+    unsettled: BTreeMap<u64, u64>,
+    /// Sequence numbers of records returned to the queue, by the key of their content, for the
+    /// redelivered record to take back
+    retrying: HashMap<u64, Vec<u64>>,
+    pending_states: VecDeque<PendingState>,
+}
+
+impl DatasourceCheckpoints {
+    /// Takes every state that no longer has an unsettled record in front of it
+    fn take_ready_states(&mut self) -> Vec<PendingState> {
+        let oldest_unsettled = self.unsettled.keys().next().copied();
+        let mut ready = vec![];
+        while let Some(state) = self.pending_states.front() {
+            if oldest_unsettled.is_some_and(|oldest| oldest < state.sequence) {
+                break;
+            }
+            ready.extend(self.pending_states.pop_front());
+        }
+        ready
+    }
+
+    fn is_idle(&self) -> bool {
+        self.unsettled.is_empty() && self.pending_states.is_empty()
+    }
+}
+
+/// Records and states seen for each datasource, in the order they were received. Records are
+/// processed by parallel workers, so a state is held back until every record received before it
+/// has been written.
+static CHECKPOINTS: Lazy<Mutex<HashMap<String, DatasourceCheckpoints>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Identifies a record by its stream and content, which stay the same when it is redelivered
+fn record_key(stream_key: Option<&str>, record: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    stream_key.hash(&mut hasher);
+    record.to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Registers a record and returns the sequence number to settle it with once it has been written.
+/// A record that was returned to the queue takes back the sequence number it had before, so the
+/// states received after it keep waiting on it.
+pub fn record_received(datasource_id: &str, stream_key: Option<&str>, record: &Value) -> u64 {
+    let key = record_key(stream_key, record);
+    let mut checkpoints = CHECKPOINTS.lock().unwrap();
+    let datasource = checkpoints.entry(datasource_id.to_string()).or_default();
+    if let Some(sequences) = datasource.retrying.get_mut(&key) {
+        let sequence = sequences.pop();
+        if sequences.is_empty() {
+            datasource.retrying.remove(&key);
+        }
+        if let Some(sequence) = sequence {
+            return sequence;
+        }
+    }
+    let sequence = datasource.next_sequence;
+    datasource.next_sequence += 1;
+    datasource.unsettled.insert(sequence, key);
+    sequence
+}
+
+/// Keeps a record that is being returned to the queue unsettled, so no state received after it is
+/// persisted before its redelivery has been written
+pub fn record_retrying(datasource_id: &str, sequence: u64) {
+    let mut checkpoints = CHECKPOINTS.lock().unwrap();
+    let Some(datasource) = checkpoints.get_mut(datasource_id) else {
+        return;
+    };
+    if let Some(key) = datasource.unsettled.get(&sequence).copied() {
+        datasource.retrying.entry(key).or_default().push(sequence);
+    }
+}
+
+/// Marks a record as written, successfully or not, and persists any states it was holding back.
+/// Rows that failed to embed are counted in `recordCount.failure` rather than holding up the sync.
+pub async fn record_settled(mongo: &Database, datasource_id: &str, sequence: u64) {
+    let ready = {
+        let mut checkpoints = CHECKPOINTS.lock().unwrap();
+        let Some(datasource) = checkpoints.get_mut(datasource_id) else {
+            return;
+        };
+        datasource.unsettled.remove(&sequence);
+        let ready = datasource.take_ready_states();
+        if datasource.is_idle() {
+            checkpoints.remove(datasource_id);
+        }
+        ready
+    };
+    persist_states(mongo, datasource_id, ready).await;
+}
+
+/// Registers a state as a checkpoint covering every record received before it. It is persisted
+/// straight away if those records have all been written already.
+pub async fn state_received(
+    mongo: &Database,
+    datasource_id: &str,
+    state: AirbyteState,
+    ack: AckHandle,
+) {
+    let ready = {
+        let mut checkpoints = CHECKPOINTS.lock().unwrap();
+        let datasource = checkpoints.entry(datasource_id.to_string()).or_default();
+        datasource.pending_states.push_back(PendingState {
+            sequence: datasource.next_sequence,
+            state,
+            ack,
+        });
+        let ready = datasource.take_ready_states();
+        if datasource.is_idle() {
+            checkpoints.remove(datasource_id);
+        }
+        ready
+    };
+    persist_states(mongo, datasource_id, ready).await;
+}
+
+async fn persist_states(mongo: &Database, datasource_id: &str, states: Vec<PendingState>) {
+    for PendingState { state, ack, .. } in states {
+        match save_sync_state(mongo, datasource_id, &state.key(), &state.to_value()).await {
+            Ok(_) => ack.ack().await,
+            Err(e) => {
+                log::error!(
+                    "Could not persist sync state for datasource: {}. Error: {}",
+                    datasource_id,
+                    e
+                );
+                ack.nack().await;
+            }
+        }
+    }
+}
+
+/// Hands back the ack handles of states still waiting on records so they can be returned to the
+/// queue at shutdown
+pub fn take_pending_states() -> Vec<AckHandle> {
+    CHECKPOINTS
+        .lock()
+        .unwrap()
+        .drain()
+        .flat_map(|(_, datasource)| datasource.pending_states)
+        .map(|state| state.ack)
+        .collect()
+}
@@ -0,0 +1,157 @@
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::adaptors::mongo::models::{
+    DataSources, Model, UnstructuredChunkingConfig, UnstructuredChunkingStrategy,
+};
+use crate::data::chunking::chunker::{TABLE, TABLE_CHUNK};
+use crate::data::chunking::{chunk_document, uses_native_chunker};
+use crate::data::models::FileType;
+use crate::data::structured_files::{read_rows, reads_row_by_row, row_payload, row_text};
+use crate::embeddings::models::EmbeddingModels;
+use crate::embeddings::tokenizer::count_tokens_each;
+use crate::utils::file_operations::detect_file_type;
+
+/// A chunk as it would be embedded
+#[derive(Serialize, Debug)]
+pub struct PreviewChunk {
+    pub index: usize,
+    #[serde(rename = "type")]
+    pub element_type: String,
+    pub text: String,
+    pub characters: usize,
+    pub tokens: u64,
+    /// Longer than the model accepts, so it would be truncated or split as the datasource's
+    /// overflow policy says
+    pub exceeds_model_limit: bool,
+    pub metadata: Value,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ChunkPreview {
+    pub file_type: FileType,
+    pub model: String,
+    pub chunk_count: usize,
+    pub total_characters: usize,
+    pub min_characters: usize,
+    pub max_characters: usize,
+    pub mean_characters: f64,
+    pub total_tokens: u64,
+    /// Tokens `by_similarity` embeds to decide where to cut. Every sentence outside a table is
+    /// embedded once, so this is taken to be the tokens of the chunks that are not tables.
+    pub similarity_tokens: u64,
+    pub max_input_tokens: Option<usize>,
+    /// Of embedding the chunks and, for `by_similarity`, the sentences compared to cut them. In
+    /// US dollars at the model's list price, zero for models run locally.
+    pub estimated_cost: f64,
+    pub chunks: Vec<PreviewChunk>,
+}
+
+/// Element type given to the rows of structured files, which are embedded one per point
+const ROW_ELEMENT_TYPE: &str = "Row";
+
+/// Chunks a file the way uploading it to the datasource would, with `config` in place of the
+/// datasource's chunking config if given. Nothing is embedded or written, apart from the
+/// sentences `by_similarity` embeds to decide where to cut.
+pub async fn preview_chunks(
+    datasource: &DataSources,
+    model: &Model,
+    file: Vec<u8>,
+    file_name: Option<String>,
+    config: Option<UnstructuredChunkingConfig>,
+) -> Result<ChunkPreview> {
+    let file_type = detect_file_type(&file, file_name.as_deref().unwrap_or_default());
+    let config = config.or(datasource.chunking_config.clone());
+    let embeds_sentences = uses_native_chunker(Some(file_type), config.as_ref())
+        && config.as_ref().is_some_and(|config| {
+            matches!(config.strategy, UnstructuredChunkingStrategy::BySimilarity)
+        });
+    let chunks: Vec<(String, String, Value)> = if reads_row_by_row(file_type, config.as_ref()) {
+        read_rows(&file, file_type)?
+            .iter()
+            .map(|row| {
+                (
+                    ROW_ELEMENT_TYPE.to_string(),
+                    row_text(row, datasource.structured_config.as_ref()),
+                    serde_json::to_value(row_payload(row)).unwrap_or_default(),
+                )
+            })
+            .collect()
+    } else {
+        chunk_document(
+            file,
+            file_name,
+            Some(file_type),
+            config,
+            model,
+            Some(datasource.id.to_string().as_str()),
+        )
+        .await?
+        .into_iter()
+        .map(|element| {
+            let metadata = serde_json::to_value(&element.metadata).unwrap_or_default();
+            (element.field_type, element.text, metadata)
+        })
+        .collect()
+    };
+
+    let embedding_model = EmbeddingModels::from(model.model.clone());
+    let max_input_tokens = embedding_model.max_input_tokens();
+    let texts: Vec<&String> = chunks.iter().map(|(_, text, _)| text).collect();
+    let token_counts = count_tokens_each(&model.model, &texts).await;
+    let preview_chunks: Vec<PreviewChunk> = chunks
+        .into_iter()
+        .zip(token_counts)
+        .enumerate()
+        .map(
+            |(index, ((element_type, text, metadata), tokens))| PreviewChunk {
+                index,
+                element_type,
+                characters: text.chars().count(),
+                tokens,
+                exceeds_model_limit: max_input_tokens.is_some_and(|max| tokens as usize > max),
+                text,
+                metadata,
+            },
+        )
+        .collect();
+    let total_characters = preview_chunks.iter().map(|chunk| chunk.characters).sum();
+    let total_tokens = preview_chunks.iter().map(|chunk| chunk.tokens).sum::<u64>();
+    let similarity_tokens = if embeds_sentences {
+        preview_chunks
+            .iter()
+            .filter(|chunk| chunk.element_type != TABLE && chunk.element_type != TABLE_CHUNK)
+            .map(|chunk| chunk.tokens)
+            .sum()
+    } else {
+        0
+    };
+    Ok(ChunkPreview {
+        file_type,
+        model: model.model.clone(),
+        chunk_count: preview_chunks.len(),
+        total_characters,
+        min_characters: preview_chunks
+            .iter()
+            .map(|chunk| chunk.characters)
+            .min()
+            .unwrap_or_default(),
+        max_characters: preview_chunks
+            .iter()
+            .map(|chunk| chunk.characters)
+            .max()
+            .unwrap_or_default(),
+        mean_characters: match preview_chunks.len() {
+            0 => 0.0,
+            count => total_characters as f64 / count as f64,
+        },
+        total_tokens,
+        similarity_tokens,
+        max_input_tokens,
+        estimated_cost: (total_tokens + similarity_tokens) as f64
+            * embedding_model.cost_per_million_tokens()
+            / 1_000_000.0,
+        chunks: preview_chunks,
+    })
+}
@@ -0,0 +1,392 @@
+use crate::adaptors::mongo::models::{UnstructuredChunkingConfig, UnstructuredChunkingStrategy};
+use crate::data::chunking::partition::{Element, ElementType};
+
+/// Element types of the chunks, matching those Unstructured IO returns
+pub const COMPOSITE_ELEMENT: &str = "CompositeElement";
+pub const TABLE: &str = "Table";
+pub const TABLE_CHUNK: &str = "TableChunk";
+
+const ELEMENT_SEPARATOR: &str = "\n\n";
+const SENTENCE_SEPARATOR: &str = " ";
+
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub element_type: &'static str,
+    pub text: String,
+    /// Page the chunk starts on
+    pub page_number: Option<i64>,
+    /// Index of the element the chunk starts with
+    pub first_element: usize,
+}
+
+/// Which elements start a new section under `strategy`. Chunks never span sections.
+/// `by_similarity` needs embeddings, so it is decided by the caller and treated as `basic` here.
+pub fn section_starts(elements: &[Element], strategy: &UnstructuredChunkingStrategy) -> Vec<bool> {
+    elements
+        .iter()
+        .enumerate()
+        .map(|(i, element)| match strategy {
+            UnstructuredChunkingStrategy::ByTitle => element.element_type == ElementType::Title,
+            UnstructuredChunkingStrategy::ByPage => {
+                i > 0 && element.page_number != elements[i - 1].page_number
+            }
+            _ => false,
+        })
+        .collect()
+}
+
+/// Cuts `text` into windows of at most `max` characters, preferring to cut at whitespace, each
+/// window repeating the last `overlap` characters of the one before
+pub(crate) fn split_text(text: &str, max: usize, overlap: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let max = max.max(1);
+    let overlap = overlap.min(max / 2);
+    let mut pieces = vec![];
+    let mut start = 0;
+    while start < chars.len() {
+        let mut end = (start + max).min(chars.len());
+        if end < chars.len() {
+            // The character just past the window can be the space to cut at
+            if let Some(space) = (start + max / 2..=end)
+                .rev()
+                .find(|i| chars[*i].is_whitespace())
+            {
+                end = space;
+            }
+        }
+        let piece = chars[start..end].iter().collect::<String>();
+        if !piece.trim().is_empty() {
+            pieces.push(piece.trim().to_string());
+        }
+        if end >= chars.len() {
+            break;
+        }
+        start = end.saturating_sub(overlap).max(start + 1);
+    }
+    pieces
+}
+
+/// Tables are split between rows, repeating the header row at the top of every chunk. Rows too
+/// long to fit even on their own are split like text.
+fn chunk_table(table: &Element, index: usize, max: usize, overlap: usize) -> Vec<Chunk> {
+    let text = table.text.trim();
+    if text.chars().count() <= max {
+        return vec![Chunk {
+            element_type: TABLE,
+            text: text.to_string(),
+            page_number: table.page_number,
+            first_element: index,
+        }];
+    }
+    let mut rows = text.lines();
+    let header = rows.next().unwrap_or_default();
+    let header_len = header.chars().count() + 1;
+    let mut texts: Vec<String> = vec![];
+    let mut current = header.to_string();
+    for row in rows {
+        let row_len = row.chars().count();
+        if current.chars().count() + 1 + row_len <= max {
+            current.push('\n');
+            current.push_str(row);
+            continue;
+        }
+        if current != header {
+            texts.push(std::mem::replace(&mut current, header.to_string()));
+        }
+        if header_len + row_len <= max {
+            current.push('\n');
+            current.push_str(row);
+        } else {
+            texts.extend(split_text(row, max, overlap));
+        }
+    }
+    if current != header || texts.is_empty() {
+        texts.push(current);
+    }
+    texts
+        .into_iter()
+        .map(|text| Chunk {
+            element_type: TABLE_CHUNK,
+            text,
+            page_number: table.page_number,
+            first_element: index,
+        })
+        .collect()
+}
+
+/// The last `overlap` characters of `text`, starting on a word where possible
+fn tail(text: &str, overlap: usize) -> &str {
+    let start = text
+        .char_indices()
+        .rev()
+        .nth(overlap.saturating_sub(1))
+        .map_or(0, |(i, _)| i);
+    let tail = &text[start..];
+    match tail.find(char::is_whitespace) {
+        Some(space) if start > 0 && space < tail.len() / 2 => tail[space..].trim_start(),
+        _ => tail,
+    }
+}
+
+/// Combines elements into chunks the way Unstructured IO does. Whole elements are packed together
+/// up to `max_characters`, and a chunk takes no more elements once it reaches
+/// `new_after_n_chars`. Elements too large for a chunk of their own are split with `overlap`.
+/// Tables are never combined with other elements. `section_starts` marks the elements that have
+/// to begin a new chunk.
+pub fn chunk_elements(
+    elements: &[Element],
+    section_starts: &[bool],
+    config: &UnstructuredChunkingConfig,
+) -> Vec<Chunk> {
+    let overlap = config.overlap;
+    // With overlap_all every chunk is prefixed with the end of the one before, so the prefix is
+    // kept out of the size limits
+    let reserved = if config.overlap_all { overlap + 1 } else { 0 };
+    let max = config.max_characters.saturating_sub(reserved).max(1);
+    let soft_max = config.new_after_n_chars.min(max).max(1);
+
+    let mut chunks: Vec<Chunk> = vec![];
+    let mut current: Option<Chunk> = None;
+    // Pieces of a split element already overlap the piece before them
+    let mut split_continuations: Vec<usize> = vec![];
+    for (i, element) in elements.iter().enumerate() {
+        let text = element.text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        let section_start = section_starts.get(i).copied().unwrap_or(false);
+        let separator = if element.continues {
+            SENTENCE_SEPARATOR
+        } else {
+            ELEMENT_SEPARATOR
+        };
+        let flush = current.as_ref().is_some_and(|chunk| {
+            let len = chunk.text.chars().count();
+            section_start
+                || element.element_type == ElementType::Table
+                || len >= soft_max
+                || len + separator.len() + text.chars().count() > max
+        });
+        if flush {
+            chunks.extend(current.take());
+        }
+        if element.element_type == ElementType::Table {
+            chunks.extend(chunk_table(element, i, max, overlap));
+            continue;
+        }
+        match current.as_mut() {
+            Some(chunk) => {
+                chunk.text.push_str(separator);
+                chunk.text.push_str(text);
+            }
+            None if text.chars().count() > max => {
+                for (part, text) in split_text(text, max, overlap).into_iter().enumerate() {
+                    if part > 0 {
+                        split_continuations.push(chunks.len());
+                    }
+                    chunks.push(Chunk {
+                        element_type: COMPOSITE_ELEMENT,
+                        text,
+                        page_number: element.page_number,
+                        first_element: i,
+                    });
+                }
+            }
+            None => {
+                current = Some(Chunk {
+                    element_type: COMPOSITE_ELEMENT,
+                    text: text.to_string(),
+                    page_number: element.page_number,
+                    first_element: i,
+                });
+            }
+        }
+    }
+    chunks.extend(current);
+
+    if config.overlap_all && overlap > 0 {
+        for i in (1..chunks.len()).rev() {
+            if chunks[i].element_type != COMPOSITE_ELEMENT
+                || chunks[i - 1].element_type != COMPOSITE_ELEMENT
+                || split_continuations.contains(&i)
+            {
+                continue;
+            }
+            let prefix = tail(&chunks[i - 1].text, overlap).to_string();
+            chunks[i].text = format!("{} {}", prefix, chunks[i].text);
+        }
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(
+        max_characters: usize,
+        overlap: usize,
+        overlap_all: bool,
+    ) -> UnstructuredChunkingConfig {
+        UnstructuredChunkingConfig {
+            max_characters,
+            new_after_n_chars: max_characters,
+            overlap,
+            overlap_all,
+            ..Default::default()
+        }
+    }
+
+    fn paragraph(text: &str) -> Element {
+        Element::new(ElementType::NarrativeText, text, Some(1))
+    }
+
+    #[test]
+    fn split_text_keeps_multibyte_pieces_within_max() {
+        let text = "Grüße aus Köln, 日本語のテキスト und ein paar Wörter mehr. ".repeat(20);
+        let pieces = split_text(&text, 40, 10);
+        assert!(pieces.len() > 1);
+        for piece in &pieces {
+            assert!(piece.chars().count() <= 40, "{:?} is too long", piece);
+        }
+    }
+
+    #[test]
+    fn split_text_repeats_overlap_without_whitespace() {
+        let text: String = "日本語のテキスト".chars().cycle().take(25).collect();
+        let pieces = split_text(&text, 10, 3);
+        let chars: Vec<Vec<char>> = pieces.iter().map(|piece| piece.chars().collect()).collect();
+        assert_eq!(
+            chars.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![10, 10, 10, 4]
+        );
+        for pair in chars.windows(2) {
+            assert_eq!(pair[0][pair[0].len() - 3..], pair[1][..3]);
+        }
+        let mut joined: Vec<char> = chars[0].clone();
+        for piece in &chars[1..] {
+            joined.extend(&piece[3..]);
+        }
+        assert_eq!(joined.into_iter().collect::<String>(), text);
+    }
+
+    #[test]
+    fn split_text_cuts_at_whitespace() {
+        let pieces = split_text("alpha beta gamma delta epsilon", 12, 0);
+        assert_eq!(pieces, vec!["alpha beta", "gamma delta", "epsilon"]);
+    }
+
+    #[test]
+    fn tail_starts_on_a_char_boundary() {
+        assert_eq!(tail("ab日本語", 2), "本語");
+        assert_eq!(tail("日本語", 10), "日本語");
+        assert_eq!(tail("eins zwei drei", 6), "drei");
+    }
+
+    #[test]
+    fn chunk_elements_packs_elements_up_to_max() {
+        let elements = vec![
+            paragraph("Äpfel und Birnen."),
+            paragraph("Kirschen."),
+            paragraph("Pflaumen und Zwetschgen sind auch Obst."),
+        ];
+        let starts = vec![false; elements.len()];
+        let chunks = chunk_elements(&elements, &starts, &config(30, 0, false));
+        let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "Äpfel und Birnen.\n\nKirschen.",
+                "Pflaumen und Zwetschgen sind",
+                "auch Obst."
+            ]
+        );
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| chunk.first_element)
+                .collect::<Vec<_>>(),
+            vec![0, 2, 2]
+        );
+    }
+
+    #[test]
+    fn chunk_elements_never_spans_sections_or_tables() {
+        let elements = vec![
+            Element::new(ElementType::Title, "Erster Teil", Some(1)),
+            paragraph("Text."),
+            Element::new(ElementType::Table, "a | b\n1 | 2", Some(1)),
+            paragraph("Mehr Text."),
+            Element::new(ElementType::Title, "Zweiter Teil", Some(2)),
+            paragraph("Ende."),
+        ];
+        let starts = section_starts(&elements, &UnstructuredChunkingStrategy::ByTitle);
+        let chunks = chunk_elements(&elements, &starts, &config(500, 0, false));
+        let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "Erster Teil\n\nText.",
+                "a | b\n1 | 2",
+                "Mehr Text.",
+                "Zweiter Teil\n\nEnde."
+            ]
+        );
+        assert_eq!(chunks[1].element_type, TABLE);
+        assert_eq!(chunks[3].page_number, Some(2));
+    }
+
+    #[test]
+    fn chunk_elements_starts_a_chunk_on_each_page() {
+        let elements = vec![
+            paragraph("Seite eins."),
+            Element::new(ElementType::NarrativeText, "Seite zwei.", Some(2)),
+        ];
+        let starts = section_starts(&elements, &UnstructuredChunkingStrategy::ByPage);
+        assert_eq!(starts, vec![false, true]);
+        assert_eq!(
+            chunk_elements(&elements, &starts, &config(500, 0, false)).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn chunk_elements_overlap_all_stays_within_max() {
+        let elements: Vec<Element> = (0..12)
+            .map(|i| paragraph(&format!("Absatz {} über Größe und Überlappung.", i)))
+            .collect();
+        let starts = vec![false; elements.len()];
+        let chunks = chunk_elements(&elements, &starts, &config(80, 15, true));
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert!(
+                chunk.text.chars().count() <= 80,
+                "{:?} is too long",
+                chunk.text
+            );
+        }
+        for pair in chunks.windows(2) {
+            let prefix = tail(&pair[0].text, 15);
+            assert!(pair[1].text.starts_with(prefix));
+        }
+    }
+
+    #[test]
+    fn chunk_table_repeats_the_header() {
+        let rows: Vec<String> = (0..10)
+            .map(|i| format!("zeile {} | wert {}", i, i))
+            .collect();
+        let table = Element::new(
+            ElementType::Table,
+            format!("spalte | größe\n{}", rows.join("\n")),
+            None,
+        );
+        let chunks = chunk_table(&table, 0, 60, 0);
+        assert!(chunks.len() > 1);
+        for chunk in &chunks {
+            assert_eq!(chunk.element_type, TABLE_CHUNK);
+            assert!(chunk.text.starts_with("spalte | größe\n"));
+            assert!(chunk.text.chars().count() <= 60);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use scraper::{ElementRef, Html, Node, Selector};
+
+use crate::data::chunking::partition::{collapse_whitespace, Element, ElementType};
+
+pub const HTML_MIME_TYPE: &str = "text/html";
+
+/// Elements that hold no readable content, or only site furniture repeated on every page
+const SKIPPED_TAGS: [&str; 13] = [
+    "head", "script", "style", "noscript", "template", "svg", "iframe", "nav", "aside", "form",
+    "button", "select", "dialog",
+];
+
+/// Page headers and footers are boilerplate, but those of an article or section hold its title
+/// and byline
+const PAGE_FURNITURE_TAGS: [&str; 2] = ["header", "footer"];
+
+const SKIPPED_ROLES: [&str; 5] = [
+    "navigation",
+    "banner",
+    "contentinfo",
+    "complementary",
+    "search",
+];
+
+const BLOCK_TAGS: [&str; 19] = [
+    "html",
+    "body",
+    "main",
+    "article",
+    "section",
+    "div",
+    "ul",
+    "ol",
+    "dl",
+    "dt",
+    "dd",
+    "blockquote",
+    "figure",
+    "figcaption",
+    "address",
+    "details",
+    "summary",
+    "hr",
+    "center",
+];
+
+fn is_boilerplate(element: &ElementRef) -> bool {
+    let value = element.value();
+    SKIPPED_TAGS.contains(&value.name())
+        || (PAGE_FURNITURE_TAGS.contains(&value.name())
+            && !element.ancestors().any(|ancestor| {
+                ancestor.value().as_element().is_some_and(|ancestor| {
+                    matches!(ancestor.name(), "main" | "article" | "section")
+                })
+            }))
+        || value
+            .attr("role")
+            .is_some_and(|role| SKIPPED_ROLES.contains(&role))
+        || value.attr("hidden").is_some()
+        || value.attr("aria-hidden") == Some("true")
+}
+
+struct HtmlPartitioner {
+    elements: Vec<Element>,
+    /// Text read since the last block started, and what it will become
+    inline: String,
+    inline_type: ElementType,
+}
+
+impl HtmlPartitioner {
+    fn flush(&mut self) {
+        let text = collapse_whitespace(&self.inline);
+        self.inline.clear();
+        if !text.is_empty() {
+            self.elements
+                .push(Element::new(self.inline_type, text, None));
+        }
+    }
+
+    fn block(&mut self, element: ElementRef, element_type: ElementType) {
+        self.flush();
+        let outer_type = std::mem::replace(&mut self.inline_type, element_type);
+        self.children(element);
+        self.flush();
+        self.inline_type = outer_type;
+    }
+
+    fn children(&mut self, element: ElementRef) {
+        for child in element.children() {
+            match child.value() {
+                Node::Text(text) => self.inline.push_str(text),
+                Node::Element(_) => {
+                    if let Some(child) = ElementRef::wrap(child) {
+                        self.element(child);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn table(&mut self, table: ElementRef) {
+        let rows = Selector::parse("tr").unwrap();
+        let cells = Selector::parse("th, td").unwrap();
+        let text = table
+            .select(&rows)
+            .map(|row| {
+                row.select(&cells)
+                    .map(|cell| collapse_whitespace(&cell.text().collect::<String>()))
+                    .collect::<Vec<String>>()
+                    .join(" | ")
+            })
+            .filter(|row| !row.replace('|', "").trim().is_empty())
+            .collect::<Vec<String>>()
+            .join("\n");
+        if !text.is_empty() {
+            self.elements
+                .push(Element::new(ElementType::Table, text, None));
+        }
+    }
+
+    fn element(&mut self, element: ElementRef) {
+        if is_boilerplate(&element) {
+            return;
+        }
+        let name = element.value().name();
+        match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.flush();
+                let text = collapse_whitespace(&element.text().collect::<String>());
+                if !text.is_empty() {
+                    let mut title = Element::new(ElementType::Title, text, None);
+                    title.depth = name[1..].parse::<usize>().unwrap_or(1) - 1;
+                    self.elements.push(title);
+                }
+            }
+            "p" => self.block(element, ElementType::NarrativeText),
+            "li" => self.block(element, ElementType::ListItem),
+            "table" => {
+                self.flush();
+                self.table(element);
+            }
+            "pre" => {
+                self.flush();
+                let code = element.text().collect::<String>();
+                if !code.trim().is_empty() {
+                    self.elements.push(Element::new(
+                        ElementType::CodeSnippet,
+                        code.trim_matches('\n'),
+                        None,
+                    ));
+                }
+            }
+            "br" => self.inline.push('\n'),
+            _ if BLOCK_TAGS.contains(&name) => self.block(element, ElementType::NarrativeText),
+            _ => self.children(element),
+        }
+    }
+}
+
+/// Partitions an HTML page into headings, paragraphs, list items, code blocks and tables. Only
+/// the page's `<main>` content is read when it marks one, and navigation, headers, footers,
+/// scripts and the like are left out.
+pub fn partition_html(html: &str) -> Vec<Element> {
+    let document = Html::parse_document(html);
+    let main = Selector::parse("main, [role=main]").unwrap();
+    let root = document
+        .select(&main)
+        .next()
+        .unwrap_or(document.root_element());
+    let mut partitioner = HtmlPartitioner {
+        elements: vec![],
+        inline: String::new(),
+        inline_type: ElementType::NarrativeText,
+    };
+    partitioner.element(root);
+    partitioner.flush();
+    partitioner.elements
+}
+
+fn markdown_row(cells: &[String], width: usize) -> String {
+    let mut row = String::from("|");
+    for i in 0..width {
+        let cell = cells.get(i).map_or("", String::as_str);
+        row.push_str(&format!(" {} |", cell.replace('|', "\\|")));
+    }
+    row
+}
+
+/// Converts an HTML table, as Unstructured IO returns in `text_as_html`, to a Markdown table with
+/// its first row as the header. Returns `None` if the table has no cells with text.
+pub fn html_table_to_markdown(html: &str) -> Option<String> {
+    let fragment = Html::parse_fragment(html);
+    let rows = Selector::parse("tr").unwrap();
+    let cells = Selector::parse("th, td").unwrap();
+    let rows: Vec<Vec<String>> = fragment
+        .select(&rows)
+        .map(|row| {
+            row.select(&cells)
+                .map(|cell| collapse_whitespace(&cell.text().collect::<String>()))
+                .collect::<Vec<String>>()
+        })
+        .filter(|cells| cells.iter().any(|cell| !cell.is_empty()))
+        .collect();
+    let width = rows.iter().map(Vec::len).max()?;
+    let mut lines = vec![
+        markdown_row(&rows[0], width),
+        format!("|{}", " --- |".repeat(width)),
+    ];
+    lines.extend(rows[1..].iter().map(|cells| markdown_row(cells, width)));
+    Some(lines.join("\n"))
+}
@@ -0,0 +1,399 @@
+use crate::adaptors::mongo::models::{
+    ChunkingBackend, Model, UnstructuredChunkingConfig, UnstructuredChunkingStrategy,
+    UnstructuredPartitioningStrategy,
+};
+use crate::data::chunking::chunker::{chunk_elements, section_starts};
+use crate::data::chunking::html::{html_table_to_markdown, partition_html, HTML_MIME_TYPE};
+use crate::data::chunking::office::{
+    office_format, partition_office, OfficeFormat, DOCX_MIME_TYPE, ODT_MIME_TYPE,
+};
+use crate::data::chunking::partition::{
+    partition_csv, partition_json, partition_markdown, partition_text, Element, ElementType,
+};
+use crate::data::chunking::pdf::{partition_pdf, PDF_MIME_TYPE};
+use crate::data::chunking::semantic::{sentence_elements, similarity_section_starts};
+use crate::data::models::FileType;
+use crate::data::unstructuredio::apis::chunk_text;
+use crate::data::unstructuredio::models::{Metadata, UnstructuredIOResponse};
+use anyhow::Result;
+use futures::future::try_join_all;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+
+pub mod chunker;
+pub mod html;
+pub mod office;
+pub mod partition;
+pub mod pdf;
+pub mod semantic;
+
+/// Whether a document may be chunked in process rather than sent to Unstructured IO. Text without
+/// a file type, such as a row's embedding field, is treated as plain text. PDFs are only read
+/// natively when the datasource opts in, as scanned pages have no text layer and need Unstructured
+/// IO's OCR.
+pub fn uses_native_chunker(
+    file_type: Option<FileType>,
+    config: Option<&UnstructuredChunkingConfig>,
+) -> bool {
+    let backend = config.and_then(|config| config.backend);
+    match file_type {
+        None
+        | Some(FileType::TXT)
+        | Some(FileType::MARKDOWN)
+        | Some(FileType::CSV)
+        | Some(FileType::HTML)
+        | Some(FileType::DOCX)
+        | Some(FileType::JSON)
+        | Some(FileType::JSONL) => backend != Some(ChunkingBackend::Unstructured),
+        Some(FileType::PDF) => backend == Some(ChunkingBackend::Native),
+        _ => false,
+    }
+}
+
+/// Partitions the document if it is in a format we can read ourselves. Returns its elements and
+/// MIME type, or `None` if it has to go to Unstructured IO.
+fn partition(
+    file: &[u8],
+    file_type: FileType,
+    config: Option<&UnstructuredChunkingConfig>,
+) -> Result<Option<(Vec<Element>, &'static str)>> {
+    let partitioned = match file_type {
+        FileType::TXT => Some((partition_text(&String::from_utf8_lossy(file)), "text/plain")),
+        FileType::MARKDOWN => Some((
+            partition_markdown(&String::from_utf8_lossy(file)),
+            "text/markdown",
+        )),
+        FileType::CSV => Some((partition_csv(file)?, "text/csv")),
+        FileType::HTML => Some((
+            partition_html(&String::from_utf8_lossy(file)),
+            HTML_MIME_TYPE,
+        )),
+        FileType::PDF => {
+            // Layout detection and OCR need Unstructured IO's models
+            let needs_layout = config.is_some_and(|config| {
+                matches!(
+                    config.partitioning,
+                    UnstructuredPartitioningStrategy::HiRes
+                        | UnstructuredPartitioningStrategy::OcrOnly
+                )
+            });
+            if needs_layout {
+                return Ok(None);
+            }
+            match partition_pdf(file) {
+                Ok(elements) => elements.map(|elements| (elements, PDF_MIME_TYPE)),
+                Err(e) => {
+                    log::warn!("Could not read PDF text layer. Error: {}", e);
+                    None
+                }
+            }
+        }
+        FileType::DOCX => match office_format(file) {
+            Some(format) => {
+                let mime_type = match format {
+                    OfficeFormat::Docx => DOCX_MIME_TYPE,
+                    OfficeFormat::Odt => ODT_MIME_TYPE,
+                };
+                Some((partition_office(file, &format)?, mime_type))
+            }
+            None => None,
+        },
+        FileType::JSON => Some((partition_json(file, false)?, "application/json")),
+        FileType::JSONL => Some((partition_json(file, true)?, "application/x-ndjson")),
+        // Spreadsheets, presentations, legacy Word documents, e-books, emails, RTF and images are
+        // read by Unstructured IO
+        _ => None,
+    };
+    Ok(partitioned)
+}
+
+fn element_id(file_name: &str, index: usize, text: &str) -> String {
+    let digest = Sha256::digest(format!("{}:{}:{}", file_name, index, text));
+    format!("{:x}", digest)[..32].to_string()
+}
+
+/// For each element, the index of the title it falls under. Titles fall under the nearest title
+/// before them at a higher level.
+fn parent_titles(elements: &[Element]) -> Vec<Option<usize>> {
+    let mut open_titles: Vec<usize> = vec![];
+    elements
+        .iter()
+        .enumerate()
+        .map(|(i, element)| {
+            if element.element_type != ElementType::Title {
+                return open_titles.last().copied();
+            }
+            open_titles.retain(|&title| elements[title].depth < element.depth);
+            let parent = open_titles.last().copied();
+            open_titles.push(i);
+            parent
+        })
+        .collect()
+}
+
+/// Titles from the top of the document down to `section`, the title whose section an element is in
+fn breadcrumbs(
+    elements: &[Element],
+    parents: &[Option<usize>],
+    section: Option<usize>,
+) -> Vec<String> {
+    let mut titles = vec![];
+    let mut title = section;
+    while let Some(i) = title {
+        titles.push(elements[i].text.clone());
+        title = parents[i];
+    }
+    titles.reverse();
+    titles
+}
+
+/// The title an element's section is opened by, which for a title is the title itself
+fn section_title(elements: &[Element], parents: &[Option<usize>], index: usize) -> Option<usize> {
+    match elements[index].element_type {
+        ElementType::Title => Some(index),
+        _ => parents[index],
+    }
+}
+
+struct ResponseFields {
+    field_type: &'static str,
+    text: String,
+    page_number: Option<i64>,
+    category_depth: Option<i64>,
+    parent_id: Option<String>,
+    breadcrumbs: Vec<String>,
+}
+
+fn response(
+    index: usize,
+    fields: ResponseFields,
+    file_name: &str,
+    mime_type: &str,
+) -> UnstructuredIOResponse {
+    UnstructuredIOResponse {
+        field_type: fields.field_type.to_string(),
+        element_id: element_id(file_name, index, &fields.text),
+        text: fields.text,
+        metadata: Metadata {
+            filetype: mime_type.to_string(),
+            page_number: fields.page_number,
+            filename: file_name.to_string(),
+            category_depth: fields.category_depth,
+            parent_id: fields.parent_id,
+            breadcrumbs: fields.breadcrumbs,
+            ..Default::default()
+        },
+    }
+}
+
+/// Chunks the elements, or returns them as they are when the datasource has no chunking
+/// configured, as Unstructured IO does
+async fn chunk_elements_natively(
+    mut elements: Vec<Element>,
+    file_name: &str,
+    mime_type: &str,
+    config: Option<UnstructuredChunkingConfig>,
+    model: &Model,
+    datasource_id: Option<&str>,
+) -> Result<Vec<UnstructuredIOResponse>> {
+    let Some(config) = config else {
+        let parents = parent_titles(&elements);
+        let title_ids: Vec<String> = elements
+            .iter()
+            .enumerate()
+            .map(|(i, element)| element_id(file_name, i, &element.text))
+            .collect();
+        return Ok(elements
+            .iter()
+            .enumerate()
+            .map(|(i, element)| {
+                let category_depth =
+                    (element.element_type == ElementType::Title).then_some(element.depth as i64);
+                let fields = ResponseFields {
+                    field_type: element.element_type.as_str(),
+                    text: element.text.clone(),
+                    page_number: element.page_number,
+                    category_depth,
+                    parent_id: parents[i].map(|title| title_ids[title].clone()),
+                    breadcrumbs: breadcrumbs(
+                        &elements,
+                        &parents,
+                        section_title(&elements, &parents, i),
+                    ),
+                };
+                response(i, fields, file_name, mime_type)
+            })
+            .collect());
+    };
+    let section_starts = match config.strategy {
+        UnstructuredChunkingStrategy::BySimilarity => {
+            // Cut between sentences rather than only between paragraphs, so chunks follow topic
+            // shifts within long paragraphs too
+            elements = sentence_elements(elements, config.max_characters);
+            similarity_section_starts(&elements, config.similarity_threshold, model, datasource_id)
+                .await?
+        }
+        _ => section_starts(&elements, &config.strategy),
+    };
+    let parents = parent_titles(&elements);
+    Ok(chunk_elements(&elements, &section_starts, &config)
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            // A chunk belongs to the section of the element it starts with, which is the title
+            // itself when it opens the section
+            let section = section_title(&elements, &parents, chunk.first_element);
+            let fields = ResponseFields {
+                field_type: chunk.element_type,
+                text: chunk.text,
+                page_number: chunk.page_number,
+                category_depth: None,
+                parent_id: section.map(|title| element_id(file_name, title, &elements[title].text)),
+                breadcrumbs: breadcrumbs(&elements, &parents, section),
+            };
+            response(i, fields, file_name, mime_type)
+        })
+        .collect())
+}
+
+/// Chunks a document with the native chunker where it can, and with Unstructured IO otherwise or
+/// when the datasource asks for it. Either way the chunks come back as Unstructured IO elements.
+/// `model` is the datasource's embedding model, used to compare sentences for `by_similarity`.
+pub async fn chunk_document(
+    file: Vec<u8>,
+    file_name: Option<String>,
+    file_type: Option<FileType>,
+    config: Option<UnstructuredChunkingConfig>,
+    model: &Model,
+    datasource_id: Option<&str>,
+) -> Result<Vec<UnstructuredIOResponse>> {
+    let mut file = file;
+    if uses_native_chunker(file_type, config.as_ref()) {
+        let native_type = file_type.unwrap_or(FileType::TXT);
+        let partition_config = config.clone();
+        // Parsing PDFs and office documents is CPU bound so it is kept off the async workers
+        let (partitioned, buffer) = tokio::task::spawn_blocking(move || {
+            let partitioned = partition(&file, native_type, partition_config.as_ref());
+            (partitioned, file)
+        })
+        .await?;
+        file = buffer;
+        if let Some((elements, mime_type)) = partitioned? {
+            let file_name =
+                file_name.unwrap_or(format!("text_file.{}", FileType::to_str(native_type)));
+            return chunk_elements_natively(
+                elements,
+                &file_name,
+                mime_type,
+                config,
+                model,
+                datasource_id,
+            )
+            .await;
+        }
+        log::debug!("Could not partition file natively, sending it to Unstructured IO");
+    }
+    let mut elements = chunk_with_unstructured(file, file_name, file_type, config).await?;
+    enrich_unstructured_elements(&mut elements);
+    Ok(elements)
+}
+
+/// Embeds tables from their HTML as Markdown, which keeps the rows and columns Unstructured IO
+/// runs together in `text`, and adds the titles each element falls under as its breadcrumbs
+fn enrich_unstructured_elements(elements: &mut [UnstructuredIOResponse]) {
+    let titles: HashMap<String, (String, Option<String>)> = elements
+        .iter()
+        .filter(|element| element.field_type == "Title")
+        .map(|element| {
+            (
+                element.element_id.clone(),
+                (element.text.clone(), element.metadata.parent_id.clone()),
+            )
+        })
+        .collect();
+    for element in elements.iter_mut() {
+        if let Some(table) = element
+            .metadata
+            .text_as_html
+            .as_deref()
+            .and_then(html_table_to_markdown)
+        {
+            element.text = table;
+        }
+        let mut title_id = match element.field_type.as_str() {
+            "Title" => Some(element.element_id.clone()),
+            _ => element.metadata.parent_id.clone(),
+        };
+        let mut seen = HashSet::new();
+        let mut breadcrumbs = vec![];
+        while let Some((text, parent_id)) = title_id
+            .filter(|id| seen.insert(id.clone()))
+            .and_then(|id| titles.get(&id))
+        {
+            breadcrumbs.push(text.clone());
+            title_id = parent_id.clone();
+        }
+        breadcrumbs.reverse();
+        element.metadata.breadcrumbs = breadcrumbs;
+    }
+}
+
+/// Splits a mailbox into its messages. Each one starts with a `From ` line, which is not part of
+/// the message, and lines in the body starting with `From ` are escaped as `>From `.
+fn split_mbox(mailbox: &[u8]) -> Vec<Vec<u8>> {
+    let text = String::from_utf8_lossy(mailbox);
+    let mut messages = vec![];
+    let mut message: Vec<&str> = vec![];
+    for line in text.lines() {
+        if line.starts_with("From ") {
+            if !message.is_empty() {
+                messages.push(message.join("\n").into_bytes());
+            }
+            message = vec![];
+            continue;
+        }
+        message.push(
+            line.strip_prefix('>')
+                .filter(|line| line.starts_with("From "))
+                .unwrap_or(line),
+        );
+    }
+    if message.iter().any(|line| !line.trim().is_empty()) {
+        messages.push(message.join("\n").into_bytes());
+    }
+    messages
+}
+
+async fn chunk_with_unstructured(
+    file: Vec<u8>,
+    file_name: Option<String>,
+    file_type: Option<FileType>,
+    config: Option<UnstructuredChunkingConfig>,
+) -> Result<Vec<UnstructuredIOResponse>> {
+    if file_type != Some(FileType::MBOX) {
+        return chunk_text(file, file_name, config, file_type).await;
+    }
+    // Unstructured IO reads single emails, so mailboxes are sent a message at a time
+    let messages = split_mbox(&file)
+        .into_iter()
+        .enumerate()
+        .map(|(i, message)| {
+            chunk_text(
+                message,
+                Some(format!("message_{}.eml", i + 1)),
+                config.clone(),
+                Some(FileType::EML),
+            )
+        });
+    let mut responses: Vec<UnstructuredIOResponse> = try_join_all(messages)
+        .await?
+        .into_iter()
+        .flatten()
+        .collect();
+    if let Some(file_name) = &file_name {
+        for element in responses.iter_mut() {
+            element.metadata.filename = file_name.clone();
+        }
+    }
+    Ok(responses)
+}
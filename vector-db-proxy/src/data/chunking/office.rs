@@ -0,0 +1,313 @@
+use std::io::{Cursor, Read};
+
+use anyhow::{anyhow, Result};
+use quick_xml::escape::unescape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use zip::ZipArchive;
+
+use crate::data::chunking::partition::{collapse_whitespace, Element, ElementType};
+
+pub const DOCX_MIME_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+pub const ODT_MIME_TYPE: &str = "application/vnd.oasis.opendocument.text";
+
+/// Largest an archive entry may be once decompressed. Entries are XML that compresses very well, so
+/// a small upload can hide an enormous one.
+const MAX_ENTRY_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Office documents are zip archives, these are the ones with a format we can read
+pub enum OfficeFormat {
+    Docx,
+    Odt,
+}
+
+fn read_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<Option<String>> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    // The declared size can not be trusted, so reading is capped as well
+    if entry.size() > MAX_ENTRY_BYTES {
+        return Err(anyhow!("{} is too large to read", name));
+    }
+    let mut content = String::new();
+    entry
+        .by_ref()
+        .take(MAX_ENTRY_BYTES + 1)
+        .read_to_string(&mut content)?;
+    if content.len() as u64 > MAX_ENTRY_BYTES {
+        return Err(anyhow!("{} is too large to read", name));
+    }
+    Ok(Some(content))
+}
+
+/// Works out whether the archive is a Word or OpenDocument text document. Spreadsheets and
+/// presentations come back as `None`.
+pub fn office_format(data: &[u8]) -> Option<OfficeFormat> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).ok()?;
+    if archive.by_name("word/document.xml").is_ok() {
+        return Some(OfficeFormat::Docx);
+    }
+    match read_entry(&mut archive, "mimetype") {
+        Ok(Some(mimetype)) if mimetype.trim() == ODT_MIME_TYPE => Some(OfficeFormat::Odt),
+        _ => None,
+    }
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    element
+        .try_get_attribute(name)
+        .ok()
+        .flatten()
+        .and_then(|attribute| {
+            unescape(&String::from_utf8_lossy(&attribute.value))
+                .ok()
+                .map(|value| value.into_owned())
+        })
+}
+
+/// Paragraphs and tables collected while walking the document body. Paragraphs inside a table
+/// become the text of the cell they are in.
+#[derive(Default)]
+struct DocumentBuilder {
+    elements: Vec<Element>,
+    page_number: i64,
+    paragraph: String,
+    paragraph_type: Option<ElementType>,
+    heading_depth: usize,
+    /// Rows of the tables currently open, innermost last
+    tables: Vec<Vec<Vec<String>>>,
+}
+
+impl DocumentBuilder {
+    fn new() -> Self {
+        DocumentBuilder {
+            page_number: 1,
+            ..Default::default()
+        }
+    }
+
+    fn start_paragraph(&mut self) {
+        self.paragraph.clear();
+        self.paragraph_type = None;
+        self.heading_depth = 0;
+    }
+
+    fn end_paragraph(&mut self) {
+        let text = collapse_whitespace(&self.paragraph);
+        self.paragraph.clear();
+        if text.is_empty() {
+            return;
+        }
+        if let Some(cell) = self
+            .tables
+            .last_mut()
+            .and_then(|rows| rows.last_mut())
+            .and_then(|cells| cells.last_mut())
+        {
+            if !cell.is_empty() {
+                cell.push(' ');
+            }
+            cell.push_str(&text);
+            return;
+        }
+        let element_type = self.paragraph_type.unwrap_or(ElementType::NarrativeText);
+        let mut element = Element::new(element_type, text, Some(self.page_number));
+        element.depth = self.heading_depth;
+        self.elements.push(element);
+    }
+
+    fn start_table(&mut self) {
+        self.tables.push(vec![]);
+    }
+
+    fn start_row(&mut self) {
+        if let Some(rows) = self.tables.last_mut() {
+            rows.push(vec![]);
+        }
+    }
+
+    fn start_cell(&mut self) {
+        if let Some(cells) = self.tables.last_mut().and_then(|rows| rows.last_mut()) {
+            cells.push(String::new());
+        }
+    }
+
+    fn end_table(&mut self) {
+        let Some(rows) = self.tables.pop() else {
+            return;
+        };
+        let text = rows
+            .iter()
+            .filter(|cells| cells.iter().any(|cell| !cell.is_empty()))
+            .map(|cells| cells.join(" | "))
+            .collect::<Vec<String>>()
+            .join("\n");
+        if text.is_empty() {
+            return;
+        }
+        // A table nested in another one ends up in the cell it sits in
+        match self
+            .tables
+            .last_mut()
+            .and_then(|rows| rows.last_mut())
+            .and_then(|cells| cells.last_mut())
+        {
+            Some(cell) => {
+                if !cell.is_empty() {
+                    cell.push(' ');
+                }
+                cell.push_str(&text.replace('\n', " "));
+            }
+            None => self.elements.push(Element::new(
+                ElementType::Table,
+                text,
+                Some(self.page_number),
+            )),
+        }
+    }
+}
+
+/// Heading level of a Word paragraph style, such as `Heading2` or `Title`
+fn docx_heading_depth(style: &str) -> Option<usize> {
+    if style == "Title" {
+        return Some(0);
+    }
+    style
+        .strip_prefix("Heading")
+        .and_then(|level| level.parse::<usize>().ok())
+        .map(|level| level.saturating_sub(1))
+}
+
+fn partition_docx_xml(xml: &str) -> Result<Vec<Element>> {
+    let mut reader = Reader::from_str(xml);
+    let mut builder = DocumentBuilder::new();
+    let mut in_text = false;
+    // Word marks where it broke pages when the document was last laid out, which covers explicit
+    // page breaks too. Older writers leave only the explicit ones.
+    let has_rendered_breaks = xml.contains("<w:lastRenderedPageBreak/>");
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => match element.name().as_ref() {
+                b"w:p" => builder.start_paragraph(),
+                b"w:numPr" => {
+                    builder.paragraph_type.get_or_insert(ElementType::ListItem);
+                }
+                b"w:t" => in_text = true,
+                b"w:tbl" => builder.start_table(),
+                b"w:tr" => builder.start_row(),
+                b"w:tc" => builder.start_cell(),
+                _ => {}
+            },
+            Event::Empty(element) => match element.name().as_ref() {
+                b"w:pStyle" => {
+                    let style = attribute(&element, "w:val").unwrap_or_default();
+                    if let Some(depth) = docx_heading_depth(&style) {
+                        builder.paragraph_type = Some(ElementType::Title);
+                        builder.heading_depth = depth;
+                    } else if style == "ListParagraph" {
+                        builder.paragraph_type = Some(ElementType::ListItem);
+                    }
+                }
+                b"w:numPr" => {
+                    builder.paragraph_type.get_or_insert(ElementType::ListItem);
+                }
+                b"w:tab" => builder.paragraph.push('\t'),
+                b"w:br" | b"w:cr" => {
+                    if attribute(&element, "w:type").as_deref() == Some("page") {
+                        if !has_rendered_breaks {
+                            builder.page_number += 1;
+                        }
+                    } else {
+                        builder.paragraph.push('\n');
+                    }
+                }
+                b"w:lastRenderedPageBreak" => builder.page_number += 1,
+                _ => {}
+            },
+            Event::Text(text) if in_text => builder.paragraph.push_str(&text.unescape()?),
+            Event::End(element) => match element.name().as_ref() {
+                b"w:p" => builder.end_paragraph(),
+                b"w:t" => in_text = false,
+                b"w:tbl" => builder.end_table(),
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(builder.elements)
+}
+
+fn partition_odt_xml(xml: &str) -> Result<Vec<Element>> {
+    let mut reader = Reader::from_str(xml);
+    let mut builder = DocumentBuilder::new();
+    let mut list_depth = 0;
+    let mut in_body = false;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => match element.name().as_ref() {
+                b"office:body" => in_body = true,
+                b"text:h" => {
+                    builder.start_paragraph();
+                    builder.paragraph_type = Some(ElementType::Title);
+                    builder.heading_depth = attribute(&element, "text:outline-level")
+                        .and_then(|level| level.parse::<usize>().ok())
+                        .unwrap_or(1)
+                        .saturating_sub(1);
+                }
+                b"text:p" => {
+                    builder.start_paragraph();
+                    if list_depth > 0 {
+                        builder.paragraph_type = Some(ElementType::ListItem);
+                    }
+                }
+                b"text:list-item" => list_depth += 1,
+                b"table:table" => builder.start_table(),
+                b"table:table-row" => builder.start_row(),
+                b"table:table-cell" => builder.start_cell(),
+                _ => {}
+            },
+            Event::Empty(element) => match element.name().as_ref() {
+                b"text:s" => {
+                    let count = attribute(&element, "text:c")
+                        .and_then(|count| count.parse::<usize>().ok())
+                        .unwrap_or(1);
+                    builder.paragraph.push_str(&" ".repeat(count));
+                }
+                b"text:tab" => builder.paragraph.push('\t'),
+                b"text:line-break" => builder.paragraph.push('\n'),
+                b"table:table-cell" => builder.start_cell(),
+                _ => {}
+            },
+            Event::Text(text) if in_body => builder.paragraph.push_str(&text.unescape()?),
+            Event::End(element) => match element.name().as_ref() {
+                b"text:h" | b"text:p" => builder.end_paragraph(),
+                b"text:list-item" => list_depth -= 1,
+                b"table:table" => builder.end_table(),
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(builder.elements)
+}
+
+/// Partitions a Word or OpenDocument text document into headings, paragraphs, list items and
+/// tables. Page numbers follow the page breaks saved in the document.
+pub fn partition_office(data: &[u8], format: &OfficeFormat) -> Result<Vec<Element>> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+    let entry = match format {
+        OfficeFormat::Docx => "word/document.xml",
+        OfficeFormat::Odt => "content.xml",
+    };
+    let xml = read_entry(&mut archive, entry)?
+        .ok_or(anyhow!("Document is missing its {} entry", entry))?;
+    match format {
+        OfficeFormat::Docx => partition_docx_xml(&xml),
+        OfficeFormat::Odt => partition_odt_xml(&xml),
+    }
+}
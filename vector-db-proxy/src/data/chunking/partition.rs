@@ -0,0 +1,389 @@
+use anyhow::Result;
+use serde_json::Value;
+
+/// Kinds of element a document is partitioned into, named as Unstructured IO names them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementType {
+    Title,
+    NarrativeText,
+    ListItem,
+    Table,
+    CodeSnippet,
+}
+
+impl ElementType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ElementType::Title => "Title",
+            ElementType::NarrativeText => "NarrativeText",
+            ElementType::ListItem => "ListItem",
+            ElementType::Table => "Table",
+            ElementType::CodeSnippet => "CodeSnippet",
+        }
+    }
+}
+
+/// A block of a document, such as a heading, a paragraph or a table
+#[derive(Debug, Clone)]
+pub struct Element {
+    pub element_type: ElementType,
+    /// For tables, one row per line with the header row first
+    pub text: String,
+    pub page_number: Option<i64>,
+    /// Heading level of titles, starting at 0 for the top level
+    pub depth: usize,
+    /// Carries on the paragraph of the element before it, as sentences split out of one do
+    pub continues: bool,
+}
+
+impl Element {
+    pub fn new(
+        element_type: ElementType,
+        text: impl Into<String>,
+        page_number: Option<i64>,
+    ) -> Self {
+        Element {
+            element_type,
+            text: text.into(),
+            page_number,
+            depth: 0,
+            continues: false,
+        }
+    }
+}
+
+/// Joins the words of `text` with single spaces
+pub(crate) fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn list_item_text(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    for bullet in ["- ", "* ", "+ ", "• "] {
+        if let Some(rest) = line.strip_prefix(bullet) {
+            return Some(rest.trim());
+        }
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 && digits < 4 {
+        let rest = &line[digits..];
+        if let Some(rest) = rest.strip_prefix(". ").or(rest.strip_prefix(") ")) {
+            return Some(rest.trim());
+        }
+    }
+    None
+}
+
+/// A short single line that does not read like a sentence. Close to the heuristic Unstructured IO
+/// uses for plain text.
+fn looks_like_title(paragraph: &str) -> bool {
+    !paragraph.contains('\n')
+        && paragraph.split_whitespace().count() <= 12
+        && paragraph.chars().any(|c| c.is_alphabetic())
+        && !paragraph.ends_with(['.', ',', ';', ':'])
+}
+
+/// Partitions plain text into paragraphs, titles and list items. Form feeds are treated as page
+/// breaks, so text with them gets page numbers.
+pub fn partition_text(text: &str) -> Vec<Element> {
+    let text = text.replace("\r\n", "\n");
+    let has_pages = text.contains('\u{c}');
+    let mut elements = vec![];
+    for (page, page_text) in text.split('\u{c}').enumerate() {
+        let page_number = has_pages.then_some(page as i64 + 1);
+        let mut paragraph: Vec<&str> = vec![];
+        for line in page_text.lines().chain([""]) {
+            if !line.trim().is_empty() {
+                paragraph.push(line.trim());
+                continue;
+            }
+            if paragraph.is_empty() {
+                continue;
+            }
+            if paragraph.iter().all(|line| list_item_text(line).is_some()) {
+                elements.extend(paragraph.iter().filter_map(|line| {
+                    list_item_text(line)
+                        .map(|item| Element::new(ElementType::ListItem, item, page_number))
+                }));
+            } else if paragraph.len() == 1 && looks_like_title(paragraph[0]) {
+                elements.push(Element::new(ElementType::Title, paragraph[0], page_number));
+            } else {
+                // Hard wrapped lines belong to the same paragraph
+                elements.push(Element::new(
+                    ElementType::NarrativeText,
+                    paragraph.join(" "),
+                    page_number,
+                ));
+            }
+            paragraph.clear();
+        }
+    }
+    elements
+}
+
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&level) {
+        if let Some(text) = line[level..].strip_prefix(' ') {
+            return Some((level - 1, text.trim().trim_end_matches('#').trim()));
+        }
+    }
+    None
+}
+
+fn is_table_separator(line: &str) -> bool {
+    line.contains('-')
+        && line
+            .chars()
+            .all(|c| matches!(c, '|' | '-' | ':' | ' ' | '\t'))
+}
+
+/// Partitions Markdown into headings, paragraphs, list items, code blocks and tables. Heading
+/// levels are kept as the depth of each title.
+pub fn partition_markdown(text: &str) -> Vec<Element> {
+    let text = text.replace("\r\n", "\n");
+    let mut elements: Vec<Element> = vec![];
+    let mut paragraph: Vec<&str> = vec![];
+    let mut table: Vec<&str> = vec![];
+    let mut code: Option<Vec<&str>> = None;
+
+    fn flush_paragraph(paragraph: &mut Vec<&str>, elements: &mut Vec<Element>) {
+        if !paragraph.is_empty() {
+            elements.push(Element::new(
+                ElementType::NarrativeText,
+                paragraph.join(" "),
+                None,
+            ));
+            paragraph.clear();
+        }
+    }
+
+    fn flush_table(table: &mut Vec<&str>, elements: &mut Vec<Element>) {
+        if !table.is_empty() {
+            let rows: Vec<String> = table
+                .iter()
+                .filter(|row| !is_table_separator(row))
+                .map(|row| {
+                    row.trim_matches('|')
+                        .split('|')
+                        .map(str::trim)
+                        .collect::<Vec<&str>>()
+                        .join(" | ")
+                })
+                .collect();
+            elements.push(Element::new(ElementType::Table, rows.join("\n"), None));
+            table.clear();
+        }
+    }
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if let Some(code_lines) = code.as_mut() {
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                elements.push(Element::new(
+                    ElementType::CodeSnippet,
+                    code_lines.join("\n"),
+                    None,
+                ));
+                code = None;
+            } else {
+                code_lines.push(line);
+            }
+            continue;
+        }
+        if !trimmed.starts_with('|') {
+            flush_table(&mut table, &mut elements);
+        }
+        if trimmed.is_empty() {
+            flush_paragraph(&mut paragraph, &mut elements);
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            flush_paragraph(&mut paragraph, &mut elements);
+            code = Some(vec![]);
+        } else if let Some((depth, title)) = heading(trimmed) {
+            flush_paragraph(&mut paragraph, &mut elements);
+            let mut element = Element::new(ElementType::Title, title, None);
+            element.depth = depth;
+            elements.push(element);
+        } else if trimmed.starts_with('|') {
+            flush_paragraph(&mut paragraph, &mut elements);
+            table.push(trimmed);
+        } else if let Some(item) = list_item_text(trimmed) {
+            flush_paragraph(&mut paragraph, &mut elements);
+            elements.push(Element::new(ElementType::ListItem, item, None));
+        } else if !paragraph.is_empty()
+            && (trimmed.chars().all(|c| c == '=') || trimmed.chars().all(|c| c == '-'))
+        {
+            // Setext headings underline the line before them
+            let title = paragraph.join(" ");
+            paragraph.clear();
+            let mut element = Element::new(ElementType::Title, title, None);
+            element.depth = usize::from(trimmed.starts_with('-'));
+            elements.push(element);
+        } else {
+            paragraph.push(trimmed);
+        }
+    }
+    flush_table(&mut table, &mut elements);
+    flush_paragraph(&mut paragraph, &mut elements);
+    if let Some(code_lines) = code {
+        elements.push(Element::new(
+            ElementType::CodeSnippet,
+            code_lines.join("\n"),
+            None,
+        ));
+    }
+    elements.retain(|element| !element.text.trim().is_empty());
+    elements
+}
+
+/// Partitions a CSV file into a single table, one row per line with cells separated by ` | `
+pub fn partition_csv(data: &[u8]) -> Result<Vec<Element>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(data);
+    let mut rows = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let row: Vec<&str> = record.iter().map(str::trim).collect();
+        if row.iter().any(|cell| !cell.is_empty()) {
+            rows.push(row.join(" | "));
+        }
+    }
+    if rows.is_empty() {
+        return Ok(vec![]);
+    }
+    Ok(vec![Element::new(
+        ElementType::Table,
+        rows.join("\n"),
+        None,
+    )])
+}
+
+fn json_scalar(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+/// Flattens a JSON value into `path: value` lines, with nested keys joined by dots
+fn flatten_json(path: &str, value: &Value, lines: &mut Vec<String>) {
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                flatten_json(&path, value, lines);
+            }
+        }
+        Value::Array(items) if items.iter().any(|item| item.is_object() || item.is_array()) => {
+            for (i, item) in items.iter().enumerate() {
+                flatten_json(&format!("{}[{}]", path, i), item, lines);
+            }
+        }
+        Value::Array(items) => {
+            let values: Vec<String> = items.iter().map(json_scalar).collect();
+            lines.push(format!("{}: {}", path, values.join(", ")));
+        }
+        value if path.is_empty() => lines.push(json_scalar(value)),
+        value => lines.push(format!("{}: {}", path, json_scalar(value))),
+    }
+}
+
+fn json_element(value: &Value) -> Option<Element> {
+    let mut lines = vec![];
+    flatten_json("", value, &mut lines);
+    let text = lines.join("\n");
+    (!text.trim().is_empty()).then(|| Element::new(ElementType::NarrativeText, text, None))
+}
+
+/// Partitions a JSON document into one element per record, a record being each item of a top
+/// level array or the whole document otherwise. With `lines` set every line is a record.
+pub fn partition_json(data: &[u8], lines: bool) -> Result<Vec<Element>> {
+    let text = String::from_utf8_lossy(data);
+    if lines {
+        return text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Ok(json_element(&serde_json::from_str(line)?)))
+            .filter_map(Result::transpose)
+            .collect();
+    }
+    Ok(match serde_json::from_str(&text)? {
+        Value::Array(records) => records.iter().filter_map(json_element).collect(),
+        record => json_element(&record).into_iter().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn types(elements: &[Element]) -> Vec<ElementType> {
+        elements
+            .iter()
+            .map(|element| element.element_type)
+            .collect()
+    }
+
+    #[test]
+    fn partition_text_finds_titles_lists_and_pages() {
+        let text = "Überblick\n\nDer erste Absatz\nist umgebrochen.\n\n• eins\n• zwei\n\u{c}日本語の段落です。";
+        let elements = partition_text(text);
+        assert_eq!(
+            types(&elements),
+            vec![
+                ElementType::Title,
+                ElementType::NarrativeText,
+                ElementType::ListItem,
+                ElementType::ListItem,
+                ElementType::Title,
+            ]
+        );
+        assert_eq!(elements[1].text, "Der erste Absatz ist umgebrochen.");
+        assert_eq!(elements[3].text, "zwei");
+        assert_eq!(elements[3].page_number, Some(1));
+        assert_eq!(elements[4].page_number, Some(2));
+    }
+
+    #[test]
+    fn partition_markdown_keeps_headings_tables_and_code() {
+        let text = "# Größen\n\nText\n\n| a | b |\n|---|---|\n| ä | ö |\n\n```\nlet x = 1;\n```\n\nUnterpunkt\n---";
+        let elements = partition_markdown(text);
+        assert_eq!(
+            types(&elements),
+            vec![
+                ElementType::Title,
+                ElementType::NarrativeText,
+                ElementType::Table,
+                ElementType::CodeSnippet,
+                ElementType::Title,
+            ]
+        );
+        assert_eq!(elements[0].text, "Größen");
+        assert_eq!(elements[2].text, "a | b\nä | ö");
+        assert_eq!(elements[3].text, "let x = 1;");
+        assert_eq!(elements[4].depth, 1);
+    }
+
+    #[test]
+    fn partition_csv_skips_empty_rows() {
+        let elements = partition_csv("name,stadt\nJürgen,Köln\n,\n".as_bytes()).unwrap();
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0].text, "name | stadt\nJürgen | Köln");
+    }
+
+    #[test]
+    fn partition_json_flattens_records() {
+        let elements = partition_json(br#"[{"a": {"b": 1}, "c": ["x", "y"]}, {}]"#, false).unwrap();
+        assert_eq!(elements.len(), 1);
+        assert_eq!(elements[0].text, "a.b: 1\nc: x, y");
+        let elements = partition_json(b"{\"a\": 1}\n\n{\"a\": 2}\n", true).unwrap();
+        assert_eq!(elements.len(), 2);
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId};
+
+use crate::data::chunking::partition::{partition_text, Element};
+
+pub const PDF_MIME_TYPE: &str = "application/pdf";
+
+/// Partitions the text layer of a PDF page by page. Returns `None` for PDFs we cannot read
+/// ourselves, encrypted ones and scans without a text layer, which need Unstructured IO's OCR.
+pub fn partition_pdf(data: &[u8]) -> Result<Option<Vec<Element>>> {
+    let document = Document::load_mem(data)?;
+    if document.is_encrypted() {
+        return Ok(None);
+    }
+    let mut elements = vec![];
+    for page_number in document.get_pages().into_keys() {
+        let text = match document.extract_text(&[page_number]) {
+            Ok(text) => text,
+            Err(e) => {
+                log::warn!(
+                    "Could not extract text from page {}. Error: {}",
+                    page_number,
+                    e
+                );
+                continue;
+            }
+        };
+        elements.extend(partition_text(&text).into_iter().map(|mut element| {
+            element.page_number = Some(page_number as i64);
+            element
+        }));
+    }
+    if elements.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(elements))
+}
+
+/// Each part of a split PDF, numbered by its first page, with the part saved as a PDF of its own
+pub type PageRanges = Vec<(usize, Vec<u8>)>;
+
+/// Page attributes a page takes from the page tree nodes above it when it does not set them
+const INHERITABLE_PAGE_ATTRIBUTES: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+/// Deepest page tree walked up when looking for inherited attributes, which guards against cycles
+const MAX_PAGE_TREE_DEPTH: usize = 64;
+
+fn inherited_attribute<'a>(
+    document: &'a Document,
+    page: &Dictionary,
+    key: &[u8],
+) -> Option<&'a Object> {
+    let mut parent = page.get(b"Parent").and_then(Object::as_reference).ok();
+    for _ in 0..MAX_PAGE_TREE_DEPTH {
+        let node = document.get_dictionary(parent?).ok()?;
+        if let Ok(value) = node.get(key) {
+            return Some(value);
+        }
+        parent = node.get(b"Parent").and_then(Object::as_reference).ok();
+    }
+    None
+}
+
+/// IDs of the objects `values` refer to, directly or through other objects. Parent links and
+/// references to `skipped_pages` are not followed, so a page does not pull in the rest of the
+/// document.
+fn referenced_objects(
+    document: &Document,
+    values: Vec<&Object>,
+    skipped_pages: &BTreeSet<ObjectId>,
+) -> BTreeSet<ObjectId> {
+    let mut found = BTreeSet::new();
+    let mut values = values;
+    while let Some(value) = values.pop() {
+        let dictionary = match value {
+            Object::Reference(id) => {
+                if !skipped_pages.contains(id) && found.insert(*id) {
+                    values.extend(document.get_object(*id));
+                }
+                continue;
+            }
+            Object::Array(items) => {
+                values.extend(items);
+                continue;
+            }
+            Object::Dictionary(dictionary) => dictionary,
+            Object::Stream(stream) => &stream.dict,
+            _ => continue,
+        };
+        values.extend(
+            dictionary
+                .iter()
+                .filter(|(key, _)| key.as_slice() != b"Parent")
+                .map(|(_, value)| value),
+        );
+    }
+    found
+}
+
+/// A new document holding only `pages` of `document` and the objects they use
+fn extract_pages(
+    document: &Document,
+    pages: &[ObjectId],
+    skipped_pages: &BTreeSet<ObjectId>,
+) -> Result<Document> {
+    let mut page_dictionaries = vec![];
+    for &page_id in pages {
+        let mut page = document.get_dictionary(page_id)?.clone();
+        for key in INHERITABLE_PAGE_ATTRIBUTES {
+            if !page.has(key) {
+                if let Some(value) = inherited_attribute(document, &page, key) {
+                    page.set(key, value.clone());
+                }
+            }
+        }
+        page_dictionaries.push((page_id, page));
+    }
+    let mut part = Document::with_version(document.version.clone());
+    let values = page_dictionaries
+        .iter()
+        .flat_map(|(_, page)| page.iter())
+        .filter(|(key, _)| key.as_slice() != b"Parent")
+        .map(|(_, value)| value)
+        .collect();
+    for id in referenced_objects(document, values, skipped_pages) {
+        if let Ok(object) = document.get_object(id) {
+            part.objects.insert(id, object.clone());
+        }
+    }
+    part.max_id = document.max_id;
+    let pages_id = part.new_object_id();
+    for (page_id, mut page) in page_dictionaries {
+        page.set("Parent", pages_id);
+        part.objects.insert(page_id, Object::Dictionary(page));
+    }
+    let kids: Vec<Object> = pages.iter().map(|&id| Object::Reference(id)).collect();
+    part.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => pages.len() as i64,
+        }),
+    );
+    let catalog_id = part.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    part.trailer.set("Root", catalog_id);
+    Ok(part)
+}
+
+/// Splits a PDF into documents of at most `pages_per_range` pages each, returned with the number
+/// of the first page they hold. Each document is built from just the objects its pages use.
+/// PDFs that fit in one range, and encrypted ones, come back as `None`.
+pub fn split_pdf(data: &[u8], pages_per_range: usize) -> Result<Option<PageRanges>> {
+    let document = Document::load_mem(data)?;
+    let pages: Vec<ObjectId> = document.get_pages().into_values().collect();
+    if document.is_encrypted() || pages_per_range == 0 || pages.len() <= pages_per_range {
+        return Ok(None);
+    }
+    let mut ranges = vec![];
+    for (i, range) in pages.chunks(pages_per_range).enumerate() {
+        let skipped_pages: BTreeSet<ObjectId> = pages
+            .iter()
+            .filter(|page| !range.contains(page))
+            .copied()
+            .collect();
+        let mut part = extract_pages(&document, range, &skipped_pages)?;
+        let mut buffer = vec![];
+        part.save_to(&mut buffer)?;
+        ranges.push((i * pages_per_range + 1, buffer));
+    }
+    Ok(Some(ranges))
+}
+
+#[cfg(test)]
+mod tests {
+    use lopdf::content::{Content, Operation};
+    use lopdf::Stream;
+
+    use super::*;
+
+    /// A PDF whose pages take their font and size from the page tree, saying "Page <n>"
+    fn pdf_with_pages(count: usize) -> Vec<u8> {
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let font_id = document.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let resources_id = document.add_object(dictionary! {
+            "Font" => dictionary! { "F1" => font_id },
+        });
+        let kids: Vec<Object> = (1..=count)
+            .map(|page| {
+                let content = Content {
+                    operations: vec![
+                        Operation::new("BT", vec![]),
+                        Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                        Operation::new("Td", vec![100.into(), 600.into()]),
+                        Operation::new(
+                            "Tj",
+                            vec![Object::string_literal(format!("Page {}", page))],
+                        ),
+                        Operation::new("ET", vec![]),
+                    ],
+                };
+                let content_id =
+                    document.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+                document
+                    .add_object(dictionary! {
+                        "Type" => "Page",
+                        "Parent" => pages_id,
+                        "Contents" => content_id,
+                    })
+                    .into()
+            })
+            .collect();
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => kids,
+                "Count" => count as i64,
+                "Resources" => resources_id,
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        document.trailer.set("Root", catalog_id);
+        let mut buffer = vec![];
+        document.save_to(&mut buffer).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn split_pdf_extracts_each_range() {
+        let ranges = split_pdf(&pdf_with_pages(5), 2).unwrap().unwrap();
+        assert_eq!(
+            ranges.iter().map(|(first, _)| *first).collect::<Vec<_>>(),
+            vec![1, 3, 5]
+        );
+        for (first_page, data) in ranges {
+            let part = Document::load_mem(&data).unwrap();
+            let pages = part.get_pages();
+            assert_eq!(pages.len(), if first_page == 5 { 1 } else { 2 });
+            for (number, page_id) in pages {
+                let page = part.get_dictionary(page_id).unwrap();
+                assert!(page.has(b"Resources") && page.has(b"MediaBox"));
+                let text = part.extract_text(&[number]).unwrap();
+                assert!(text.contains(&format!("Page {}", first_page + number as usize - 1)));
+            }
+            // Only the content of its own pages is copied. The cross-reference stream is read back
+            // as an object too, so it is left out.
+            let contents = part
+                .objects
+                .values()
+                .filter(
+                    |object| matches!(object, Object::Stream(stream) if !stream.dict.has(b"Type")),
+                )
+                .count();
+            assert_eq!(contents, part.get_pages().len());
+        }
+    }
+
+    #[test]
+    fn split_pdf_leaves_short_pdfs_whole() {
+        assert!(split_pdf(&pdf_with_pages(2), 2).unwrap().is_none());
+        assert!(split_pdf(&pdf_with_pages(3), 0).unwrap().is_none());
+    }
+}
@@ -0,0 +1,112 @@
+use crate::adaptors::mongo::models::Model;
+use crate::data::chunking::chunker::split_text;
+use crate::data::chunking::partition::{Element, ElementType};
+use crate::embeddings::utils::embed_text_chunks_async;
+use anyhow::{anyhow, Result};
+
+/// Words ending in a full stop that do not end a sentence
+const ABBREVIATIONS: [&str; 16] = [
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "vs", "etc", "e.g", "i.e", "inc", "ltd",
+    "no", "fig",
+];
+
+fn ends_sentence(word: &str) -> bool {
+    let word = word.trim_end_matches(['"', '\'', ')', ']', '”', '’']);
+    if !word.ends_with(['.', '!', '?']) {
+        return false;
+    }
+    let stem = word
+        .trim_start_matches(['"', '\'', '(', '[', '“', '‘'])
+        .trim_end_matches('.')
+        .to_lowercase();
+    // Initials such as the "J." in "J. Smith"
+    let is_initial = stem.chars().count() == 1 && stem.chars().all(char::is_alphabetic);
+    word.ends_with(['!', '?']) || !(is_initial || ABBREVIATIONS.contains(&stem.as_str()))
+}
+
+fn starts_sentence(word: &str) -> bool {
+    word.trim_start_matches(['"', '\'', '(', '[', '“', '‘'])
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_uppercase() || c.is_ascii_digit())
+}
+
+/// Splits a paragraph after each full stop, question or exclamation mark that is followed by the
+/// start of a new sentence
+pub fn split_sentences(text: &str) -> Vec<String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut sentences = vec![];
+    let mut sentence: Vec<&str> = vec![];
+    for (i, word) in words.iter().enumerate() {
+        sentence.push(word);
+        let next = words.get(i + 1);
+        if next.is_none() || (ends_sentence(word) && next.is_some_and(|w| starts_sentence(w))) {
+            sentences.push(sentence.join(" "));
+            sentence.clear();
+        }
+    }
+    sentences
+}
+
+/// Breaks paragraphs up into sentences so chunk boundaries can fall between them. Sentences longer
+/// than `max_characters` are cut to fit, and everything other than paragraphs is kept whole.
+pub fn sentence_elements(elements: Vec<Element>, max_characters: usize) -> Vec<Element> {
+    let mut sentences = vec![];
+    for element in elements {
+        if element.element_type != ElementType::NarrativeText {
+            sentences.push(element);
+            continue;
+        }
+        let pieces = split_sentences(&element.text)
+            .into_iter()
+            .flat_map(|sentence| split_text(&sentence, max_characters, 0));
+        for (i, piece) in pieces.enumerate() {
+            let mut sentence = Element::new(ElementType::NarrativeText, piece, element.page_number);
+            sentence.continues = i > 0;
+            sentences.push(sentence);
+        }
+    }
+    sentences
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+/// Starts a new section wherever an element is less similar to the one before it than
+/// `threshold`, comparing them with the datasource's own embedding model. Tables always stand
+/// alone so they are not embedded.
+pub async fn similarity_section_starts(
+    elements: &[Element],
+    threshold: f64,
+    model: &Model,
+    datasource_id: Option<&str>,
+) -> Result<Vec<bool>> {
+    let compared: Vec<usize> = (0..elements.len())
+        .filter(|&i| elements[i].element_type != ElementType::Table)
+        .collect();
+    let texts: Vec<String> = compared.iter().map(|&i| elements[i].text.clone()).collect();
+    let embeddings = embed_text_chunks_async(texts, model, datasource_id).await?;
+    if embeddings.len() != compared.len() {
+        return Err(anyhow!(
+            "Expected {} embeddings from the model but got {}",
+            compared.len(),
+            embeddings.len()
+        ));
+    }
+    let mut section_starts = vec![false; elements.len()];
+    for (pair, vectors) in compared.windows(2).zip(embeddings.windows(2)) {
+        // Only neighbours are compared, anything either side of a table starts afresh anyway
+        if pair[1] == pair[0] + 1 {
+            section_starts[pair[1]] =
+                (cosine_similarity(&vectors[0], &vectors[1]) as f64) < threshold;
+        }
+    }
+    Ok(section_starts)
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::vector_databases::models::{Point, SearchRequest, VectorDatabaseStatus};
+use crate::vector_databases::vector_database::VectorDatabase;
+
+/// Payload field holding the fingerprint of the text a point's vector was embedded from
+pub const CONTENT_HASH_PAYLOAD_FIELD: &str = "ac_content_hash";
+
+/// SHA-256 over the embedding model and the cleaned text. The same text embedded with a different
+/// model gives a different fingerprint, so changing a datasource's model still re-embeds every row.
+pub fn content_hash(model: &str, text: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(model.as_bytes());
+    hasher.update([0]);
+    hasher.update(text.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Writes `payload` without re-embedding if the point in the live collection was embedded from the
+/// same content. Overwrite syncs stage into a fresh collection, so there the existing vector is
+/// copied across with the new payload. Returns false if the row has to be embedded.
+pub async fn reuse_unchanged_embedding(
+    vector_database_client: &dyn VectorDatabase,
+    live_request: SearchRequest,
+    target_request: SearchRequest,
+    point_id: &str,
+    content_hash: &str,
+    payload: HashMap<String, Value>,
+) -> bool {
+    let is_staging = live_request.collection != target_request.collection;
+    let existing = match vector_database_client
+        .get_point(live_request, point_id.to_string(), is_staging)
+        .await
+    {
+        Ok(Some(existing)) => existing,
+        Ok(None) => return false,
+        Err(e) => {
+            log::warn!(
+                "Could not read point: {} to compare content, embedding anyway. Error: {}",
+                point_id,
+                e
+            );
+            return false;
+        }
+    };
+    let is_unchanged = existing
+        .payload
+        .as_ref()
+        .and_then(|payload| payload.get(CONTENT_HASH_PAYLOAD_FIELD))
+        .and_then(|hash| hash.as_str())
+        .is_some_and(|hash| hash == content_hash);
+    if !is_unchanged {
+        return false;
+    }
+    let result = match (is_staging, existing.vector) {
+        (false, _) => {
+            vector_database_client
+                .update_point_payload(target_request, point_id.to_string(), payload)
+                .await
+        }
+        (true, Some(vector)) => {
+            let point = Point::new(
+                Some(Value::String(point_id.to_string())),
+                vector,
+                Some(payload),
+            );
+            vector_database_client
+                .insert_point(target_request, point)
+                .await
+        }
+        (true, None) => return false,
+    };
+    match result {
+        Ok(VectorDatabaseStatus::Ok) => true,
+        Ok(_) => false,
+        Err(e) => {
+            log::warn!(
+                "Could not update payload of unchanged point: {}, embedding anyway. Error: {}",
+                point_id,
+                e
+            );
+            false
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use mongodb::Database;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::adaptors::mongo::queries::claim_point_cursor;
+use crate::vector_databases::models::SearchRequest;
+use crate::vector_databases::vector_database::VectorDatabase;
+
+/// Payload field holding the value of the stream's cursor field for the row a point was built from
+pub const CURSOR_PAYLOAD_FIELD: &str = "ac_cursor";
+
+const NUMBER_OF_POINT_LOCKS: usize = 256;
+
+/// Workers in this process hold the lock for a point ID from the cursor check until the point is
+/// written, so an older version of a row that passed the check can not overwrite a newer one
+/// written in between. The point ID migration holds it while moving a legacy point to its new ID,
+/// so it can not overwrite a row written under that ID since. Locks are striped to keep memory flat however many rows are synced. Workers
+/// in other processes are kept in order by the cursor stored in MongoDB.
+static POINT_LOCKS: Lazy<Vec<Mutex<()>>> =
+    Lazy::new(|| (0..NUMBER_OF_POINT_LOCKS).map(|_| Mutex::new(())).collect());
+
+pub async fn lock_point(point_id: &str) -> MutexGuard<'static, ()> {
+    let mut hasher = DefaultHasher::new();
+    point_id.hash(&mut hasher);
+    POINT_LOCKS[hasher.finish() as usize % NUMBER_OF_POINT_LOCKS]
+        .lock()
+        .await
+}
+
+/// Reads the cursor value from a record. Airbyte cursor fields are paths into nested objects.
+pub fn cursor_value(record: &HashMap<String, Value>, cursor_field: &[String]) -> Option<Value> {
+    let (first, rest) = cursor_field.split_first()?;
+    let mut value = record.get(first)?;
+    for key in rest {
+        value = value.get(key)?;
+    }
+    Some(value.clone()).filter(|v| !v.is_null())
+}
+
+/// Numbers are compared numerically and strings lexicographically, which also orders ISO 8601
+/// timestamps correctly. Any other combination can not be ordered.
+fn compare_cursors(incoming: &Value, existing: &Value) -> Option<Ordering> {
+    match (incoming, existing) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// Returns true if a newer version of the row than `incoming_cursor` has already been written to
+/// `point_id`. The newest cursor of each point is claimed in MongoDB with a compare-and-set, which
+/// holds across every proxy process. If MongoDB can not be reached the cursor stored with the point
+/// is compared instead. Cursors that can not be compared are never considered newer so the write
+/// goes ahead.
+pub async fn is_stale_update(
+    mongo: &Database,
+    datasource_id: &str,
+    vector_database_client: &dyn VectorDatabase,
+    search_request: SearchRequest,
+    point_id: &str,
+    incoming_cursor: &Value,
+) -> bool {
+    match claim_point_cursor(mongo, datasource_id, point_id, incoming_cursor).await {
+        Ok(claimed) => return !claimed,
+        Err(e) => log::warn!(
+            "Could not claim cursor for point: {}, comparing with the stored point. Error: {}",
+            point_id,
+            e
+        ),
+    }
+    match vector_database_client
+        .get_point(search_request, point_id.to_string(), false)
+        .await
+    {
+        Ok(Some(existing)) => existing
+            .payload
+            .as_ref()
+            .and_then(|payload| payload.get(CURSOR_PAYLOAD_FIELD))
+            .and_then(|existing_cursor| compare_cursors(incoming_cursor, existing_cursor))
+            .is_some_and(|ordering| ordering == Ordering::Less),
+        Ok(None) => false,
+        Err(e) => {
+            log::warn!(
+                "Could not read point: {} to compare cursors, writing anyway. Error: {}",
+                point_id,
+                e
+            );
+            false
+        }
+    }
+}
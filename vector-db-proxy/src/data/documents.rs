@@ -0,0 +1,182 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use mongodb::Database;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::adaptors::mongo::models::{DataSources, DocumentRecord};
+use crate::adaptors::mongo::queries::{
+    delete_document_points, delete_document_record, get_datasource, get_document_by_id,
+    get_document_points, save_document, set_document_points,
+};
+use crate::data::sync_modes::{live_collection, search_request_for};
+use crate::vector_databases::helpers::check_byo_vector_database;
+use crate::vector_databases::models::{Point, VectorDatabaseStatus};
+use crate::vector_databases::vector_database::default_vector_db_client;
+
+/// Points written while ingesting a file
+#[derive(Debug, Default)]
+pub struct IngestedPoints {
+    pub point_ids: Vec<String>,
+    /// False if some of the file could not be embedded or written
+    pub complete: bool,
+}
+
+/// IDs of the points, in the form they are registered under
+pub fn point_ids(points: &[Point]) -> Vec<String> {
+    points
+        .iter()
+        .filter_map(|point| match &point.index {
+            Some(Value::String(id)) => Some(id.clone()),
+            Some(id) => Some(id.to_string()),
+            None => None,
+        })
+        .collect()
+}
+
+pub fn file_checksum(file: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(file))
+}
+
+/// File name of a path as read from an ingestion message, which may still be quoted
+pub fn document_file_name(file_path: &str) -> String {
+    let file_path = file_path.trim_matches('"');
+    Path::new(file_path)
+        .file_name()
+        .map_or(file_path.to_string(), |name| {
+            name.to_string_lossy().into_owned()
+        })
+}
+
+/// A file needs no re-ingesting if it has not changed since it was embedded in full with the same
+/// model
+pub fn is_unchanged(existing: Option<&DocumentRecord>, checksum: &str, model: &str) -> bool {
+    existing.is_some_and(|document| {
+        document.complete && document.checksum == checksum && document.model == model
+    })
+}
+
+/// IDs of the points a document was stored as, including those of documents registered before
+/// the IDs were kept apart from them
+async fn document_point_ids(mongo: &Database, document: &DocumentRecord) -> Result<Vec<String>> {
+    let mut point_ids = get_document_points(mongo, document.id).await?;
+    let registered: HashSet<String> = point_ids.iter().cloned().collect();
+    point_ids.extend(
+        document
+            .legacy_point_ids
+            .iter()
+            .filter(|id| !registered.contains(*id))
+            .cloned(),
+    );
+    Ok(point_ids)
+}
+
+async fn delete_points(mongo: &Database, datasource: &DataSources, ids: Vec<String>) -> Result<()> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let vector_database_client = check_byo_vector_database(datasource.clone(), mongo)
+        .await
+        .unwrap_or(default_vector_db_client().await);
+    let search_request = search_request_for(datasource, live_collection(datasource));
+    let vector_database_client = vector_database_client.read().await;
+    match vector_database_client
+        .delete_points_by_ids(search_request, ids)
+        .await
+    {
+        Ok(VectorDatabaseStatus::Ok) => Ok(()),
+        Ok(status) => Err(anyhow!("Vector database returned: {:?}", status)),
+        Err(e) => Err(anyhow!("{}", e)),
+    }
+}
+
+/// A document for a file about to be ingested, registered once its points are written
+pub fn new_document(
+    datasource: &DataSources,
+    file_name: String,
+    source_location: Value,
+    checksum: String,
+    model: String,
+) -> DocumentRecord {
+    let now = DateTime::now();
+    DocumentRecord {
+        id: ObjectId::new(),
+        datasource_id: datasource.id,
+        team_id: datasource.team_id,
+        file_name,
+        source_location,
+        checksum,
+        complete: false,
+        point_count: 0,
+        legacy_point_ids: vec![],
+        model,
+        created_date: now,
+        updated_date: now,
+    }
+}
+
+/// Records the points a file was ingested as, taking the place of the previous version of the
+/// file if there was one. Points of the previous version that the new one did not overwrite are
+/// deleted. If the new version was only partly written they are kept instead, and stay registered
+/// so deleting the document still removes them. The document is then marked incomplete, so
+/// uploading the same file again ingests it again.
+pub async fn register_document(
+    mongo: &Database,
+    datasource: &DataSources,
+    existing: Option<DocumentRecord>,
+    mut document: DocumentRecord,
+    ingested: IngestedPoints,
+) -> Result<()> {
+    let mut point_ids = ingested.point_ids;
+    document.complete = ingested.complete;
+    if let Some(existing) = existing {
+        let written: HashSet<&String> = point_ids.iter().collect();
+        let stale: Vec<String> = document_point_ids(mongo, &existing)
+            .await?
+            .into_iter()
+            .filter(|id| !written.contains(id))
+            .collect();
+        if ingested.complete {
+            if let Err(e) = delete_points(mongo, datasource, stale.clone()).await {
+                log::warn!(
+                    "Could not remove old points of document: {}. Error: {}",
+                    existing.id,
+                    e
+                );
+                point_ids.extend(stale);
+            }
+        } else {
+            point_ids.extend(stale);
+        }
+        document.id = existing.id;
+        document.created_date = existing.created_date;
+    }
+    document.point_count = point_ids.len() as u64;
+    set_document_points(mongo, datasource.id, document.id, &point_ids).await?;
+    save_document(mongo, &document).await
+}
+
+/// Deletes the points of a document and then the document itself. Returns the number of points
+/// removed, or `None` if the datasource has no such document.
+pub async fn delete_document(
+    mongo: &Database,
+    datasource_id: &str,
+    document_id: &str,
+) -> Result<Option<usize>> {
+    let Some(datasource) = get_datasource(mongo, datasource_id).await? else {
+        return Ok(None);
+    };
+    let Some(document) = get_document_by_id(mongo, datasource_id, document_id).await? else {
+        return Ok(None);
+    };
+    let point_ids = document_point_ids(mongo, &document).await?;
+    let point_count = point_ids.len();
+    delete_points(mongo, &datasource, point_ids).await?;
+    delete_document_points(mongo, document.id).await?;
+    delete_document_record(mongo, document.id).await?;
+    Ok(Some(point_count))
+}
@@ -1,6 +1,35 @@
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 use std::hash::{DefaultHasher, Hash, Hasher};
-use uuid::Uuid;
+use uuid::{uuid, Uuid};
 
+/// Payload field recording which scheme a point's ID was derived with
+pub const HASH_VERSION_PAYLOAD_FIELD: &str = "ac_hash_version";
+/// IDs built by `hash_string_to_uuid`
+pub const LEGACY_HASH_VERSION: u64 = 1;
+/// IDs built by `point_id_for_key` and `point_id_for_record`
+pub const POINT_ID_HASH_VERSION: u64 = 2;
+
+/// Payload fields linking the points a row was split into under
+/// `EmbeddingOverflowPolicy::Split`. The first part keeps the row's own point ID, which is also
+/// the parent ID.
+pub const PARENT_ID_PAYLOAD_FIELD: &str = "ac_parent_id";
+pub const PART_INDEX_PAYLOAD_FIELD: &str = "ac_part_index";
+pub const PART_COUNT_PAYLOAD_FIELD: &str = "ac_part_count";
+
+/// Payload fields linking the chunks of a document, so a search can widen a match to the section
+/// it is in or to the chunks either side of it
+pub const DOCUMENT_ID_PAYLOAD_FIELD: &str = "ac_document_id";
+pub const SECTION_ID_PAYLOAD_FIELD: &str = "ac_section_id";
+pub const CHUNK_INDEX_PAYLOAD_FIELD: &str = "ac_chunk_index";
+pub const PREVIOUS_CHUNK_ID_PAYLOAD_FIELD: &str = "ac_previous_chunk_id";
+pub const NEXT_CHUNK_ID_PAYLOAD_FIELD: &str = "ac_next_chunk_id";
+
+/// Namespace all point IDs are derived in. Changing it changes every point ID.
+const POINT_ID_NAMESPACE: Uuid = uuid!("8b5d3f0e-4c1a-5e3b-9f6d-2a7c1e0b9d44");
+
+/// Legacy point IDs. `DefaultHasher` output is not guaranteed to be the same across Rust releases,
+/// so this is only kept to recognise points written before `POINT_ID_HASH_VERSION`.
 pub fn hash_string_to_uuid(hashing_salt: &str, string: &str) -> String {
     // Create a hasher for the first 64 bits
     let mut hasher1 = DefaultHasher::new();
@@ -21,3 +50,88 @@ pub fn hash_string_to_uuid(hashing_salt: &str, string: &str) -> String {
     // Convert the 128-bit array into a UUID
     Uuid::from_bytes(uuid_bytes).to_string()
 }
+
+/// Sorts object keys at every level so the same value always serializes to the same string
+fn canonicalize(value: &Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let mut keys: Vec<&String> = object.keys().collect();
+            keys.sort();
+            Value::Object(
+                keys.into_iter()
+                    .map(|key| (key.clone(), canonicalize(&object[key])))
+                    .collect::<Map<String, Value>>(),
+            )
+        }
+        Value::Array(values) => Value::Array(values.iter().map(canonicalize).collect()),
+        value => value.clone(),
+    }
+}
+
+/// UUIDv5 in `POINT_ID_NAMESPACE` over the salt, a NUL byte and the canonical JSON of `value`
+fn stable_uuid(hashing_salt: &str, value: &Value) -> String {
+    let mut name = hashing_salt.as_bytes().to_vec();
+    name.push(0);
+    name.extend(canonicalize(value).to_string().into_bytes());
+    Uuid::new_v5(&POINT_ID_NAMESPACE, &name).to_string()
+}
+
+/// Point ID for a row, derived from the values of its primary key columns in key order
+pub fn point_id_for_key(hashing_salt: &str, key_values: &[Value]) -> String {
+    stable_uuid(hashing_salt, &Value::Array(key_values.to_vec()))
+}
+
+/// Point ID for a row whose primary key columns are missing, derived from the whole row. Updates
+/// to such a row create a new point rather than replacing the old one.
+pub fn point_id_for_record(hashing_salt: &str, record: &HashMap<String, Value>) -> String {
+    let record: Map<String, Value> = record
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    stable_uuid(hashing_salt, &Value::Object(record))
+}
+
+/// Point ID for chunk `chunk` of a row whose embedding field was chunked. Chunk 0 is the row's
+/// own point.
+pub fn chunk_point_id(hashing_salt: &str, row_id: &str, chunk: usize) -> String {
+    if chunk == 0 {
+        return row_id.to_string();
+    }
+    stable_uuid(
+        hashing_salt,
+        &Value::Array(vec![
+            Value::String(row_id.to_string()),
+            Value::String("chunk".to_string()),
+            Value::from(chunk),
+        ]),
+    )
+}
+
+/// ID shared by the chunks under the same title of a document. Chunks before the first title
+/// belong to the document as a whole.
+pub fn section_id(hashing_salt: &str, document_id: &str, title_id: Option<&str>) -> String {
+    match title_id {
+        Some(title_id) => stable_uuid(
+            hashing_salt,
+            &Value::Array(vec![
+                Value::String(document_id.to_string()),
+                Value::String(title_id.to_string()),
+            ]),
+        ),
+        None => document_id.to_string(),
+    }
+}
+
+/// Point ID for part `part` of a row split into several points. Part 0 is the row's own point.
+pub fn part_point_id(hashing_salt: &str, parent_id: &str, part: usize) -> String {
+    if part == 0 {
+        return parent_id.to_string();
+    }
+    stable_uuid(
+        hashing_salt,
+        &Value::Array(vec![
+            Value::String(parent_id.to_string()),
+            Value::from(part),
+        ]),
+    )
+}
@@ -1,4 +1,16 @@
-mod helpers;
+pub mod cdc;
+pub mod content_hash;
+pub mod checkpoints;
+pub mod chunk_preview;
+pub mod chunking;
+pub mod cursor;
+pub mod documents;
+pub(crate) mod helpers;
 pub mod models;
+pub mod point_id_migration;
 pub mod processing_incoming_messages;
+pub mod record_counts;
+pub mod structured_files;
+pub mod sync_modes;
+pub mod traces;
 pub mod unstructuredio;
@@ -1,13 +1,33 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum FileType {
     PDF,
     TXT,
     CSV,
+    /// Word and OpenDocument text documents
     DOCX,
+    /// Legacy Word documents, which are not zip archives but compound files
+    DOC,
     MARKDOWN,
+    HTML,
+    JSON,
+    /// One JSON value per line
+    JSONL,
+    /// Excel and OpenDocument spreadsheets
+    XLSX,
+    /// PowerPoint and OpenDocument presentations
+    PPTX,
+    EPUB,
+    /// A single email message
+    EML,
+    /// Many email messages in one file
+    MBOX,
+    /// An Outlook message, a compound file rather than text
+    MSG,
+    RTF,
+    IMAGE,
     UNKNOWN,
 }
 
@@ -17,8 +37,21 @@ impl FileType {
             Self::MARKDOWN => "markdown",
             Self::CSV => "csv",
             Self::DOCX => "docx",
+            Self::DOC => "doc",
             Self::PDF => "pdf",
             Self::TXT => "txt",
+            Self::HTML => "html",
+            Self::JSON => "json",
+            Self::JSONL => "jsonl",
+            Self::XLSX => "xlsx",
+            Self::PPTX => "pptx",
+            Self::EPUB => "epub",
+            Self::EML => "eml",
+            Self::MBOX => "mbox",
+            Self::MSG => "msg",
+            Self::RTF => "rtf",
+            // The image format is not known from the type, see `image_extension`
+            Self::IMAGE => "image",
             _ => "unknown",
         }
     }
@@ -26,12 +59,26 @@ impl FileType {
 
 impl From<String> for FileType {
     fn from(value: String) -> Self {
-        match value.as_str() {
+        match value.to_lowercase().as_str() {
             "pdf" => Self::PDF,
-            "txt" => Self::TXT,
+            "txt" | "text" | "log" => Self::TXT,
             "csv" => Self::CSV,
-            "markdown" => Self::MARKDOWN,
-            "docx" | "pptx" | "xlsx" | "odt" | "ods" | "odp" => Self::DOCX,
+            "markdown" | "md" => Self::MARKDOWN,
+            "html" | "htm" => Self::HTML,
+            "docx" | "odt" => Self::DOCX,
+            "doc" => Self::DOC,
+            "json" => Self::JSON,
+            "jsonl" | "ndjson" => Self::JSONL,
+            "xlsx" | "xls" | "ods" => Self::XLSX,
+            "pptx" | "ppt" | "odp" => Self::PPTX,
+            "epub" => Self::EPUB,
+            "eml" => Self::EML,
+            "msg" => Self::MSG,
+            "mbox" => Self::MBOX,
+            "rtf" => Self::RTF,
+            "png" | "jpg" | "jpeg" | "tif" | "tiff" | "bmp" | "gif" | "heic" | "webp" => {
+                Self::IMAGE
+            }
             _ => Self::UNKNOWN,
         }
     }
@@ -0,0 +1,203 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use mongodb::Database;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tokio::sync::RwLock;
+
+use crate::adaptors::mongo::models::DataSources;
+use crate::adaptors::mongo::queries::{get_datasource, set_point_id_hash_version};
+use crate::data::cursor::lock_point;
+use crate::data::helpers::{
+    hash_string_to_uuid, point_id_for_key, HASH_VERSION_PAYLOAD_FIELD, POINT_ID_HASH_VERSION,
+};
+use crate::data::sync_modes::{live_collection, search_request_for};
+use crate::init::env_variables::GLOBAL_DATA;
+use crate::vector_databases::helpers::check_byo_vector_database;
+use crate::vector_databases::models::Point;
+use crate::vector_databases::vector_database::default_vector_db_client;
+
+const MIGRATION_PAGE_SIZE: u32 = 256;
+
+#[derive(Serialize, Debug, Default)]
+pub struct PointIdMigrationReport {
+    pub migrated: usize,
+    /// Legacy points removed because the row had already been written under its new ID
+    pub superseded: usize,
+    /// Points already on the current scheme, or that were not keyed on a primary key
+    pub unchanged: usize,
+    /// Keyed points whose primary key columns are not in the payload, so their ID can not be rebuilt
+    pub skipped: usize,
+}
+
+/// Works out the values a point's ID was derived from. Of the datasource's primary keys, the one
+/// that reproduces the point's legacy ID is preferred, otherwise the first whose columns are all in
+/// the payload is used.
+fn key_values_for_point(
+    hashing_salt: &str,
+    point_id: &str,
+    payload: &Map<String, Value>,
+    primary_keys: &[Vec<String>],
+) -> Option<Vec<Value>> {
+    let candidates: Vec<Vec<Value>> = primary_keys
+        .iter()
+        .filter_map(|primary_key| {
+            primary_key
+                .iter()
+                .map(|column| payload.get(column).cloned())
+                .collect::<Option<Vec<Value>>>()
+        })
+        .collect();
+    candidates
+        .iter()
+        .find(|values| {
+            serde_json::to_string(values)
+                .is_ok_and(|json| hash_string_to_uuid(hashing_salt, &json) == point_id)
+        })
+        .or(candidates.first())
+        .cloned()
+}
+
+/// Re-keys the points of a datasource's live collection from the legacy `DefaultHasher` IDs to
+/// `POINT_ID_HASH_VERSION`, keeping their vectors and payloads. Points written by append syncs or
+/// chunked rows are not keyed on a primary key and are left alone. Legacy points whose row has
+/// already been written under its new ID are only removed. Safe to run again if it is interrupted,
+/// points that have already been moved are recognised by their hash version.
+pub async fn migrate_point_ids(
+    mongo: &Database,
+    datasource_id: &str,
+) -> anyhow::Result<PointIdMigrationReport> {
+    let datasource = get_datasource(mongo, datasource_id)
+        .await?
+        .ok_or(anyhow!("Datasource: {} does not exist", datasource_id))?;
+    let hashing_salt = GLOBAL_DATA.read().await.hashing_salt.clone();
+    let mut primary_keys: Vec<Vec<String>> = datasource
+        .stream_config
+        .iter()
+        .flat_map(|stream_config| stream_config.values())
+        .map(|stream_config| stream_config.primaryKey.clone())
+        .filter(|primary_key| !primary_key.is_empty())
+        .collect();
+    primary_keys.sort();
+    primary_keys.dedup();
+
+    let vector_database_client = check_byo_vector_database(datasource.clone(), mongo)
+        .await
+        .unwrap_or(default_vector_db_client().await);
+    let vector_database_client = vector_database_client.read().await;
+    let search_request = search_request_for(&datasource, live_collection(&datasource));
+
+    let mut report = PointIdMigrationReport::default();
+    let mut offset = None;
+    loop {
+        let (points, next_offset) = vector_database_client
+            .scroll_page(search_request.clone(), offset, MIGRATION_PAGE_SIZE)
+            .await?;
+        let mut old_ids = vec![];
+        for point in points {
+            let payload: Map<String, Value> =
+                point.payload.unwrap_or_default().into_iter().collect();
+            let is_current = payload
+                .get(HASH_VERSION_PAYLOAD_FIELD)
+                .and_then(|version| version.as_u64())
+                .is_some_and(|version| version >= POINT_ID_HASH_VERSION);
+            let is_keyed = payload
+                .get("index")
+                .and_then(|index| index.as_str())
+                .is_some_and(|index| index == point.id);
+            if is_current || !is_keyed {
+                report.unchanged += 1;
+                continue;
+            }
+            let (Some(key_values), Some(vector)) = (
+                key_values_for_point(&hashing_salt, &point.id, &payload, &primary_keys),
+                point.vector,
+            ) else {
+                report.skipped += 1;
+                continue;
+            };
+            let new_id = point_id_for_key(&hashing_salt, &key_values);
+            // Rows are written under their new ID while this runs. Holding the point's lock from
+            // the check to the write keeps the legacy point from overwriting one written since
+            let _point_lock = lock_point(&new_id).await;
+            if vector_database_client
+                .get_point(search_request.clone(), new_id.clone(), false)
+                .await?
+                .is_some()
+            {
+                report.superseded += 1;
+                old_ids.push(point.id);
+                continue;
+            }
+            let mut payload: HashMap<String, Value> = payload.into_iter().collect();
+            payload.insert("index".to_string(), Value::String(new_id.clone()));
+            payload.insert(
+                HASH_VERSION_PAYLOAD_FIELD.to_string(),
+                Value::from(POINT_ID_HASH_VERSION),
+            );
+            // Written before the old point is removed so nothing is lost if this fails
+            vector_database_client
+                .insert_point(
+                    search_request.clone(),
+                    Point::new(Some(Value::String(new_id)), vector, Some(payload)),
+                )
+                .await?;
+            report.migrated += 1;
+            old_ids.push(point.id);
+        }
+        if !old_ids.is_empty() {
+            vector_database_client
+                .delete_points_by_ids(search_request.clone(), old_ids)
+                .await?;
+        }
+        match next_offset {
+            Some(next_offset) => offset = Some(next_offset),
+            None => break,
+        }
+    }
+    set_point_id_hash_version(mongo, datasource_id, POINT_ID_HASH_VERSION).await?;
+    log::info!(
+        "Migrated point IDs for datasource: {}. {:?}",
+        datasource_id,
+        report
+    );
+    Ok(report)
+}
+
+/// Datasources whose migration has been started by this process
+static STARTED_MIGRATIONS: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Starts migrating the datasource's points in the background unless they are already on
+/// `POINT_ID_HASH_VERSION` or the migration has been started before. Rows written while it runs get
+/// their new ID straight away, and the legacy point they replace is removed when the migration
+/// reaches it. A failed migration is not restarted by later rows, it can be run again through the
+/// migration API or is started again after a restart.
+pub fn migrate_point_ids_in_background(mongo: Arc<RwLock<Database>>, datasource: &DataSources) {
+    if datasource
+        .point_id_hash_version
+        .is_some_and(|version| version >= POINT_ID_HASH_VERSION)
+    {
+        return;
+    }
+    let datasource_id = datasource.id.to_string();
+    if !STARTED_MIGRATIONS
+        .lock()
+        .unwrap()
+        .insert(datasource_id.clone())
+    {
+        return;
+    }
+    tokio::spawn(async move {
+        let mongo = mongo.read().await;
+        if let Err(e) = migrate_point_ids(&mongo, &datasource_id).await {
+            log::error!(
+                "Could not migrate point IDs for datasource: {}. Error: {}",
+                datasource_id,
+                e
+            );
+        }
+    });
+}
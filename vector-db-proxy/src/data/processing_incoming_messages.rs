@@ -1,23 +1,60 @@
-use crate::adaptors::mongo::models::{DataSources, Model, UnstructuredChunkingConfig};
+use crate::adaptors::mongo::error::is_transient;
+use crate::adaptors::mongo::models::{
+    DataSources, EmbeddingOverflowPolicy, Model, SyncMode, UnstructuredChunkingConfig,
+};
 use crate::adaptors::mongo::queries::{
-    get_model_and_embedding_key, increment_by_one, set_datasource_state,
+    get_document, get_model, get_model_and_embedding_key, set_datasource_state,
+};
+use crate::data::cdc::{apply_cdc_delete, is_deleted_record};
+use crate::data::checkpoints::{record_retrying, record_settled, take_pending_states};
+use crate::data::chunking::{chunk_document, uses_native_chunker};
+use crate::data::content_hash::{
+    content_hash, reuse_unchanged_embedding, CONTENT_HASH_PAYLOAD_FIELD,
+};
+use crate::data::cursor::{cursor_value, is_stale_update, lock_point, CURSOR_PAYLOAD_FIELD};
+use crate::data::documents::{
+    document_file_name, file_checksum, is_unchanged, new_document, point_ids, register_document,
+    IngestedPoints,
+};
+use crate::data::helpers::{
+    part_point_id, point_id_for_key, point_id_for_record, DOCUMENT_ID_PAYLOAD_FIELD,
+    HASH_VERSION_PAYLOAD_FIELD, PART_COUNT_PAYLOAD_FIELD, POINT_ID_HASH_VERSION,
+};
+use crate::data::point_id_migration::migrate_point_ids_in_background;
+use crate::data::record_counts::{add_to_record_count, increment_record_count};
+use crate::data::structured_files::{
+    primary_key_values, read_rows, reads_row_by_row, row_identity, row_payload, row_text,
+    StructuredRow,
+};
+use crate::data::sync_modes::{
+    dedupes_on_primary_key, live_collection, search_request_for, staging_collection,
 };
-use crate::data::helpers::hash_string_to_uuid;
-use crate::data::unstructuredio::apis::chunk_text;
 use crate::embeddings::helpers::clean_text;
-use crate::embeddings::utils::{embed_bulk_insert_unstructured_response, embed_text};
+use crate::embeddings::utils::{
+    embed_bulk_insert_unstructured_response, embed_text_with_overflow_policy, split_text_points,
+    TextEmbedding,
+};
 use crate::init::env_variables::GLOBAL_DATA;
+use crate::init::shutdown::Shutdown;
+use crate::messages::envelope::{IngestionEnvelope, SourceKind};
+use crate::messages::models::AckHandle;
+use crate::messages::scheduler::{FairScheduler, IngestionTask};
+use crate::utils::file_operations;
+use crate::utils::webhook::send_webapp_embed_ready;
 use crate::vector_databases::helpers::check_byo_vector_database;
-use crate::vector_databases::models::{Point, SearchRequest, SearchType, VectorDatabaseStatus, Region};
-use crate::vector_databases::vector_database::default_vector_db_client;
+use crate::vector_databases::models::{
+    Point, Region, SearchRequest, SearchType, VectorDatabaseStatus,
+};
+use crate::vector_databases::vector_database::{default_vector_db_client, VectorDatabase};
 use anyhow::anyhow;
-use crossbeam::channel::Receiver;
 use mongodb::Database;
 use serde_json::{to_vec, Value};
 use std::collections::HashMap;
-use std::io::Cursor;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{RwLock, Semaphore};
+use tokio::task::AbortHandle;
+use tokio::time::timeout;
 
 pub async fn embed_text_construct_point(
     mongo_conn: Arc<RwLock<Database>>,
@@ -27,7 +64,7 @@ pub async fn embed_text_construct_point(
     embedding_model: Model,
     chunking_strategy: Option<UnstructuredChunkingConfig>,
     search_type: SearchType,
-) -> anyhow::Result<Option<Point>, anyhow::Error> {
+) -> anyhow::Result<Vec<Point>, anyhow::Error> {
     if !data.is_empty() {
         if let Some(ds) = datasource {
             // Convert embedding_field_name to lowercase
@@ -39,25 +76,26 @@ pub async fn embed_text_construct_point(
                 );
 
                 if let Some(chunking_config) = chunking_strategy.clone() {
-                    let global_data = GLOBAL_DATA.read().await.clone();
-                    let unstructuredio_url = global_data.unstructuredio_url;
-                    let unstructuredio_api_key =
-                        Some(global_data.unstructuredio_api_key).filter(|s| !s.is_empty());
-                    //    write value to buffer
-                    let buffer =
-                        Cursor::new(to_vec(&Value::String(clean_text(value.to_string())))?);
-                    let handle = tokio::task::spawn_blocking(move || {
-                        let response = chunk_text(
-                            unstructuredio_url,
-                            unstructuredio_api_key,
-                            buffer,
-                            None,
-                            chunking_strategy,
-                            chunking_config.file_type,
-                        );
-                        response
-                    });
-                    match handle.await? {
+                    let file_type = chunking_config.file_type;
+                    // The native chunker needs the line breaks that cleaning strips out
+                    let buffer = if uses_native_chunker(file_type, Some(&chunking_config)) {
+                        match &value {
+                            Value::String(text) => text.clone().into_bytes(),
+                            _ => value.to_string().into_bytes(),
+                        }
+                    } else {
+                        to_vec(&Value::String(clean_text(value.to_string())))?
+                    };
+                    match chunk_document(
+                        buffer,
+                        None,
+                        file_type,
+                        chunking_strategy,
+                        &embedding_model,
+                        Some(&ds.id.to_string()),
+                    )
+                    .await
+                    {
                         Ok(documents) => {
                             embed_bulk_insert_unstructured_response(
                                 documents,
@@ -68,26 +106,31 @@ pub async fn embed_text_construct_point(
                                 search_type,
                             )
                             .await;
-                            return Ok(None);
+                            return Ok(vec![]);
                         }
                         Err(e) => {
-                            log::error!(
-                                "Error embedding response from unstructuredIO. Error: {}",
-                                e
-                            );
+                            log::error!("Error chunking text. Error: {}", e);
                         }
                     }
                 }
                 // Embedding data
-                let embedding_vec =
-                    embed_text(vec![&clean_text(value.to_string())], &embedding_model).await?;
-                // Construct a Point to insert into the vector DB
-                if !embedding_vec.is_empty() {
-                    if let Some(vector) = embedding_vec.into_iter().next() {
-                        let index = payload.get("index").map_or(None, |id| Some(id.to_owned()));
-                        let point = Point::new(index, vector, Some(payload));
-                        return Ok(Some(point));
-                    }
+                let embeddings = embed_text_with_overflow_policy(
+                    vec![clean_text(value.to_string())],
+                    &embedding_model,
+                    ds.overflow_policy.unwrap_or_default(),
+                    Some(&ds.id.to_string()),
+                )
+                .await?;
+                // Construct the Points to insert into the vector DB
+                if let Some(parts) = embeddings.into_iter().next() {
+                    let index = payload.get("index").cloned();
+                    return match <[TextEmbedding; 1]>::try_from(parts) {
+                        Ok([part]) => Ok(vec![Point::new(index, part.vector, Some(payload))]),
+                        Err(parts) => {
+                            let hashing_salt = GLOBAL_DATA.read().await.hashing_salt.clone();
+                            Ok(split_text_points(&hashing_salt, index, payload, parts))
+                        }
+                    };
                 }
             }
         } else {
@@ -99,6 +142,50 @@ pub async fn embed_text_construct_point(
     Err(anyhow!("Row is empty"))
 }
 
+/// Deletes the parts of a previously split row beyond the `part_count` it is now written as.
+/// Only rows keyed on their primary key replace their old points, so only those are checked.
+async fn remove_stale_parts(
+    vector_database_client: &dyn VectorDatabase,
+    search_request: &SearchRequest,
+    first_part: &Point,
+    part_count: usize,
+) {
+    let Some(Value::String(point_id)) = first_part
+        .payload
+        .as_ref()
+        .and_then(|payload| payload.get("index"))
+    else {
+        return;
+    };
+    let previous_part_count = match vector_database_client
+        .get_point(search_request.clone(), point_id.clone(), false)
+        .await
+    {
+        Ok(Some(point)) => point
+            .payload
+            .and_then(|payload| payload.get(PART_COUNT_PAYLOAD_FIELD)?.as_u64())
+            .unwrap_or(1) as usize,
+        _ => return,
+    };
+    if previous_part_count <= part_count {
+        return;
+    }
+    let hashing_salt = GLOBAL_DATA.read().await.hashing_salt.clone();
+    let stale_ids = (part_count..previous_part_count)
+        .map(|part| part_point_id(&hashing_salt, point_id, part))
+        .collect();
+    if let Err(e) = vector_database_client
+        .delete_points_by_ids(search_request.clone(), stale_ids)
+        .await
+    {
+        log::warn!(
+            "Could not remove old parts of point: {}. Error: {}",
+            point_id,
+            e
+        );
+    }
+}
+
 async fn handle_embedding(
     mongo_connection: Arc<RwLock<Database>>,
     //mut vector_database_client: Arc<RwLock<dyn VectorDatabase>>,
@@ -107,7 +194,7 @@ async fn handle_embedding(
     datasource: DataSources,
     embedding_model: Model,
     chunking_strategy: Option<UnstructuredChunkingConfig>,
-) {
+) -> bool {
     let mongo_connection_clone = Arc::clone(&mongo_connection);
     let metadata = metadata.clone();
     let field_path = "recordCount.failure";
@@ -140,139 +227,808 @@ async fn handle_embedding(
     )
     .await
     {
-        Ok(point) => match point {
-            Some(p) => {
-                vector_database_client.read().await.display_config().await;
-                match vector_database_client
-                    .read()
-                    .await
-                    .insert_point(search_request, p)
-                    .await
-                {
-                    Ok(result) => match result {
-                        VectorDatabaseStatus::Ok => (),
-                        _ => {
-                            log::warn!("An error occurred while inserting into vector database");
-                            increment_by_one(&mongo, &datasource.id.to_string(), field_path)
-                                .await
-                                .unwrap();
-                        }
-                    },
-                    Err(e) => {
-                        log::warn!(
-                            "An error occurred while inserting into vector database. Error: {}",
-                            e
-                        );
-                        increment_by_one(&mongo, &datasource.id.to_string(), field_path)
-                            .await
-                            .unwrap();
+        Ok(mut points) if points.len() == 1 => {
+            let p = points.remove(0);
+            let vector_database_client = vector_database_client.read().await;
+            vector_database_client.display_config().await;
+            // A row that used to be split may now fit in a single point, its other parts go
+            if datasource.overflow_policy == Some(EmbeddingOverflowPolicy::Split) {
+                remove_stale_parts(&*vector_database_client, &search_request, &p, 1).await;
+            }
+            match vector_database_client.insert_point(search_request, p).await {
+                Ok(result) => match result {
+                    VectorDatabaseStatus::Ok => true,
+                    _ => {
+                        log::warn!("An error occurred while inserting into vector database");
+                        increment_record_count(&datasource.id.to_string(), field_path);
+                        false
                     }
+                },
+                Err(e) => {
+                    log::warn!(
+                        "An error occurred while inserting into vector database. Error: {}",
+                        e
+                    );
+                    increment_record_count(&datasource.id.to_string(), field_path);
+                    false
                 }
             }
-            None => (),
-        },
-        Err(e) => {
-            increment_by_one(&mongo, &datasource.id.to_string(), field_path)
+        }
+        Ok(points) => {
+            let Some(first_part) = points.first() else {
+                return true;
+            };
+            let vector_database_client = vector_database_client.read().await;
+            remove_stale_parts(
+                &*vector_database_client,
+                &search_request,
+                first_part,
+                points.len(),
+            )
+            .await;
+            match vector_database_client
+                .bulk_insert_points(search_request, points)
                 .await
-                .unwrap();
+            {
+                Ok(VectorDatabaseStatus::Ok) => true,
+                Ok(_) => {
+                    log::warn!("An error occurred while inserting into vector database");
+                    increment_record_count(&datasource.id.to_string(), field_path);
+                    false
+                }
+                Err(e) => {
+                    log::warn!(
+                        "An error occurred while inserting into vector database. Error: {}",
+                        e
+                    );
+                    increment_record_count(&datasource.id.to_string(), field_path);
+                    false
+                }
+            }
+        }
+        Err(e) => {
+            increment_record_count(&datasource.id.to_string(), field_path);
             log::error!(
                 "An error occurred while upserting  point structs to vector database: {}",
                 e
             );
+            false
+        }
+    }
+}
+
+/// Whether a task's message is acked or returned to the queue once the task has finished
+enum TaskOutcome {
+    Done,
+    /// Failed on an error that is likely to clear, such as MongoDB being unreachable
+    Retry,
+}
+
+/// Ack handle of a running task and, once it has been spawned, the handle to abort it with
+type InFlightTask = (AckHandle, Option<AbortHandle>);
+
+/// Tasks currently being processed, keyed by an ID assigned when they are started. Each entry holds
+/// what is needed to return the message to the queue if the task is still running at shutdown.
+#[derive(Clone, Default)]
+struct InFlightTasks {
+    tasks: Arc<std::sync::Mutex<HashMap<u64, InFlightTask>>>,
+}
+
+impl InFlightTasks {
+    fn start(&self, id: u64, ack: AckHandle) {
+        self.tasks.lock().unwrap().insert(id, (ack, None));
+    }
+
+    fn set_abort_handle(&self, id: u64, abort_handle: AbortHandle) {
+        if let Some((_, handle)) = self.tasks.lock().unwrap().get_mut(&id) {
+            *handle = Some(abort_handle);
         }
     }
-    drop(vector_database_client)
+
+    fn finish(&self, id: u64) -> Option<AckHandle> {
+        self.tasks.lock().unwrap().remove(&id).map(|(ack, _)| ack)
+    }
+
+    /// Aborts every task that is still running and returns their ack handles
+    fn abort_all(&self) -> Vec<AckHandle> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(_, (ack, abort_handle))| {
+                if let Some(abort_handle) = abort_handle {
+                    abort_handle.abort();
+                }
+                ack
+            })
+            .collect()
+    }
 }
 
+/// Pulls tasks off the scheduler and processes them on the shared runtime. At most
+/// `number_of_workers` tasks are in flight at once; once they are all busy the scheduler fills up
+/// and the consumers stop pulling messages until a worker frees up.
+///
+/// On shutdown no new tasks are started and anything still queued is returned to the message
+/// queue. Tasks already running get `SHUTDOWN_GRACE_PERIOD_SECS` to finish before they are aborted
+/// and their messages returned to the queue as well.
 pub async fn process_incoming_messages(
-    receiver: Receiver<(DataSources, Option<String>, String)>,
+    scheduler: Arc<FairScheduler>,
     //vector_database_client: Arc<RwLock<dyn VectorDatabase>>,
     mongo_conn: Arc<RwLock<Database>>,
+    number_of_workers: usize,
+    shutdown: Shutdown,
 ) {
-    let mongo_connection = Arc::clone(&mongo_conn);
-    let receiver_clone = receiver.clone();
-    let global_data = GLOBAL_DATA.read().await;
-    while let Ok(msg) = receiver_clone.recv() {
-        let (datasource, stream_config_key, message) = msg;
-        let datasource_clone = datasource.clone();
-        match serde_json::from_str(message.as_str()) {
-            Ok::<Value, _>(message_data) => {
-                let mongo = mongo_connection.read().await;
-                match get_model_and_embedding_key(&mongo, datasource.clone(), stream_config_key)
+    let number_of_workers = number_of_workers.max(1);
+    let worker_pool = Arc::new(Semaphore::new(number_of_workers));
+    let in_flight = InFlightTasks::default();
+    let mut next_task_id: u64 = 0;
+    loop {
+        // Wait for a free worker before taking the next task off the scheduler
+        let permit = tokio::select! {
+            permit = Arc::clone(&worker_pool).acquire_owned() => permit.ok(),
+            _ = shutdown.wait() => None,
+        };
+        let Some(permit) = permit else { break };
+        let task = tokio::select! {
+            task = scheduler.pop() => task,
+            _ = shutdown.wait() => None,
+        };
+        let Some(IngestionTask {
+            datasource,
+            envelope,
+            ack,
+            sequence,
+        }) = task
+        else {
+            break;
+        };
+        let task_id = next_task_id;
+        next_task_id += 1;
+        in_flight.start(task_id, ack);
+        let mongo_connection = Arc::clone(&mongo_conn);
+        let datasource_id = datasource.id.to_string();
+        let work = tokio::spawn(async move {
+            match envelope.source {
+                SourceKind::File => {
+                    process_file_upload(datasource, envelope, mongo_connection).await
+                }
+                SourceKind::StreamRecord => {
+                    process_incoming_message(datasource, envelope, mongo_connection).await
+                }
+            }
+        });
+        in_flight.set_abort_handle(task_id, work.abort_handle());
+        let in_flight_for_task = in_flight.clone();
+        let mongo_connection = Arc::clone(&mongo_conn);
+        // Awaited separately from the work so a task that panics still has its message returned
+        // to the queue rather than left unacked until shutdown
+        tokio::spawn(async move {
+            let outcome = work.await;
+            if let Some(sequence) = sequence {
+                match outcome {
+                    // The record has been written, so any checkpoint waiting on it can be persisted
+                    Ok(TaskOutcome::Done) => {
+                        let mongo = mongo_connection.read().await;
+                        record_settled(&mongo, &datasource_id, sequence).await;
+                    }
+                    // Returned to the queue, so checkpoints keep waiting for its redelivery
+                    _ => record_retrying(&datasource_id, sequence),
+                }
+            }
+            // Tasks aborted at shutdown have already had their ack handle taken
+            if let Some(ack) = in_flight_for_task.finish(task_id) {
+                match outcome {
+                    Ok(TaskOutcome::Done) => ack.ack().await,
+                    Ok(TaskOutcome::Retry) => ack.nack().await,
+                    Err(e) => {
+                        log::error!(
+                            "Ingestion task for datasource: {} failed, returning it to the \
+                            queue. Error: {}",
+                            datasource_id,
+                            e
+                        );
+                        ack.nack().await
+                    }
+                }
+            }
+            drop(permit);
+        });
+    }
+
+    for task in scheduler.close() {
+        task.ack.nack().await;
+    }
+    let grace_period = Duration::from_secs(GLOBAL_DATA.read().await.shutdown_grace_period_secs);
+    log::info!(
+        "Waiting up to {:?} for in-flight ingestion tasks to finish",
+        grace_period
+    );
+    // Every worker permit being free again means every in-flight task has finished
+    let all_workers_idle = worker_pool.acquire_many(number_of_workers as u32);
+    if timeout(grace_period, all_workers_idle).await.is_err() {
+        let unfinished = in_flight.abort_all();
+        log::warn!(
+            "{} ingestion tasks did not finish in time, returning them to the queue",
+            unfinished.len()
+        );
+        for ack in unfinished {
+            ack.nack().await;
+        }
+    }
+    // Checkpoints still waiting on records that were returned to the queue are redelivered with them
+    for ack in take_pending_states() {
+        ack.nack().await;
+    }
+}
+
+/// Rows of a structured file embedded and written per request
+const ROW_BATCH_SIZE: usize = 100;
+
+/// Stores every row of an uploaded CSV, spreadsheet or JSON Lines file as its own point, with its
+/// columns as payload fields. Point IDs come from the configured primary key, or from the whole
+/// row without one, so uploading the same file again overwrites its points. Rows missing from the
+/// new version of a file have their points removed when the document is registered, as the
+/// returned IDs replace the ones the document had.
+async fn embed_structured_rows(
+    rows: Vec<StructuredRow>,
+    file_path: &str,
+    datasource: &DataSources,
+    mongo_client: Arc<RwLock<Database>>,
+    embedding_model: &Model,
+) -> IngestedPoints {
+    let datasource_id = datasource.id.to_string();
+    let mut ingested = IngestedPoints {
+        point_ids: vec![],
+        complete: true,
+    };
+    let config = datasource.structured_config.as_ref();
+    let primary_key = config.map_or(&[][..], |config| config.primary_key.as_slice());
+    let hashing_salt = GLOBAL_DATA.read().await.hashing_salt.clone();
+    let vector_database_client = {
+        let mongo = mongo_client.read().await;
+        check_byo_vector_database(datasource.clone(), &mongo)
+            .await
+            .unwrap_or(default_vector_db_client().await)
+    };
+    let search_request = search_request_for(datasource, live_collection(datasource));
+    for batch in rows.chunks(ROW_BATCH_SIZE) {
+        let mut payloads = vec![];
+        let mut texts = vec![];
+        for row in batch {
+            let text = clean_text(row_text(row, config));
+            if text.trim().is_empty() {
+                log::debug!(
+                    "Skipping row {} of file: {}, it has no text to embed",
+                    row.row_number,
+                    file_path
+                );
+                increment_record_count(&datasource_id, "recordCount.skipped");
+                continue;
+            }
+            let point_id = match primary_key_values(row, primary_key) {
+                Some(values) if !primary_key.is_empty() => point_id_for_key(&hashing_salt, &values),
+                Some(_) => point_id_for_record(&hashing_salt, &row_identity(row)),
+                None => {
+                    log::warn!(
+                        "Row {} of file: {} is missing primary key columns: {:?}. Deriving its \
+                        ID from the whole row",
+                        row.row_number,
+                        file_path,
+                        primary_key
+                    );
+                    point_id_for_record(&hashing_salt, &row_identity(row))
+                }
+            };
+            let mut payload = row_payload(row);
+            payload.insert("page_content".to_string(), Value::String(text.clone()));
+            payload.insert("index".to_string(), Value::String(point_id));
+            payload.insert(
+                HASH_VERSION_PAYLOAD_FIELD.to_string(),
+                Value::from(POINT_ID_HASH_VERSION),
+            );
+            payload.insert(
+                "metadata.filename".to_string(),
+                Value::String(file_path.to_string()),
+            );
+            payloads.push(payload);
+            texts.push(text);
+        }
+        if texts.is_empty() {
+            continue;
+        }
+        let row_count = texts.len() as i64;
+        let embeddings = match embed_text_with_overflow_policy(
+            texts,
+            embedding_model,
+            datasource.overflow_policy.unwrap_or_default(),
+            Some(&datasource_id),
+        )
+        .await
+        {
+            Ok(embeddings) => embeddings,
+            Err(e) => {
+                log::error!("An error occurred while embedding rows. Error: {}", e);
+                add_to_record_count(&datasource_id, "recordCount.failure", row_count);
+                ingested.complete = false;
+                continue;
+            }
+        };
+        let mut points = vec![];
+        for (payload, parts) in payloads.into_iter().zip(embeddings) {
+            let index = payload.get("index").cloned();
+            if let [part] = parts.as_slice() {
+                points.push(Point::new(index, part.vector.to_vec(), Some(payload)));
+            } else {
+                points.extend(split_text_points(&hashing_salt, index, payload, parts));
+            }
+        }
+        let batch_point_ids = point_ids(&points);
+        let vector_database_client = vector_database_client.read().await;
+        match vector_database_client
+            .bulk_insert_points(search_request.clone(), points)
+            .await
+        {
+            Ok(VectorDatabaseStatus::Ok) => {
+                add_to_record_count(&datasource_id, "recordCount.success", row_count);
+                ingested.point_ids.extend(batch_point_ids);
+            }
+            Ok(_) => {
+                log::warn!("An error occurred while inserting rows into vector database");
+                add_to_record_count(&datasource_id, "recordCount.failure", row_count);
+                ingested.complete = false;
+            }
+            Err(e) => {
+                log::warn!(
+                    "An error occurred while inserting rows into vector database. Error: {}",
+                    e
+                );
+                add_to_record_count(&datasource_id, "recordCount.failure", row_count);
+                ingested.complete = false;
+            }
+        }
+    }
+    ingested
+}
+
+async fn process_file_upload(
+    datasource: DataSources,
+    envelope: IngestionEnvelope,
+    mongo_client: Arc<RwLock<Database>>,
+) -> TaskOutcome {
+    let datasource_id = datasource.id.to_string();
+    let model_parameters = {
+        let mongodb_connection = mongo_client.read().await;
+        match get_model(&mongodb_connection, datasource_id.as_str()).await {
+            Ok(Some(model)) => model,
+            Err(e) if is_transient(&e) => {
+                log::warn!(
+                    "Could not look up embedding model for datasource: {}, returning file to \
+                    the queue. Error: {}",
+                    datasource_id,
+                    e
+                );
+                return TaskOutcome::Retry;
+            }
+            _ => {
+                log::error!(
+                    "There was no embedding model associated with datasource: {}",
+                    datasource_id
+                );
+                return TaskOutcome::Done;
+            }
+        }
+    };
+    if let Some(file_reference) = envelope.file {
+        let source_location = file_reference.location.clone();
+        match file_operations::read_file_from_source(
+            Some(file_reference.provider),
+            file_reference.location,
+        )
+        .await
+        {
+            Some((file_type, file, file_path)) => {
+                let checksum = file_checksum(&file);
+                let existing_document = {
+                    let mongo = mongo_client.read().await;
+                    match get_document(&mongo, &datasource_id, &source_location).await {
+                        Ok(document) => document,
+                        Err(e) => {
+                            log::warn!("Could not look up previous version of file. Error: {}", e);
+                            None
+                        }
+                    }
+                };
+                if is_unchanged(
+                    existing_document.as_ref(),
+                    &checksum,
+                    &model_parameters.model,
+                ) {
+                    log::info!(
+                        "File: {} has not changed since it was last ingested, skipping it",
+                        file_path
+                    );
+                    let _ = send_webapp_embed_ready(datasource_id.as_str())
+                        .await
+                        .map_err(|e| log::error!("{}", e));
+                    return TaskOutcome::Done;
+                }
+                let document = new_document(
+                    &datasource,
+                    document_file_name(&file_path),
+                    source_location,
+                    checksum,
+                    model_parameters.model.clone(),
+                );
+                // Chunks carry the ID the document is registered under, which a new version of the
+                // file keeps
+                let document_id = existing_document
+                    .as_ref()
+                    .map_or(document.id, |existing| existing.id)
+                    .to_hex();
+                // dynamically get user's chunking strategy of choice from the database
+                let chunking_strategy: Option<UnstructuredChunkingConfig> =
+                    datasource.clone().chunking_config;
+                let ingested = if reads_row_by_row(file_type, chunking_strategy.as_ref()) {
+                    match read_rows(&file, file_type) {
+                        Ok(rows) => {
+                            embed_structured_rows(
+                                rows,
+                                &file_path,
+                                &datasource,
+                                Arc::clone(&mongo_client),
+                                &model_parameters,
+                            )
+                            .await
+                        }
+                        Err(e) => {
+                            log::error!("Could not read rows of file. Error: {}", e);
+                            IngestedPoints::default()
+                        }
+                    }
+                } else {
+                    match chunk_document(
+                        file,
+                        Some(file_path),
+                        Some(file_type),
+                        chunking_strategy,
+                        &model_parameters,
+                        Some(datasource_id.as_str()),
+                    )
+                    .await
+                    {
+                        Ok(documents) => {
+                            let point_ids = embed_bulk_insert_unstructured_response(
+                                documents,
+                                datasource.clone(),
+                                mongo_client.clone(),
+                                model_parameters,
+                                Some(HashMap::from([(
+                                    DOCUMENT_ID_PAYLOAD_FIELD.to_string(),
+                                    Value::String(document_id),
+                                )])),
+                                SearchType::default(),
+                            )
+                            .await;
+                            IngestedPoints {
+                                complete: point_ids.is_some(),
+                                point_ids: point_ids.unwrap_or_default(),
+                            }
+                        }
+                        Err(e) => {
+                            log::error!("An error occurred while chunking file. Error : {}", e);
+                            IngestedPoints::default()
+                        }
+                    }
+                };
+                // Nothing was written, so whatever an earlier version of the file left stays
+                if !ingested.point_ids.is_empty() || ingested.complete {
+                    let mongo = mongo_client.read().await;
+                    if let Err(e) = register_document(
+                        &mongo,
+                        &datasource,
+                        existing_document,
+                        document,
+                        ingested,
+                    )
                     .await
-                {
-                    Ok(embedding_config) => {
-                        if let Some(embedding_model) = embedding_config.model {
-                            // extract metadata from message if message is coming from pubsub
-                            if let Value::Object(mut data_obj) = message_data {
-                                // This is to account for airbyte sending the data in the _airbyte_data object when the destination is PubSub
-                                if let Some(is_pubsub) = data_obj.get("_airbyte_data") {
-                                    if let Some(pubsub_is_obj) = is_pubsub.as_object() {
-                                        data_obj = pubsub_is_obj.to_owned();
+                    {
+                        log::error!("Could not register document. Error: {}", e);
+                    }
+                }
+                let _ = send_webapp_embed_ready(datasource_id.as_str())
+                    .await
+                    .map_err(|e| log::error!("{}", e));
+            }
+            None => {
+                log::warn!("Could not read file from source...source returned NONE!")
+            }
+        }
+    }
+    TaskOutcome::Done
+}
+
+async fn process_incoming_message(
+    datasource: DataSources,
+    envelope: IngestionEnvelope,
+    mongo_connection: Arc<RwLock<Database>>,
+) -> TaskOutcome {
+    let global_data = GLOBAL_DATA.read().await.clone();
+    let datasource_clone = datasource.clone();
+    match envelope.record {
+        Some(message_data) => {
+            let mongo = mongo_connection.read().await;
+            match get_model_and_embedding_key(&mongo, datasource.clone(), envelope.stream_key).await
+            {
+                Ok(embedding_config) => {
+                    if let Some(embedding_model) = embedding_config.model {
+                        // extract metadata from message if message is coming from pubsub
+                        if let Value::Object(data_obj) = message_data {
+                            let mut metadata = HashMap::from_iter(data_obj);
+                            let sync_mode = embedding_config.sync_mode.clone();
+                            // If we find a primary key associated with the datasource, use
+                            // as vector index so that we do not create duplicates. Append syncs
+                            // keep every version of a row so they skip this
+                            let mut point_id = None;
+                            if dedupes_on_primary_key(sync_mode.as_ref()) {
+                                migrate_point_ids_in_background(
+                                    Arc::clone(&mongo_connection),
+                                    &datasource,
+                                );
+                            }
+                            if let Some(list_of_primary_keys) = embedding_config
+                                .primary_key
+                                .filter(|primary_key| !primary_key.is_empty())
+                            {
+                                let list_of_primary_key_values: Option<Vec<Value>> =
+                                    list_of_primary_keys
+                                        .iter()
+                                        .map(|k| metadata.get(k).cloned())
+                                        .collect();
+                                let hash = match list_of_primary_key_values {
+                                    Some(values) => {
+                                        point_id_for_key(global_data.hashing_salt.as_str(), &values)
+                                    }
+                                    None => {
+                                        log::warn!(
+                                            "Row in datasource: {} is missing primary key \
+                                            columns: {:?}. Deriving its ID from the whole row",
+                                            datasource.id,
+                                            list_of_primary_keys
+                                        );
+                                        point_id_for_record(
+                                            global_data.hashing_salt.as_str(),
+                                            &metadata,
+                                        )
                                     }
+                                };
+                                if dedupes_on_primary_key(sync_mode.as_ref()) {
+                                    metadata
+                                        .insert(String::from("index"), Value::String(hash.clone()));
+                                    metadata.insert(
+                                        HASH_VERSION_PAYLOAD_FIELD.to_string(),
+                                        Value::from(POINT_ID_HASH_VERSION),
+                                    );
                                 }
+                                point_id = Some(hash);
+                            };
 
-                                let mut metadata = HashMap::from_iter(data_obj);
-                                // If we find a primary key associated with the datasource, use
-                                // as vector index so that we do not create duplicates
-                                if let Some(list_of_primary_keys) = embedding_config.primary_key {
-                                    let list_of_primary_key_values: Vec<Value> =
-                                        list_of_primary_keys
-                                            .iter()
-                                            .map(|k| metadata.get(k).cloned().unwrap())
-                                            .collect();
-                                    if let Ok(json_string) =
-                                        serde_json::to_string(&list_of_primary_key_values)
-                                    {
-                                        let json_string_hash = hash_string_to_uuid(
-                                            global_data.hashing_salt.as_str(),
-                                            json_string.as_str(),
+                            // Rows deleted at the source are removed from the vector database
+                            // rather than embedded
+                            if is_deleted_record(&metadata) {
+                                if dedupes_on_primary_key(sync_mode.as_ref()) {
+                                    apply_cdc_delete(&mongo, &datasource, point_id).await;
+                                } else {
+                                    // Append syncs write every version of a row under a random ID,
+                                    // so there is no point to remove
+                                    log::debug!(
+                                        "Skipping deleted record for append sync of datasource: {}",
+                                        datasource.id
+                                    );
+                                    increment_record_count(
+                                        &datasource.id.to_string(),
+                                        "recordCount.skipped",
+                                    );
+                                }
+                                return TaskOutcome::Done;
+                            }
+
+                            let live_search_request =
+                                search_request_for(&datasource, live_collection(&datasource));
+                            let mut datasource = datasource;
+                            // Overwrite syncs are written to a staging collection which replaces the
+                            // live one once the sync completes
+                            if let Some(SyncMode::FullRefreshOverwrite) = sync_mode {
+                                let vector_database_client =
+                                    check_byo_vector_database(datasource.clone(), &mongo)
+                                        .await
+                                        .unwrap_or(default_vector_db_client().await);
+                                let vector_database_client = vector_database_client.read().await;
+                                match staging_collection(
+                                    &mongo,
+                                    &*vector_database_client,
+                                    &datasource,
+                                    embedding_model.embeddingLength as usize,
+                                )
+                                .await
+                                {
+                                    Ok(Some(collection)) => {
+                                        datasource.collection_name = Some(collection)
+                                    }
+                                    Ok(None) => {}
+                                    Err(e) => {
+                                        log::error!(
+                                            "Could not stage overwrite sync for datasource: \
+                                            {}. Error: {}",
+                                            datasource.id,
+                                            e
                                         );
-                                        metadata.insert(
-                                            String::from("index"),
-                                            Value::String(json_string_hash),
+                                        increment_record_count(
+                                            &datasource.id.to_string(),
+                                            "recordCount.failure",
                                         );
+                                        return TaskOutcome::Done;
                                     }
-                                };
+                                }
+                            }
 
-                                if let Some(embedding_field_name) = embedding_config.embedding_key {
-                                    let mongo_connection_clone = Arc::clone(&mongo_connection);
-                                    let embed_text_worker = tokio::spawn(async move {
-                                        let _ = handle_embedding(
-                                            mongo_connection_clone,
-                                            metadata,
-                                            embedding_field_name,
-                                            datasource.clone(),
-                                            embedding_model,
-                                            embedding_config.chunking_strategy,
+                            // Rows are embedded by parallel workers, so an older version of a
+                            // row can arrive after a newer one. The newest cursor of each point is
+                            // claimed before the point is overwritten
+                            let cursor = embedding_config
+                                .cursor_field
+                                .as_deref()
+                                .and_then(|cursor_field| cursor_value(&metadata, cursor_field));
+                            // Held until the point is written, so neither an older version of
+                            // the row nor the point ID migration can overwrite it in between
+                            let mut _point_lock = None;
+                            if let Some(point_id) = point_id
+                                .as_deref()
+                                .filter(|_| dedupes_on_primary_key(sync_mode.as_ref()))
+                            {
+                                _point_lock = Some(lock_point(point_id).await);
+                            }
+                            if let Some(cursor) = cursor {
+                                metadata.insert(CURSOR_PAYLOAD_FIELD.to_string(), cursor.clone());
+                                if let Some(point_id) = point_id
+                                    .as_deref()
+                                    .filter(|_| dedupes_on_primary_key(sync_mode.as_ref()))
+                                {
+                                    let vector_database_client =
+                                        check_byo_vector_database(datasource.clone(), &mongo)
+                                            .await
+                                            .unwrap_or(default_vector_db_client().await);
+                                    let search_request = search_request_for(
+                                        &datasource,
+                                        live_collection(&datasource),
+                                    );
+                                    let is_stale = is_stale_update(
+                                        &mongo,
+                                        &datasource.id.to_string(),
+                                        &*vector_database_client.read().await,
+                                        search_request,
+                                        point_id,
+                                        &cursor,
+                                    )
+                                    .await;
+                                    if is_stale {
+                                        log::debug!(
+                                            "Skipping out of order update for point: {} in \
+                                            datasource: {}",
+                                            point_id,
+                                            datasource.id
+                                        );
+                                        increment_record_count(
+                                            &datasource.id.to_string(),
+                                            "recordCount.skipped",
+                                        );
+                                        return TaskOutcome::Done;
+                                    }
+                                }
+                            }
+
+                            if let Some(embedding_field_name) = embedding_config.embedding_key {
+                                // Rows whose text has not changed since they were last embedded
+                                // only get their payload updated. Chunked rows map to several
+                                // points so they are always re-embedded
+                                let text = metadata
+                                    .get(&embedding_field_name)
+                                    .map(|value| clean_text(value.to_string()));
+                                if let (Some(text), None) =
+                                    (text, embedding_config.chunking_strategy.as_ref())
+                                {
+                                    let content_hash = content_hash(&embedding_model.model, &text);
+                                    metadata.insert(
+                                        CONTENT_HASH_PAYLOAD_FIELD.to_string(),
+                                        Value::String(content_hash.clone()),
+                                    );
+                                    if let Some(point_id) = point_id
+                                        .as_deref()
+                                        .filter(|_| dedupes_on_primary_key(sync_mode.as_ref()))
+                                    {
+                                        let mut payload = metadata.clone();
+                                        payload.remove(&embedding_field_name);
+                                        payload.insert(
+                                            "page_content".to_string(),
+                                            Value::String(text),
+                                        );
+                                        let vector_database_client =
+                                            check_byo_vector_database(datasource.clone(), &mongo)
+                                                .await
+                                                .unwrap_or(default_vector_db_client().await);
+                                        let target_search_request = search_request_for(
+                                            &datasource,
+                                            live_collection(&datasource),
+                                        );
+                                        let reused = reuse_unchanged_embedding(
+                                            &*vector_database_client.read().await,
+                                            live_search_request,
+                                            target_search_request,
+                                            point_id,
+                                            &content_hash,
+                                            payload,
                                         )
                                         .await;
-                                    });
-                                    tokio::select! {
-                                        _ = embed_text_worker => {
-                                            set_datasource_state(
+                                        if reused {
+                                            increment_record_count(
+                                                &datasource.id.to_string(),
+                                                "recordCount.skipped",
+                                            );
+                                            if let Err(e) = set_datasource_state(
                                                 &mongo,
                                                 datasource_clone,
-                                                "ready"
-                                            ).await.unwrap();
-                                            log::info!("Finished embedding task")
+                                                "ready",
+                                            )
+                                            .await
+                                            {
+                                                log::error!("{}", e);
+                                            }
+                                            return TaskOutcome::Done;
                                         }
                                     }
                                 }
+                                let mongo_connection_clone = Arc::clone(&mongo_connection);
+                                let embed_text_worker = tokio::spawn(async move {
+                                    handle_embedding(
+                                        mongo_connection_clone,
+                                        metadata,
+                                        embedding_field_name,
+                                        datasource.clone(),
+                                        embedding_model,
+                                        embedding_config.chunking_strategy,
+                                    )
+                                    .await
+                                });
+                                // Failed rows are counted in `recordCount.failure` and leave the
+                                // status as it is
+                                if let Ok(true) = embed_text_worker.await {
+                                    if let Err(e) =
+                                        set_datasource_state(&mongo, datasource_clone, "ready")
+                                            .await
+                                    {
+                                        log::error!("{}", e);
+                                    }
+                                    log::info!("Finished embedding task")
+                                }
                             }
                         }
                     }
-                    Err(e) => {
-                        log::error!("An error occurred: {}", e);
-                    }
+                }
+                Err(e) if is_transient(&e) => {
+                    log::warn!("Returning record to the queue. Error: {}", e);
+                    return TaskOutcome::Retry;
+                }
+                Err(e) => {
+                    log::error!("An error occurred: {}", e);
                 }
             }
-            Err(e) => {
-                log::error!(
-                    "An error occurred while attempting to convert message to JSON: {}",
-                    e
-                );
-            }
+        }
+        None => {
+            log::error!(
+                "Message with trace ID: {} did not contain a record to embed",
+                envelope.trace_id
+            );
         }
     }
+    TaskOutcome::Done
 }
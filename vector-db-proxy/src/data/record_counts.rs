@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use mongodb::Database;
+use once_cell::sync::Lazy;
+use tokio::sync::RwLock;
+
+use crate::adaptors::mongo::queries::increment_by;
+
+/// Record counter increments that have not been written to Mongo yet, keyed by datasource ID and
+/// the `recordCount` field path. Buffering them keeps a write per row off the hot path and lets
+/// shutdown flush whatever is left in one go.
+static PENDING_RECORD_COUNTS: Lazy<Mutex<HashMap<(String, String), i64>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn increment_record_count(datasource_id: &str, field_path: &str) {
+    add_to_record_count(datasource_id, field_path, 1);
+}
+
+pub fn add_to_record_count(datasource_id: &str, field_path: &str, amount: i64) {
+    let mut pending = PENDING_RECORD_COUNTS.lock().unwrap();
+    *pending
+        .entry((datasource_id.to_string(), field_path.to_string()))
+        .or_insert(0) += amount;
+}
+
+/// Writes all buffered increments to Mongo. Increments that fail to write are put back so they
+/// are retried on the next flush.
+pub async fn flush_record_counts(db: &Database) {
+    let pending = mem::take(&mut *PENDING_RECORD_COUNTS.lock().unwrap());
+    for ((datasource_id, field_path), amount) in pending {
+        if let Err(e) = increment_by(db, &datasource_id, &field_path, amount).await {
+            log::error!(
+                "Could not flush {} for datasource: {}. Error: {}",
+                field_path,
+                datasource_id,
+                e
+            );
+            add_to_record_count(&datasource_id, &field_path, amount);
+        }
+    }
+}
+
+pub async fn flush_record_counts_periodically(
+    mongo_conn: Arc<RwLock<Database>>,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let mongo = mongo_conn.read().await;
+        flush_record_counts(&mongo).await;
+    }
+}
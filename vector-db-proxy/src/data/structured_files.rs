@@ -0,0 +1,355 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+
+use anyhow::{anyhow, Result};
+use calamine::{open_workbook_auto_from_rs, Data, Reader};
+use serde_json::Value;
+
+use crate::adaptors::mongo::models::{
+    ChunkingBackend, StructuredFileConfig, UnstructuredChunkingConfig,
+};
+use crate::data::models::FileType;
+
+/// Payload field holding the name of the worksheet a spreadsheet row came from
+pub const SHEET_PAYLOAD_FIELD: &str = "ac_sheet";
+/// Payload field holding a row's position in its file or sheet, counting from 1
+pub const ROW_NUMBER_PAYLOAD_FIELD: &str = "ac_row_number";
+/// Prefix given to columns named like a payload field the proxy sets itself
+pub const RESERVED_COLUMN_PREFIX: &str = "ac_column_";
+
+/// A row of a CSV, spreadsheet or JSON Lines file with its columns typed
+#[derive(Debug, Clone)]
+pub struct StructuredRow {
+    pub sheet: Option<String>,
+    pub row_number: usize,
+    /// Column names in the order the file has them
+    pub columns: Vec<String>,
+    pub fields: HashMap<String, Value>,
+}
+
+/// Files that are read row by row rather than chunked as a document
+pub fn is_structured_file(file_type: FileType) -> bool {
+    matches!(file_type, FileType::CSV | FileType::XLSX | FileType::JSONL)
+}
+
+/// Whether an uploaded file is embedded a row at a time. Datasources chunking with Unstructured IO
+/// have it read structured files as documents instead.
+pub fn reads_row_by_row(file_type: FileType, config: Option<&UnstructuredChunkingConfig>) -> bool {
+    is_structured_file(file_type)
+        && config.and_then(|config| config.backend.as_ref()) != Some(&ChunkingBackend::Unstructured)
+}
+
+/// Types a cell read as text. Numbers with leading zeros, such as zip codes and IDs, stay strings
+/// so they are not changed by the round trip.
+fn typed_value(cell: &str) -> Value {
+    let cell = cell.trim();
+    if cell.is_empty() {
+        return Value::Null;
+    }
+    if cell.eq_ignore_ascii_case("true") || cell.eq_ignore_ascii_case("false") {
+        return Value::Bool(cell.eq_ignore_ascii_case("true"));
+    }
+    let digits = cell.trim_start_matches(['-', '+']);
+    if digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.") {
+        return Value::String(cell.to_string());
+    }
+    if let Ok(integer) = cell.parse::<i64>() {
+        return Value::from(integer);
+    }
+    match cell.parse::<f64>() {
+        Ok(float) if float.is_finite() => Value::from(float),
+        _ => Value::String(cell.to_string()),
+    }
+}
+
+/// Spreadsheets store every number as a float, whole ones are turned back into integers
+fn cell_value(cell: &Data) -> Value {
+    match cell {
+        Data::Int(integer) => Value::from(*integer),
+        Data::Float(float) if float.fract() == 0.0 && float.abs() < i64::MAX as f64 => {
+            Value::from(*float as i64)
+        }
+        Data::Float(float) => Value::from(*float),
+        Data::String(text) if text.trim().is_empty() => Value::Null,
+        Data::String(text) => Value::String(text.trim().to_string()),
+        Data::Bool(boolean) => Value::Bool(*boolean),
+        Data::DateTime(date_time) => date_time
+            .as_datetime()
+            .map(|date_time| Value::String(date_time.format("%Y-%m-%dT%H:%M:%S").to_string()))
+            .unwrap_or(Value::Null),
+        Data::DateTimeIso(text) | Data::DurationIso(text) => Value::String(text.clone()),
+        Data::Error(_) | Data::Empty => Value::Null,
+    }
+}
+
+/// Column names from a header row. Blank headers are named after their position and repeated
+/// ones get a numeric suffix, so every column ends up in the payload.
+fn column_names(header: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    header
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let name = match name.trim() {
+                "" => format!("column_{}", i + 1),
+                name => name.to_string(),
+            };
+            let mut unique = name.clone();
+            let mut suffix = 2;
+            while !seen.insert(unique.clone()) {
+                unique = format!("{}_{}", name, suffix);
+                suffix += 1;
+            }
+            unique
+        })
+        .collect()
+}
+
+fn row_fields(columns: &[String], values: Vec<Value>) -> Option<HashMap<String, Value>> {
+    if values.iter().all(Value::is_null) {
+        return None;
+    }
+    Some(columns.iter().cloned().zip(values).collect())
+}
+
+fn read_csv_rows(data: &[u8]) -> Result<Vec<StructuredRow>> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(data);
+    let columns = column_names(reader.headers()?.iter().map(String::from).collect());
+    let mut rows = vec![];
+    for (i, record) in reader.records().enumerate() {
+        let values = record?.iter().map(typed_value).collect();
+        if let Some(fields) = row_fields(&columns, values) {
+            rows.push(StructuredRow {
+                sheet: None,
+                row_number: i + 1,
+                columns: columns.clone(),
+                fields,
+            });
+        }
+    }
+    Ok(rows)
+}
+
+/// Reads every worksheet, taking the first row of each as its header
+fn read_spreadsheet_rows(data: &[u8]) -> Result<Vec<StructuredRow>> {
+    let mut workbook = open_workbook_auto_from_rs(Cursor::new(data))?;
+    let mut rows = vec![];
+    for sheet in workbook.sheet_names() {
+        let range = workbook.worksheet_range(&sheet)?;
+        let mut sheet_rows = range.rows();
+        let Some(header) = sheet_rows.next() else {
+            continue;
+        };
+        let columns = column_names(
+            header
+                .iter()
+                .map(|cell| match cell_value(cell) {
+                    Value::String(text) => text,
+                    Value::Null => String::new(),
+                    value => value.to_string(),
+                })
+                .collect(),
+        );
+        for (i, cells) in sheet_rows.enumerate() {
+            let values = cells.iter().map(cell_value).collect();
+            if let Some(fields) = row_fields(&columns, values) {
+                rows.push(StructuredRow {
+                    sheet: Some(sheet.clone()),
+                    row_number: i + 1,
+                    columns: columns.clone(),
+                    fields,
+                });
+            }
+        }
+    }
+    Ok(rows)
+}
+
+fn read_json_lines(data: &[u8]) -> Result<Vec<StructuredRow>> {
+    let text = String::from_utf8_lossy(data);
+    let mut rows = vec![];
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line)? {
+            Value::Object(fields) => rows.push(StructuredRow {
+                sheet: None,
+                row_number: i + 1,
+                columns: fields.keys().cloned().collect(),
+                fields: HashMap::from_iter(fields),
+            }),
+            _ => log::warn!(
+                "Skipping line {} of JSON Lines file, it is not an object",
+                i + 1
+            ),
+        }
+    }
+    Ok(rows)
+}
+
+/// Reads the rows of a CSV, spreadsheet or JSON Lines file
+pub fn read_rows(data: &[u8], file_type: FileType) -> Result<Vec<StructuredRow>> {
+    match file_type {
+        FileType::CSV => read_csv_rows(data),
+        FileType::XLSX => read_spreadsheet_rows(data),
+        FileType::JSONL => read_json_lines(data),
+        file_type => Err(anyhow!(
+            "{} files cannot be read row by row",
+            FileType::to_str(file_type)
+        )),
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+/// Replaces each `{column}` in `template` with that column's value. Placeholders naming a column
+/// the row does not have are left as they are.
+fn render_template(template: &str, fields: &HashMap<String, Value>) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        let placeholder = &rest[start..];
+        match placeholder.find('}') {
+            Some(end) => {
+                match fields.get(&placeholder[1..end]) {
+                    Some(value) => text.push_str(&value_text(value)),
+                    None => text.push_str(&placeholder[..=end]),
+                }
+                rest = &placeholder[end + 1..];
+            }
+            None => {
+                text.push_str(placeholder);
+                rest = "";
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+/// The text embedded for a row: the configured template, else the embedding column, else every
+/// column as `name: value` lines
+pub fn row_text(row: &StructuredRow, config: Option<&StructuredFileConfig>) -> String {
+    if let Some(template) = config.and_then(|config| config.embedding_template.as_deref()) {
+        return render_template(template, &row.fields);
+    }
+    if let Some(column) = config.and_then(|config| config.embedding_column.as_deref()) {
+        return row.fields.get(column).map(value_text).unwrap_or_default();
+    }
+    row.columns
+        .iter()
+        .filter_map(|column| match row.fields.get(column) {
+            None | Some(Value::Null) => None,
+            Some(value) => Some(format!("{}: {}", column, value_text(value))),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Values of the row's primary key columns in key order, `None` if any of them is missing
+pub fn primary_key_values(row: &StructuredRow, primary_key: &[String]) -> Option<Vec<Value>> {
+    primary_key
+        .iter()
+        .map(|column| {
+            row.fields
+                .get(column)
+                .filter(|value| !value.is_null())
+                .cloned()
+        })
+        .collect()
+}
+
+/// Whether a column name is taken by a field the proxy writes to every point. Those fields are
+/// `index`, `page_content`, `metadata.*` and everything prefixed with `ac_`.
+fn is_reserved_field(name: &str) -> bool {
+    matches!(name, "index" | "page_content" | "metadata")
+        || name.starts_with("metadata.")
+        || name.starts_with("ac_")
+}
+
+/// Columns of a row as payload fields. Nested JSON objects are kept as objects. Columns named
+/// like a reserved field are stored under `ac_column_<name>` so they cannot overwrite it.
+pub fn row_payload(row: &StructuredRow) -> HashMap<String, Value> {
+    let mut payload: HashMap<String, Value> = row
+        .fields
+        .iter()
+        .map(|(column, value)| {
+            let field = if is_reserved_field(column) {
+                format!("{}{}", RESERVED_COLUMN_PREFIX, column)
+            } else {
+                column.clone()
+            };
+            (field, value.clone())
+        })
+        .collect();
+    if let Some(sheet) = &row.sheet {
+        payload.insert(
+            SHEET_PAYLOAD_FIELD.to_string(),
+            Value::String(sheet.clone()),
+        );
+    }
+    payload.insert(
+        ROW_NUMBER_PAYLOAD_FIELD.to_string(),
+        Value::from(row.row_number),
+    );
+    payload
+}
+
+/// Identifies a row without a primary key by its sheet and columns, so re-uploading the same
+/// file writes the same points
+pub fn row_identity(row: &StructuredRow) -> HashMap<String, Value> {
+    let mut identity = row.fields.clone();
+    if let Some(sheet) = &row.sheet {
+        identity.insert(
+            SHEET_PAYLOAD_FIELD.to_string(),
+            Value::String(sheet.clone()),
+        );
+    }
+    identity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn row_payload_keeps_columns_off_reserved_fields() {
+        let row = StructuredRow {
+            sheet: Some("Tabelle1".to_string()),
+            row_number: 3,
+            columns: vec![],
+            fields: HashMap::from([
+                ("index".to_string(), Value::from(7)),
+                ("page_content".to_string(), Value::from("text")),
+                ("ac_row_number".to_string(), Value::from(99)),
+                ("name".to_string(), Value::from("Jürgen")),
+            ]),
+        };
+        let payload = row_payload(&row);
+        assert_eq!(payload.get("index"), None);
+        assert_eq!(payload.get("page_content"), None);
+        assert_eq!(payload.get("ac_column_index"), Some(&Value::from(7)));
+        assert_eq!(
+            payload.get("ac_column_page_content"),
+            Some(&Value::from("text"))
+        );
+        assert_eq!(
+            payload.get("ac_column_ac_row_number"),
+            Some(&Value::from(99))
+        );
+        assert_eq!(payload.get(ROW_NUMBER_PAYLOAD_FIELD), Some(&Value::from(3)));
+        assert_eq!(
+            payload.get(SHEET_PAYLOAD_FIELD),
+            Some(&Value::from("Tabelle1"))
+        );
+        assert_eq!(payload.get("name"), Some(&Value::from("Jürgen")));
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use mongodb::Database;
+use once_cell::sync::Lazy;
+use uuid::Uuid;
+
+use crate::adaptors::mongo::models::{DataSources, SyncMode};
+use crate::adaptors::mongo::queries::{
+    claim_staging_collection, clear_staging_collection, get_datasource, set_collection_name,
+};
+use crate::init::env_variables::GLOBAL_DATA;
+use crate::vector_databases::helpers::check_byo_vector_database;
+use crate::vector_databases::models::{
+    CollectionCreate, Region, SearchRequest, SearchType, VectorDatabaseStatus,
+};
+use crate::vector_databases::vector_database::{default_vector_db_client, VectorDatabase};
+
+/// Staging collections this process has written to, keyed by datasource ID, along with the time of
+/// the last write so the swap can wait for rows that are still being embedded.
+static STAGING_COLLECTIONS: Lazy<Mutex<HashMap<String, (String, Instant)>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Append syncs keep every version of a row, so only the other modes use the primary key as the
+/// point ID. Rows with no known sync mode keep being deduplicated as before.
+pub fn dedupes_on_primary_key(sync_mode: Option<&SyncMode>) -> bool {
+    !matches!(
+        sync_mode,
+        Some(SyncMode::FullRefreshAppend) | Some(SyncMode::IncrementalAppend)
+    )
+}
+
+pub fn live_collection(datasource: &DataSources) -> String {
+    datasource
+        .collection_name
+        .clone()
+        .unwrap_or(datasource.id.to_string())
+}
+
+pub fn search_request_for(datasource: &DataSources, collection: String) -> SearchRequest {
+    let mut search_request = SearchRequest::new(SearchType::Collection, collection);
+    search_request.byo_vector_db = datasource.byo_vector_db;
+    search_request.namespace = datasource.namespace.clone();
+    search_request.region = datasource.region.clone().map(|r| Region::from_str(&r));
+    search_request
+}
+
+/// Returns the collection rows of an overwrite sync should be written to, creating it when the
+/// first row of the sync arrives. Returns `None` when the vector database can not swap collections,
+/// in which case rows are upserted into the live collection as before.
+pub async fn staging_collection(
+    mongo: &Database,
+    vector_database_client: &dyn VectorDatabase,
+    datasource: &DataSources,
+    dimensions: usize,
+) -> anyhow::Result<Option<String>> {
+    if !vector_database_client.supports_collection_aliases() {
+        return Ok(None);
+    }
+    let datasource_id = datasource.id.to_string();
+    if let Some((collection, last_write)) =
+        STAGING_COLLECTIONS.lock().unwrap().get_mut(&datasource_id)
+    {
+        *last_write = Instant::now();
+        return Ok(Some(collection.clone()));
+    }
+
+    let live_collection = live_collection(datasource);
+    let proposed_collection = format!("{}_{}", live_collection, Uuid::new_v4().simple());
+    let collection = claim_staging_collection(mongo, &datasource_id, &proposed_collection).await?;
+    let staging_request = search_request_for(datasource, collection.clone());
+    let exists = vector_database_client
+        .check_collection_exists(staging_request.clone())
+        .await?;
+    if let VectorDatabaseStatus::NotFound = exists.status {
+        // Keep the distance metric of the collection being replaced
+        let distance = vector_database_client
+            .get_collection_info(search_request_for(datasource, live_collection))
+            .await
+            .ok()
+            .flatten()
+            .and_then(|info| info.metric)
+            .unwrap_or_default();
+        let collection_create = CollectionCreate {
+            collection_name: collection.clone(),
+            dimensions,
+            namespace: datasource.namespace.clone(),
+            distance,
+            vector_name: None,
+            region: datasource.region.clone(),
+            cloud: datasource.cloud.clone(),
+            index_name: None,
+        };
+        if let Err(e) = vector_database_client
+            .create_collection(collection_create)
+            .await
+        {
+            // Another worker may have created it in the meantime
+            let exists = vector_database_client
+                .check_collection_exists(staging_request)
+                .await?;
+            if let VectorDatabaseStatus::NotFound = exists.status {
+                return Err(anyhow!(
+                    "Could not create staging collection: {}. Error: {}",
+                    collection,
+                    e
+                ));
+            }
+        }
+        log::info!(
+            "Overwrite sync for datasource: {} is staging into collection: {}",
+            datasource_id,
+            collection
+        );
+    }
+    STAGING_COLLECTIONS
+        .lock()
+        .unwrap()
+        .insert(datasource_id, (collection.clone(), Instant::now()));
+    Ok(Some(collection))
+}
+
+/// Replaces the datasource's live collection with its staging collection, so rows that were not
+/// part of the overwrite sync disappear. Returns the collection that was promoted, if any.
+pub async fn promote_staging_collection(
+    mongo: &Database,
+    datasource_id: &str,
+) -> anyhow::Result<Option<String>> {
+    let datasource = get_datasource(mongo, datasource_id)
+        .await?
+        .ok_or(anyhow!("Datasource: {} does not exist", datasource_id))?;
+    let Some(staging_collection) = datasource.staging_collection.clone() else {
+        return Ok(None);
+    };
+    let vector_database_client = check_byo_vector_database(datasource.clone(), mongo)
+        .await
+        .unwrap_or(default_vector_db_client().await);
+    let live_collection = live_collection(&datasource);
+    let vector_database_client = vector_database_client.read().await;
+    let alias = vector_database_client
+        .swap_collection_alias(
+            search_request_for(&datasource, live_collection.clone()),
+            staging_collection.clone(),
+        )
+        .await?;
+    if alias != live_collection {
+        // The live collection was a regular collection, so the staging collection went behind an
+        // alias of its own. It is only removed once the datasource is read through that alias.
+        set_collection_name(mongo, datasource_id, &alias).await?;
+        if let Err(e) = vector_database_client
+            .delete_collection(search_request_for(&datasource, live_collection.clone()))
+            .await
+        {
+            log::warn!(
+                "Could not remove collection: {} replaced by alias: {}. Error: {}",
+                live_collection,
+                alias,
+                e
+            );
+        }
+    }
+    clear_staging_collection(mongo, datasource_id).await?;
+    STAGING_COLLECTIONS.lock().unwrap().remove(datasource_id);
+    log::info!(
+        "Promoted staging collection: {} for datasource: {}",
+        staging_collection,
+        datasource_id
+    );
+    Ok(Some(staging_collection))
+}
+
+/// Airbyte reports a sync as complete once the rows are on the queue, not once they are embedded.
+/// Waits until nothing has been written to the staging collection for `SYNC_SWAP_QUIET_PERIOD_SECS`
+/// before promoting it.
+pub async fn promote_staging_collection_when_idle(mongo: &Database, datasource_id: &str) {
+    let quiet_period =
+        Duration::from_secs(GLOBAL_DATA.read().await.sync_swap_quiet_period_secs.max(1));
+    loop {
+        let last_write = STAGING_COLLECTIONS
+            .lock()
+            .unwrap()
+            .get(datasource_id)
+            .map(|(_, last_write)| *last_write);
+        match last_write {
+            Some(last_write) if last_write.elapsed() < quiet_period => {
+                tokio::time::sleep(quiet_period - last_write.elapsed()).await
+            }
+            _ => break,
+        }
+    }
+    if let Err(e) = promote_staging_collection(mongo, datasource_id).await {
+        log::error!(
+            "Could not promote staging collection for datasource: {}. Error: {}",
+            datasource_id,
+            e
+        );
+    }
+}
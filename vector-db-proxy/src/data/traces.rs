@@ -0,0 +1,44 @@
+use mongodb::Database;
+
+use crate::adaptors::mongo::queries::{
+    set_datasource_error, set_stream_row_estimate, set_sync_row_estimate,
+};
+use crate::messages::airbyte::AirbyteTrace;
+
+/// Applies an Airbyte trace message to the datasource. Errors put the datasource into the `error`
+/// status and estimates update `recordCount.total`. Other traces are only informational.
+pub async fn apply_trace(mongo: &Database, datasource_id: &str, trace: AirbyteTrace) {
+    let result = match trace {
+        AirbyteTrace::Error { error } => {
+            log::warn!(
+                "Sync for datasource: {} reported an error: {}",
+                datasource_id,
+                error.message
+            );
+            set_datasource_error(
+                mongo,
+                datasource_id,
+                &error.message,
+                error.failure_type.as_deref(),
+            )
+            .await
+        }
+        AirbyteTrace::Estimate { estimate } => match estimate.row_estimate {
+            Some(row_estimate) if estimate.estimate_type == "SYNC" => {
+                set_sync_row_estimate(mongo, datasource_id, row_estimate).await
+            }
+            Some(row_estimate) => {
+                set_stream_row_estimate(mongo, datasource_id, &estimate.name, row_estimate).await
+            }
+            None => Ok(()),
+        },
+        AirbyteTrace::StreamStatus { .. } | AirbyteTrace::Analytics { .. } => Ok(()),
+    };
+    if let Err(e) = result {
+        log::error!(
+            "Could not apply trace message for datasource: {}. Error: {}",
+            datasource_id,
+            e
+        );
+    }
+}
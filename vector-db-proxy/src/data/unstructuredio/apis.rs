@@ -1,33 +1,86 @@
 use crate::adaptors::mongo::models::{
     UnstructuredChunkingConfig, UnstructuredChunkingStrategy, UnstructuredPartitioningStrategy,
 };
+use crate::data::chunking::pdf::split_pdf;
 use crate::data::models::FileType;
 use crate::data::unstructuredio::models::UnstructuredIOResponse;
+use crate::init::env_variables::GLOBAL_DATA;
+use crate::utils::file_operations::image_extension;
 use anyhow::{anyhow, Result};
 use backoff::backoff::Backoff;
 use backoff::ExponentialBackoff;
-use reqwest::blocking::multipart::{Form, Part};
-use reqwest::blocking::Client;
-use reqwest::header::{HeaderMap, HeaderValue};
-use std::io::Cursor;
-use std::thread::sleep;
+use bytes::Bytes;
+use futures::future::try_join_all;
+use once_cell::sync::Lazy;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Body, Client, StatusCode};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tokio::sync::Semaphore;
 
-fn chunking_strategy_to_form_data(
-    file_buffer: Cursor<Vec<u8>>,
-    file_name: Option<String>,
-    chunking_strategy: Option<UnstructuredChunkingConfig>,
+static CLIENT: Lazy<Client> = Lazy::new(|| {
+    Client::builder()
+        .connect_timeout(Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::limited(10))
+        .build()
+        .unwrap_or_default()
+});
+
+/// One semaphore per endpoint, shared by every worker, so a busy endpoint is not sent more
+/// requests than it can partition at once
+static ENDPOINT_PERMITS: Lazy<Mutex<HashMap<String, Arc<Semaphore>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn endpoint_permits(endpoint: &str, max_concurrent_requests: usize) -> Arc<Semaphore> {
+    Arc::clone(
+        ENDPOINT_PERMITS
+            .lock()
+            .unwrap()
+            .entry(endpoint.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(max_concurrent_requests.max(1)))),
+    )
+}
+
+/// A document, or a page range of a PDF, to be partitioned. The file is reference counted so
+/// retries and other endpoints send it without copying it.
+#[derive(Clone)]
+struct PartitionRequest {
+    file: Bytes,
+    file_name: String,
+    config: Option<UnstructuredChunkingConfig>,
     file_type: Option<FileType>,
-) -> Result<Form> {
-    // If there's no file name give we send with a placeholder name and the file extension
-    // associated with the file type
-    let name = file_name.unwrap_or(format!(
-        "text_file.{}",
-        FileType::to_str(file_type.unwrap())
-    ));
-    let mut form = Form::new().part("files", Part::reader(file_buffer).file_name(name));
+    starting_page_number: Option<usize>,
+}
+
+/// Why a request to an endpoint failed, which decides whether the next endpoint is tried
+enum RequestError {
+    /// The endpoint could not be reached, is misconfigured or kept failing
+    Unavailable(anyhow::Error),
+    /// The document was rejected, which any other endpoint would do as well
+    Rejected(anyhow::Error),
+}
+
+/// Timeouts, rate limiting and the server being briefly unable to take the request
+fn is_retryable(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::REQUEST_TIMEOUT
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+fn chunking_strategy_to_form_data(request: &PartitionRequest) -> Form {
+    let file =
+        Part::stream_with_length(Body::from(request.file.clone()), request.file.len() as u64)
+            .file_name(request.file_name.clone());
+    let mut form = Form::new().part("files", file);
 
-    if let Some(strategy_config) = chunking_strategy {
+    if let Some(strategy_config) = &request.config {
         let chunking_strategy = UnstructuredChunkingStrategy::as_str(&strategy_config.strategy);
         if strategy_config.strategy != UnstructuredChunkingStrategy::Basic {
             form = form.text("chunking_strategy", chunking_strategy);
@@ -44,101 +97,218 @@ fn chunking_strategy_to_form_data(
         let overlap_all = strategy_config.overlap_all.to_string();
         form = form.text("overlap_all", overlap_all);
 
-        let partitioning_strategy =
-            UnstructuredPartitioningStrategy::as_str(&strategy_config.partitioning);
+        // Text in images can only be read with OCR, which the fast strategy skips
+        let partitioning = match (request.file_type, strategy_config.partitioning.clone()) {
+            (Some(FileType::IMAGE), UnstructuredPartitioningStrategy::Fast) => {
+                UnstructuredPartitioningStrategy::HiRes
+            }
+            (_, partitioning) => partitioning,
+        };
+        let partitioning_strategy = UnstructuredPartitioningStrategy::as_str(&partitioning);
         form = form.text("strategy", partitioning_strategy);
     }
 
-    if let Some(file_type) = file_type {
-        match file_type {
-            FileType::PDF => {
-                form = form.text("pdf_infer_table_structure", "true");
-            }
-            _ => (),
-        }
+    if let Some(FileType::PDF) = request.file_type {
+        form = form.text("pdf_infer_table_structure", "true");
+    }
+    if let Some(starting_page_number) = request.starting_page_number {
+        form = form.text("starting_page_number", starting_page_number.to_string());
     }
 
-    Ok(form)
+    form
 }
 
-pub fn chunk_text(
-    url: String,
-    api_key: Option<String>,
-    file_buffer: Cursor<Vec<u8>>,
-    file_name: Option<String>,
-    chunking_strategy: Option<UnstructuredChunkingConfig>,
-    file_type: Option<FileType>,
-) -> Result<Vec<UnstructuredIOResponse>> {
+/// Sends the request to one endpoint, retrying connection errors and retryable statuses with
+/// backoff. A permit for the endpoint is held only while a request is in flight.
+async fn partition_with_endpoint(
+    endpoint: &str,
+    api_key: Option<&str>,
+    request: &PartitionRequest,
+) -> Result<Vec<UnstructuredIOResponse>, RequestError> {
+    let (max_concurrent_requests, timeout_secs) = {
+        let global_data = GLOBAL_DATA.read().await;
+        (
+            global_data.unstructuredio_max_concurrent_requests,
+            global_data.unstructuredio_timeout_secs,
+        )
+    };
+    let permits = endpoint_permits(endpoint, max_concurrent_requests);
     let mut backoff = ExponentialBackoff {
-        current_interval: Duration::from_millis(50),
-        initial_interval: Duration::from_millis(50),
-        max_interval: Duration::from_secs(3),
-        max_elapsed_time: Some(Duration::from_secs(30)),
-        multiplier: 1.5,
-        randomization_factor: 0.5,
+        initial_interval: Duration::from_millis(500),
+        max_interval: Duration::from_secs(10),
+        max_elapsed_time: Some(Duration::from_secs(60)),
         ..ExponentialBackoff::default()
     };
-
-    let client = Client::builder()
-        .timeout(Duration::from_secs(2000))
-        .redirect(reqwest::redirect::Policy::limited(10))
-        .build()?;
-
-    let mut header_map = HeaderMap::new();
-    header_map.insert("accept", HeaderValue::from_str("application/json")?);
-
-    api_key
-        .map(|key| header_map.insert("unstructured-api-key", HeaderValue::from_str(&key).unwrap()));
-
     loop {
-        let form = chunking_strategy_to_form_data(
-            file_buffer.clone(),
-            file_name.clone(),
-            chunking_strategy.clone(),
-            file_type,
-        )?;
-        // Cloning the form for each retry attempt
-        let response = client
-            .post(&url)
-            .headers(header_map.clone())
-            .multipart(form)
-            .send();
-
-        match response {
-            Ok(response_obj) => {
-                return if response_obj.status().is_success() {
-                    // Deserialize the response if the request was successful
-                    match response_obj.json::<Vec<UnstructuredIOResponse>>() {
-                        Ok(unstructuredio_response) => Ok(unstructuredio_response),
-                        Err(e) => Err(anyhow!(
-                            "An error occurred while unpacking the successful response. Error: {:?}",
-                            e
-                        )),
-                    }
-                } else {
-                    // Handle the error response
-                    let status = response_obj.status();
-                    let error_text = response_obj.text()?;
-                    log::error!(
-                        "Received error response with status {}: {}",
-                        status,
-                        error_text
-                    );
-                    Err(anyhow!(
-                        "Received an error response from Unstructured IO: {}",
-                        error_text
-                    ))
-                };
+        // The status of a failed response, none if the request could not be sent at all
+        let (status, error) = {
+            let _permit = permits
+                .acquire()
+                .await
+                .map_err(|e| RequestError::Unavailable(e.into()))?;
+            let mut builder = CLIENT
+                .post(endpoint)
+                .header("accept", "application/json")
+                .timeout(Duration::from_secs(timeout_secs))
+                .multipart(chunking_strategy_to_form_data(request));
+            if let Some(api_key) = api_key {
+                builder = builder.header("unstructured-api-key", api_key);
             }
-            Err(e) => {
-                log::warn!("Encountered an error while sending the request: {}", e);
+            match builder.send().await {
+                Ok(response) if response.status().is_success() => {
+                    return response
+                        .json::<Vec<UnstructuredIOResponse>>()
+                        .await
+                        .map_err(|e| {
+                            RequestError::Rejected(anyhow!(
+                                "An error occurred while unpacking the successful response. Error: {:?}",
+                                e
+                            ))
+                        });
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let error_text = response.text().await.unwrap_or_default();
+                    (
+                        Some(status),
+                        anyhow!("{} responded with {}: {}", endpoint, status, error_text),
+                    )
+                }
+                Err(e) => (
+                    None,
+                    anyhow!("Could not send request to {}. Error: {}", endpoint, e),
+                ),
+            }
+        };
+        match status {
+            Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND) => {
+                return Err(RequestError::Unavailable(error))
+            }
+            Some(status) if !is_retryable(status) => return Err(RequestError::Rejected(error)),
+            _ => {}
+        }
+        match backoff.next_backoff() {
+            Some(delay) => {
+                log::warn!("{}, retrying in {}ms", error, delay.as_millis());
+                tokio::time::sleep(delay).await;
             }
+            None => return Err(RequestError::Unavailable(error)),
         }
+    }
+}
 
-        if let Some(next_backoff) = backoff.next_backoff() {
-            sleep(next_backoff);
-        } else {
-            return Err(anyhow!("Reached maximum retry attempts"));
+/// Tries each endpoint in turn until one partitions the document. A document an endpoint rejects
+/// is not sent to the others.
+async fn partition(
+    endpoints: &[String],
+    api_key: Option<&str>,
+    request: &PartitionRequest,
+) -> Result<Vec<UnstructuredIOResponse>> {
+    let mut last_error = anyhow!("No Unstructured IO endpoint is configured");
+    for endpoint in endpoints {
+        match partition_with_endpoint(endpoint, api_key, request).await {
+            Ok(elements) => return Ok(elements),
+            Err(RequestError::Rejected(e)) => return Err(e),
+            Err(RequestError::Unavailable(e)) => {
+                log::warn!("Unstructured IO endpoint unavailable. Error: {}", e);
+                last_error = e;
+            }
         }
     }
+    Err(last_error)
+}
+
+/// Partitions a page range of a PDF, numbering its pages from where the range starts. Servers
+/// that ignore `starting_page_number` count the pages of every range from 1, those are shifted.
+async fn partition_page_range(
+    endpoints: &[String],
+    api_key: Option<&str>,
+    request: PartitionRequest,
+) -> Result<Vec<UnstructuredIOResponse>> {
+    let mut elements = partition(endpoints, api_key, &request).await?;
+    let start = request.starting_page_number.unwrap_or(1) as i64;
+    let numbered_from_one = elements
+        .iter()
+        .filter_map(|element| element.metadata.page_number)
+        .any(|page_number| page_number < start);
+    if numbered_from_one {
+        for element in elements.iter_mut() {
+            if let Some(page_number) = element.metadata.page_number.as_mut() {
+                *page_number += start - 1;
+            }
+        }
+    }
+    Ok(elements)
+}
+
+/// Partitions and chunks a document with Unstructured IO. Large PDFs are split into page ranges
+/// that are partitioned in parallel, so chunks never span two ranges.
+pub async fn chunk_text(
+    file: Vec<u8>,
+    file_name: Option<String>,
+    chunking_strategy: Option<UnstructuredChunkingConfig>,
+    file_type: Option<FileType>,
+) -> Result<Vec<UnstructuredIOResponse>> {
+    let (endpoints, api_key, pages_per_request) = {
+        let global_data = GLOBAL_DATA.read().await;
+        let endpoints: Vec<String> = global_data
+            .unstructuredio_url
+            .split(',')
+            .map(str::trim)
+            .filter(|endpoint| !endpoint.is_empty())
+            .map(String::from)
+            .collect();
+        let api_key = Some(global_data.unstructuredio_api_key.clone()).filter(|s| !s.is_empty());
+        (
+            endpoints,
+            api_key,
+            global_data.unstructuredio_pdf_pages_per_request,
+        )
+    };
+    // If there's no file name give we send with a placeholder name and the file extension
+    // associated with the file type
+    let file_name = file_name.unwrap_or_else(|| {
+        let extension = match file_type {
+            Some(FileType::IMAGE) => image_extension(&file).unwrap_or("image"),
+            file_type => FileType::to_str(file_type.unwrap_or(FileType::TXT)),
+        };
+        format!("text_file.{}", extension)
+    });
+    let file = Bytes::from(file);
+    let page_ranges = if file_type == Some(FileType::PDF) && pages_per_request > 0 {
+        let pdf = file.clone();
+        // Splitting rewrites the PDF, which is CPU bound
+        match tokio::task::spawn_blocking(move || split_pdf(&pdf, pages_per_request)).await? {
+            Ok(page_ranges) => page_ranges,
+            Err(e) => {
+                log::warn!("Could not split PDF, sending it whole. Error: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let request = PartitionRequest {
+        file,
+        file_name,
+        config: chunking_strategy,
+        file_type,
+        starting_page_number: None,
+    };
+    let Some(page_ranges) = page_ranges else {
+        return partition(&endpoints, api_key.as_deref(), &request).await;
+    };
+    let range_requests = page_ranges
+        .into_iter()
+        .map(|(starting_page_number, range)| PartitionRequest {
+            file: Bytes::from(range),
+            starting_page_number: Some(starting_page_number),
+            ..request.clone()
+        })
+        .map(|range| partition_page_range(&endpoints, api_key.as_deref(), range));
+    Ok(try_join_all(range_requests)
+        .await?
+        .into_iter()
+        .flatten()
+        .collect())
 }
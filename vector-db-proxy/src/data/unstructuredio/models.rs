@@ -1,5 +1,6 @@
 use crate::embeddings::helpers::clean_text;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
 
 // Unstructured IO Response struct
@@ -11,18 +12,49 @@ pub struct UnstructuredIOResponse {
     pub text: String,
     pub metadata: Metadata,
 }
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Metadata {
+    #[serde(default)]
     pub filetype: String,
+    #[serde(default)]
     pub languages: Vec<String>,
     pub page_number: Option<i64>,
+    #[serde(default)]
     pub filename: String,
+    /// Heading level of titles, 0 being the top level
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category_depth: Option<i64>,
+    /// Element ID of the title the element falls under
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    /// Tables as HTML, which keeps the cells that `text` runs together
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_as_html: Option<String>,
+    /// Titles of the sections the element is in, outermost first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub breadcrumbs: Vec<String>,
+    /// Whatever else Unstructured IO returns, such as coordinates, links and emphasized text
+    #[serde(flatten)]
+    pub other: HashMap<String, Value>,
 }
 
 // Construct a HashMap from the Unstructured IO response struct
 impl From<&UnstructuredIOResponse> for HashMap<String, String> {
     fn from(value: &UnstructuredIOResponse) -> Self {
-        let mut map = HashMap::new();
+        // Metadata we have no field for is kept too, with values other than strings as JSON. The
+        // named fields below take precedence over any of it with the same key.
+        let mut map: HashMap<String, String> = value
+            .metadata
+            .other
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Value::String(text) => text.clone(),
+                    value => value.to_string(),
+                };
+                (format!("ac_{}", key), value)
+            })
+            .collect();
 
         // Convert fields of UnstructuredIOResponse to strings and insert them into the map
         map.insert("ac_type".to_string(), value.field_type.clone());
@@ -37,6 +69,22 @@ impl From<&UnstructuredIOResponse> for HashMap<String, String> {
         if let Some(page_number) = value.metadata.page_number {
             map.insert("ac_page_number".to_string(), page_number.to_string());
         }
+        if let Some(category_depth) = value.metadata.category_depth {
+            map.insert("ac_category_depth".to_string(), category_depth.to_string());
+        }
+        // `ac_parent_id` links the parts of a split text, so the title's ID goes under its own key
+        if let Some(parent_id) = &value.metadata.parent_id {
+            map.insert("ac_parent_element_id".to_string(), parent_id.clone());
+        }
+        if let Some(text_as_html) = &value.metadata.text_as_html {
+            map.insert("ac_text_as_html".to_string(), text_as_html.clone());
+        }
+        if !value.metadata.breadcrumbs.is_empty() {
+            map.insert(
+                "ac_breadcrumbs".to_string(),
+                value.metadata.breadcrumbs.join(" > "),
+            );
+        }
         map.insert(
             "metadata.filename".to_string(),
             value.metadata.filename.clone(),
@@ -45,3 +93,46 @@ impl From<&UnstructuredIOResponse> for HashMap<String, String> {
         map
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_keeps_all_element_metadata() {
+        let element: UnstructuredIOResponse = serde_json::from_value(serde_json::json!({
+            "type": "Table",
+            "element_id": "e1",
+            "text": "a b",
+            "metadata": {
+                "filetype": "application/pdf",
+                "languages": ["eng"],
+                "page_number": 2,
+                "filename": "report.pdf",
+                "parent_id": "t1",
+                "text_as_html": "<table><tr><td>a</td><td>b</td></tr></table>",
+                "coordinates": {"system": "PixelSpace", "points": [[1.0, 2.0]]},
+                "emphasized_text_tags": ["b"],
+                "link_urls": "https://example.com"
+            }
+        }))
+        .unwrap();
+        let round_trip: UnstructuredIOResponse =
+            serde_json::from_value(serde_json::to_value(&element).unwrap()).unwrap();
+        assert_eq!(round_trip.metadata.other, element.metadata.other);
+        assert_eq!(round_trip.metadata.parent_id.as_deref(), Some("t1"));
+
+        let payload = HashMap::<String, String>::from(&round_trip);
+        assert_eq!(payload["ac_parent_element_id"], "t1");
+        assert_eq!(
+            payload["ac_text_as_html"],
+            "<table><tr><td>a</td><td>b</td></tr></table>"
+        );
+        assert_eq!(payload["ac_link_urls"], "https://example.com");
+        assert_eq!(payload["ac_emphasized_text_tags"], r#"["b"]"#);
+        let coordinates: Value = serde_json::from_str(&payload["ac_coordinates"]).unwrap();
+        assert_eq!(coordinates, element.metadata.other["coordinates"]);
+        assert_eq!(payload["ac_page_number"], "2");
+        assert_eq!(payload["metadata.filename"], "report.pdf");
+    }
+}
@@ -0,0 +1,434 @@
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use lru::LruCache;
+use redis::aio::ConnectionManager;
+use redis::{ConnectionAddr, ConnectionInfo, RedisConnectionInfo};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+
+use crate::adaptors::mongo::models::Model;
+use crate::init::env_variables::GLOBAL_DATA;
+use crate::init::models::GlobalData;
+
+const CACHE_KEY_PREFIX: &str = "embedding";
+
+/// Somewhere to keep embeddings between calls. Lookups and writes are batched so remote stores
+/// take a single round trip per call to `embed_text`.
+#[async_trait]
+pub trait EmbeddingStore: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<f32>>>>;
+    async fn put_many(&self, entries: Vec<(String, Vec<f32>)>) -> Result<()>;
+}
+
+fn vector_to_bytes(vector: &[f32]) -> Vec<u8> {
+    vector
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect()
+}
+
+fn vector_from_bytes(bytes: &[u8]) -> Option<Vec<f32>> {
+    if bytes.is_empty() || bytes.len() % 4 != 0 {
+        return None;
+    }
+    Some(
+        bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect(),
+    )
+}
+
+fn sha256_hex(data: &str) -> String {
+    format!("{:x}", Sha256::digest(data.as_bytes()))
+}
+
+/// Least recently used embeddings held in process memory
+pub struct MemoryStore {
+    entries: Mutex<LruCache<String, Vec<f32>>>,
+}
+
+impl MemoryStore {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        MemoryStore {
+            entries: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+}
+
+#[async_trait]
+impl EmbeddingStore for MemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<f32>>>> {
+        let mut entries = self.entries.lock().unwrap();
+        Ok(keys.iter().map(|key| entries.get(key).cloned()).collect())
+    }
+
+    async fn put_many(&self, new_entries: Vec<(String, Vec<f32>)>) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        for (key, vector) in new_entries {
+            entries.put(key, vector);
+        }
+        Ok(())
+    }
+}
+
+/// One file per embedding under `directory`, so the cache survives restarts without any extra
+/// infrastructure. Files are written to a temporary name first so readers never see half a vector.
+/// Files older than the TTL are treated as missing, and every `DISK_SWEEP_INTERVAL` writes the
+/// directory is swept of expired files and, oldest first, of whatever is over `max_bytes`.
+pub struct DiskStore {
+    directory: PathBuf,
+    /// Files expire after this long, or never if it is `None`
+    ttl: Option<Duration>,
+    max_bytes: u64,
+    writes: AtomicU64,
+}
+
+/// Writes between two sweeps of the disk cache
+const DISK_SWEEP_INTERVAL: u64 = 1000;
+
+impl DiskStore {
+    pub fn new(directory: impl Into<PathBuf>, ttl_secs: u64, max_bytes: u64) -> Self {
+        DiskStore {
+            directory: directory.into(),
+            ttl: Some(Duration::from_secs(ttl_secs)).filter(|ttl| !ttl.is_zero()),
+            max_bytes,
+            writes: AtomicU64::new(0),
+        }
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        // Keys contain model names with slashes, so files are named after a hash of the key
+        let file_name = sha256_hex(key);
+        self.directory.join(&file_name[..2]).join(file_name)
+    }
+
+    fn is_expired(&self, modified: SystemTime) -> bool {
+        self.ttl
+            .is_some_and(|ttl| modified.elapsed().is_ok_and(|age| age > ttl))
+    }
+
+    /// Removes expired files, then the oldest files until the cache fits in `max_bytes`
+    fn sweep(directory: &Path, ttl: Option<Duration>, max_bytes: u64) -> std::io::Result<()> {
+        let mut files = vec![];
+        for shard in std::fs::read_dir(directory)? {
+            let shard = shard?;
+            if !shard.file_type()?.is_dir() {
+                continue;
+            }
+            for file in std::fs::read_dir(shard.path())? {
+                let file = file?;
+                let metadata = file.metadata()?;
+                let modified = metadata.modified()?;
+                if ttl.is_some_and(|ttl| modified.elapsed().is_ok_and(|age| age > ttl)) {
+                    let _ = std::fs::remove_file(file.path());
+                    continue;
+                }
+                files.push((modified, metadata.len(), file.path()));
+            }
+        }
+        let mut total_bytes: u64 = files.iter().map(|(_, len, _)| len).sum();
+        files.sort_unstable_by_key(|(modified, _, _)| *modified);
+        for (_, len, path) in files {
+            if total_bytes <= max_bytes {
+                break;
+            }
+            if std::fs::remove_file(path).is_ok() {
+                total_bytes -= len;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EmbeddingStore for DiskStore {
+    fn name(&self) -> &'static str {
+        "disk"
+    }
+
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<f32>>>> {
+        let mut vectors = Vec::with_capacity(keys.len());
+        for key in keys {
+            let path = self.path_for(key);
+            let vector = match tokio::fs::metadata(&path).await {
+                Ok(metadata) if self.is_expired(metadata.modified()?) => None,
+                Ok(_) => match tokio::fs::read(&path).await {
+                    Ok(bytes) => vector_from_bytes(&bytes),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(e) => return Err(e.into()),
+                },
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(e.into()),
+            };
+            vectors.push(vector);
+        }
+        Ok(vectors)
+    }
+
+    async fn put_many(&self, entries: Vec<(String, Vec<f32>)>) -> Result<()> {
+        let written = entries.len() as u64;
+        for (key, vector) in entries {
+            let path = self.path_for(&key);
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            let temporary_path = path.with_extension(uuid::Uuid::new_v4().simple().to_string());
+            tokio::fs::write(&temporary_path, vector_to_bytes(&vector)).await?;
+            tokio::fs::rename(&temporary_path, &path).await?;
+        }
+        let writes = self.writes.fetch_add(written, Ordering::Relaxed);
+        if writes / DISK_SWEEP_INTERVAL != (writes + written) / DISK_SWEEP_INTERVAL {
+            let (directory, ttl, max_bytes) = (self.directory.clone(), self.ttl, self.max_bytes);
+            tokio::task::spawn_blocking(move || {
+                if let Err(e) = DiskStore::sweep(&directory, ttl, max_bytes) {
+                    log::warn!("Could not sweep disk embedding cache. Error: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Embeddings kept in the Redis instance the rest of the platform uses, shared by every replica
+pub struct RedisStore {
+    connection: ConnectionManager,
+    /// Entries expire after this many seconds, or never if it is zero
+    ttl_secs: u64,
+}
+
+impl RedisStore {
+    pub async fn connect(global_data: &GlobalData) -> Result<Self> {
+        // Built from parts rather than a URL so passwords with reserved characters need no escaping
+        let connection_info = ConnectionInfo {
+            addr: ConnectionAddr::Tcp(
+                global_data.redis_host.clone(),
+                global_data.redis_port.parse()?,
+            ),
+            redis: RedisConnectionInfo {
+                db: 0,
+                username: None,
+                password: Some(global_data.redis_password.clone()).filter(|p| !p.is_empty()),
+            },
+        };
+        let client = redis::Client::open(connection_info)?;
+        let connection = client.get_connection_manager().await?;
+        Ok(RedisStore {
+            connection,
+            ttl_secs: global_data.embedding_cache_ttl_secs,
+        })
+    }
+}
+
+#[async_trait]
+impl EmbeddingStore for RedisStore {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn get_many(&self, keys: &[String]) -> Result<Vec<Option<Vec<f32>>>> {
+        // Redis rejects an `MGET` without keys
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut connection = self.connection.clone();
+        let values: Vec<Option<Vec<u8>>> = redis::cmd("MGET")
+            .arg(keys)
+            .query_async(&mut connection)
+            .await?;
+        Ok(values
+            .into_iter()
+            .map(|bytes| bytes.as_deref().and_then(vector_from_bytes))
+            .collect())
+    }
+
+    async fn put_many(&self, entries: Vec<(String, Vec<f32>)>) -> Result<()> {
+        let mut connection = self.connection.clone();
+        let mut pipeline = redis::pipe();
+        for (key, vector) in entries {
+            let command = pipeline.cmd("SET").arg(key).arg(vector_to_bytes(&vector));
+            if self.ttl_secs > 0 {
+                command.arg("EX").arg(self.ttl_secs);
+            }
+            command.ignore();
+        }
+        pipeline.query_async::<_, ()>(&mut connection).await?;
+        Ok(())
+    }
+}
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+static ERRORS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize, Debug)]
+pub struct EmbeddingCacheMetrics {
+    pub backend: Option<&'static str>,
+    pub hits: u64,
+    pub misses: u64,
+    pub errors: u64,
+    pub hit_rate: f64,
+}
+
+pub async fn embedding_cache_metrics() -> EmbeddingCacheMetrics {
+    let hits = HITS.load(Ordering::Relaxed);
+    let misses = MISSES.load(Ordering::Relaxed);
+    EmbeddingCacheMetrics {
+        backend: embedding_cache().await.map(|cache| cache.store.name()),
+        hits,
+        misses,
+        errors: ERRORS.load(Ordering::Relaxed),
+        hit_rate: if hits + misses > 0 {
+            hits as f64 / (hits + misses) as f64
+        } else {
+            0.0
+        },
+    }
+}
+
+/// Shared embedding cache in front of the embedding models. Entries are keyed by model name,
+/// dimensions, the credentials the model is called with and a hash of the text, and by default
+/// shared by every team using the same credentials. With `EMBEDDING_CACHE_ISOLATION=team`, or for
+/// the teams listed in `EMBEDDING_CACHE_ISOLATED_TEAMS`, a team only ever sees embeddings computed
+/// for its own text.
+pub struct EmbeddingCache {
+    store: Box<dyn EmbeddingStore>,
+    isolate_all_teams: bool,
+    isolated_teams: HashSet<String>,
+}
+
+impl EmbeddingCache {
+    pub fn new(
+        store: Box<dyn EmbeddingStore>,
+        isolate_all_teams: bool,
+        isolated_teams: HashSet<String>,
+    ) -> Self {
+        EmbeddingCache {
+            store,
+            isolate_all_teams,
+            isolated_teams,
+        }
+    }
+
+    pub fn key(&self, model: &Model, text: &str) -> String {
+        let team_id = model.teamId.to_hex();
+        let scope = if self.isolate_all_teams || self.isolated_teams.contains(&team_id) {
+            team_id
+        } else {
+            "shared".to_string()
+        };
+        // Models served from another endpoint, or billed to another account, do not share entries
+        let credentials = sha256_hex(&format!(
+            "{}:{}",
+            model.config.api_key.as_deref().unwrap_or_default(),
+            model.config.base_url.as_deref().unwrap_or_default()
+        ));
+        format!(
+            "{}:{}:{}:{}:{}:{}",
+            CACHE_KEY_PREFIX,
+            scope,
+            &credentials[..16],
+            model.model,
+            model.embeddingLength,
+            sha256_hex(text)
+        )
+    }
+
+    /// Looks up every key, counting hits and misses. A store that can not be reached is treated
+    /// as a miss for every key so embedding carries on without the cache.
+    pub async fn get_many(&self, keys: &[String]) -> Vec<Option<Vec<f32>>> {
+        let vectors = match self.store.get_many(keys).await {
+            Ok(vectors) => vectors,
+            Err(e) => {
+                ERRORS.fetch_add(1, Ordering::Relaxed);
+                log::warn!(
+                    "Could not read from {} embedding cache. Error: {}",
+                    self.store.name(),
+                    e
+                );
+                vec![None; keys.len()]
+            }
+        };
+        let hits = vectors.iter().filter(|vector| vector.is_some()).count() as u64;
+        HITS.fetch_add(hits, Ordering::Relaxed);
+        MISSES.fetch_add(keys.len() as u64 - hits, Ordering::Relaxed);
+        vectors
+    }
+
+    pub async fn put_many(&self, entries: Vec<(String, Vec<f32>)>) {
+        if let Err(e) = self.store.put_many(entries).await {
+            ERRORS.fetch_add(1, Ordering::Relaxed);
+            log::warn!(
+                "Could not write to {} embedding cache. Error: {}",
+                self.store.name(),
+                e
+            );
+        }
+    }
+}
+
+static EMBEDDING_CACHE: OnceCell<Option<EmbeddingCache>> = OnceCell::const_new();
+
+async fn build_embedding_cache() -> Result<Option<EmbeddingCache>> {
+    let global_data = GLOBAL_DATA.read().await.clone();
+    let store: Box<dyn EmbeddingStore> = match global_data.embedding_cache.as_str() {
+        "none" | "" => return Ok(None),
+        "memory" => Box::new(MemoryStore::new(global_data.embedding_cache_capacity)),
+        "disk" => Box::new(DiskStore::new(
+            global_data.embedding_cache_dir.clone(),
+            global_data.embedding_cache_ttl_secs,
+            global_data.embedding_cache_disk_max_mb * 1024 * 1024,
+        )),
+        "redis" => Box::new(RedisStore::connect(&global_data).await?),
+        other => {
+            return Err(anyhow!(
+                "Unknown embedding cache: {}. Expected one of `none`, `memory`, `disk` or `redis`",
+                other
+            ))
+        }
+    };
+    let isolated_teams = global_data
+        .embedding_cache_isolated_teams
+        .split(',')
+        .map(|team_id| team_id.trim().to_string())
+        .filter(|team_id| !team_id.is_empty())
+        .collect();
+    log::info!("Using {} embedding cache", store.name());
+    let isolate_all_teams = global_data.embedding_cache_isolation == "team";
+    Ok(Some(EmbeddingCache::new(
+        store,
+        isolate_all_teams,
+        isolated_teams,
+    )))
+}
+
+/// The configured embedding cache, built on first use. If it can not be built embedding carries
+/// on without a cache.
+pub async fn embedding_cache() -> Option<&'static EmbeddingCache> {
+    EMBEDDING_CACHE
+        .get_or_init(|| async {
+            build_embedding_cache().await.unwrap_or_else(|e| {
+                log::error!(
+                    "Could not set up embedding cache, continuing without it. Error: {}",
+                    e
+                );
+                None
+            })
+        })
+        .await
+        .as_ref()
+}
@@ -1,3 +1,8 @@
+pub mod cache;
 pub(crate) mod helpers;
 pub mod models;
+pub mod openai;
+pub mod rate_limit;
+pub mod tokenizer;
+pub mod usage;
 pub mod utils;
@@ -46,6 +46,39 @@ impl EmbeddingModels {
             EmbeddingModels::UNKNOWN => None,
         }
     }
+
+    /// List price in US dollars per million input tokens. Models that run locally cost nothing
+    pub fn cost_per_million_tokens(&self) -> f64 {
+        match self {
+            EmbeddingModels::OAI_ADA => 0.10,
+            EmbeddingModels::OAI_SMALL => 0.02,
+            EmbeddingModels::OAI_LARGE => 0.13,
+            _ => 0.0,
+        }
+    }
+
+    /// Longest input the model accepts, in its own tokens
+    pub fn max_input_tokens(&self) -> Option<usize> {
+        match self {
+            EmbeddingModels::OAI_ADA | EmbeddingModels::OAI_SMALL | EmbeddingModels::OAI_LARGE => {
+                Some(8191)
+            }
+            // FastEmbed truncates at 512 tokens, two of which are its start and end tokens
+            EmbeddingModels::BAAI_BGE_SMALL_EN
+            | EmbeddingModels::BAAI_BGE_SMALL_EN_V1_5
+            | EmbeddingModels::BAAI_BGE_BASE_EN
+            | EmbeddingModels::BAAI_BGE_BASE_EN_V1_5
+            | EmbeddingModels::ENTENCE_TRANSFORMERS_ALL_MINILM_L6_V2
+            | EmbeddingModels::XENOVA_FAST_MULTILINGUAL_E5_LARGE => Some(510),
+            EmbeddingModels::UNKNOWN => None,
+        }
+    }
+
+    /// Whether the model returns embeddings scaled to unit length. OpenAI embeddings are, and
+    /// FastEmbed normalizes its output.
+    pub fn normalizes_output(&self) -> bool {
+        !matches!(self, EmbeddingModels::UNKNOWN)
+    }
 }
 
 pub enum FastEmbedModels {
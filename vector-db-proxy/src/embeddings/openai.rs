@@ -0,0 +1,133 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use backoff::backoff::Backoff;
+use backoff::ExponentialBackoff;
+use once_cell::sync::Lazy;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use crate::adaptors::mongo::models::ModelConfig;
+use crate::embeddings::rate_limit::{self, RateLimits};
+use crate::embeddings::usage::BilledFailure;
+
+static HTTP_CLIENT: Lazy<reqwest::Client> = Lazy::new(reqwest::Client::new);
+
+/// Longest we keep retrying a rate limited or failing request before giving up
+const OPENAI_MAX_RETRY_TIME: Duration = Duration::from_secs(60);
+
+/// Embeddings from a successful response, in the order of the input
+async fn read_embeddings(response: reqwest::Response) -> Result<Vec<Vec<f32>>> {
+    let response: Value = response.json().await?;
+    let mut data: Vec<(u64, Vec<f32>)> = response["data"]
+        .as_array()
+        .ok_or(anyhow!("Embedding response did not contain any data"))?
+        .iter()
+        .map(|item| {
+            let embedding = serde_json::from_value(item["embedding"].clone())?;
+            Ok((item["index"].as_u64().unwrap_or_default(), embedding))
+        })
+        .collect::<Result<_>>()?;
+    data.sort_by_key(|(index, _)| *index);
+    Ok(data.into_iter().map(|(_, embedding)| embedding).collect())
+}
+
+/// The delay asked for in a response's `retry-after-ms` or `retry-after` header
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .filter(|value| value.is_finite() && *value >= 0.0)
+    };
+    header("retry-after-ms")
+        .map(|millis| Duration::from_secs_f64(millis / 1000.0))
+        .or_else(|| header("retry-after").map(Duration::from_secs_f64))
+}
+
+/// Calls an OpenAI compatible `/embeddings` endpoint. Requests go through the rate limiter for
+/// `api_key` first, and 429s and server errors are retried after the delay the provider asks for,
+/// pausing every other request using the same key in the meantime. Requests that can not be sent
+/// are retried the same way.
+pub async fn openai_embeddings(
+    model_name: &str,
+    api_key: &str,
+    config: &ModelConfig,
+    text: Vec<&String>,
+    input_tokens: u64,
+) -> Result<Vec<Vec<f32>>> {
+    let base_url = config
+        .base_url
+        .as_deref()
+        .filter(|url| !url.is_empty())
+        .unwrap_or("https://api.openai.com/v1");
+    let url = format!("{}/embeddings", base_url.trim_end_matches('/'));
+    let body = json!({ "model": model_name, "input": text });
+    let mut backoff = ExponentialBackoff {
+        max_elapsed_time: Some(OPENAI_MAX_RETRY_TIME),
+        ..Default::default()
+    };
+    let limits = RateLimits::for_model(config).await;
+    loop {
+        rate_limit::acquire(api_key, limits, input_tokens).await;
+        let mut request = HTTP_CLIENT.post(&url).bearer_auth(api_key).json(&body);
+        if let Some(org_id) = &config.org_id {
+            request = request.header("OpenAI-Organization", org_id);
+        }
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => {
+                let delay = backoff.next_backoff().ok_or(anyhow!(
+                    "Could not send embedding request after retrying for {}s. Error: {}",
+                    OPENAI_MAX_RETRY_TIME.as_secs(),
+                    e
+                ))?;
+                log::warn!(
+                    "Could not send embedding request, retrying in {}ms. Error: {}",
+                    delay.as_millis(),
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+        };
+        let status = response.status();
+        if status.is_success() {
+            // The provider bills the request once it succeeds, even if its response is unusable
+            return read_embeddings(response)
+                .await
+                .map_err(|e| BilledFailure(e).into());
+        }
+        let requested_delay = retry_after(response.headers());
+        let error = response.text().await.unwrap_or_default();
+        if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+            return Err(anyhow!(
+                "Embedding request failed with {}: {}",
+                status,
+                error
+            ));
+        }
+        let Some(delay) = backoff
+            .next_backoff()
+            .map(|delay| requested_delay.unwrap_or(delay))
+        else {
+            return Err(anyhow!(
+                "Embedding request still failing with {} after retrying for {}s: {}",
+                status,
+                OPENAI_MAX_RETRY_TIME.as_secs(),
+                error
+            ));
+        };
+        log::warn!(
+            "Embedding request failed with {}, retrying in {}ms",
+            status,
+            delay.as_millis()
+        );
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            rate_limit::pause(api_key, limits, delay).await;
+        } else {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+
+use crate::adaptors::mongo::models::ModelConfig;
+use crate::init::env_variables::GLOBAL_DATA;
+
+/// Refills continuously at `capacity` per minute and holds at most a minute's worth
+struct TokenBucket {
+    capacity: f64,
+    available: f64,
+    refill_per_sec: f64,
+}
+
+impl TokenBucket {
+    fn per_minute(limit: u64) -> Self {
+        let capacity = limit.max(1) as f64;
+        TokenBucket {
+            capacity,
+            available: capacity,
+            refill_per_sec: capacity / 60.0,
+        }
+    }
+
+    fn refill(&mut self, elapsed: Duration) {
+        self.available =
+            (self.available + elapsed.as_secs_f64() * self.refill_per_sec).min(self.capacity);
+    }
+
+    /// How long until `amount` is available. Amounts larger than the bucket are capped so a single
+    /// oversized request waits for a full bucket rather than forever.
+    fn wait_for(&self, amount: f64) -> Duration {
+        let deficit = amount.min(self.capacity) - self.available;
+        if deficit <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(deficit / self.refill_per_sec)
+        }
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.available -= amount.min(self.capacity);
+    }
+}
+
+/// Requests and tokens per minute allowed for one API key
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimits {
+    pub requests_per_minute: u64,
+    pub tokens_per_minute: u64,
+}
+
+impl RateLimits {
+    /// The limits set on the model's credentials, falling back to `EMBEDDING_RATE_LIMIT_RPM` and
+    /// `EMBEDDING_RATE_LIMIT_TPM` for whichever is not set
+    pub async fn for_model(config: &ModelConfig) -> Self {
+        let global_data = GLOBAL_DATA.read().await;
+        RateLimits {
+            requests_per_minute: config
+                .requests_per_minute
+                .unwrap_or(global_data.embedding_requests_per_minute),
+            tokens_per_minute: config
+                .tokens_per_minute
+                .unwrap_or(global_data.embedding_tokens_per_minute),
+        }
+    }
+}
+
+struct ApiKeyLimiter {
+    limits: RateLimits,
+    requests: TokenBucket,
+    tokens: TokenBucket,
+    last_refill: Instant,
+    /// Set when the provider tells us to back off
+    paused_until: Option<Instant>,
+}
+
+impl ApiKeyLimiter {
+    fn new(limits: RateLimits) -> Self {
+        ApiKeyLimiter {
+            limits,
+            requests: TokenBucket::per_minute(limits.requests_per_minute),
+            tokens: TokenBucket::per_minute(limits.tokens_per_minute),
+            last_refill: Instant::now(),
+            paused_until: None,
+        }
+    }
+
+    /// Picks up limits changed on the model, starting from a full bucket at the new limits
+    fn set_limits(&mut self, limits: RateLimits) {
+        if self.limits != limits {
+            *self = ApiKeyLimiter {
+                paused_until: self.paused_until,
+                ..ApiKeyLimiter::new(limits)
+            };
+        }
+    }
+
+    /// Takes one request and `tokens` tokens if both are available, otherwise returns how long to
+    /// wait before trying again
+    fn try_acquire(&mut self, tokens: f64) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill);
+        self.requests.refill(elapsed);
+        self.tokens.refill(elapsed);
+        self.last_refill = now;
+        let paused_for = self
+            .paused_until
+            .map(|until| until.saturating_duration_since(now))
+            .unwrap_or_default();
+        let wait = paused_for
+            .max(self.requests.wait_for(1.0))
+            .max(self.tokens.wait_for(tokens));
+        if wait > Duration::ZERO {
+            return Err(wait);
+        }
+        self.requests.take(1.0);
+        self.tokens.take(tokens);
+        Ok(())
+    }
+}
+
+/// One limiter per API key, shared by every worker so requests for the same key are spread over
+/// the minute instead of bursting into 429s. Keys are stored hashed.
+static LIMITERS: Lazy<Mutex<HashMap<String, Arc<Mutex<ApiKeyLimiter>>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn limiter_for(api_key: &str, limits: RateLimits) -> Arc<Mutex<ApiKeyLimiter>> {
+    let key = format!("{:x}", Sha256::digest(api_key.as_bytes()));
+    let limiter = Arc::clone(
+        LIMITERS
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| Arc::new(Mutex::new(ApiKeyLimiter::new(limits)))),
+    );
+    limiter.lock().unwrap().set_limits(limits);
+    limiter
+}
+
+/// Rough token count for models without a known tokenizer. Providers count roughly four
+/// characters of English text per token.
+pub fn estimate_tokens(texts: &[&String]) -> u64 {
+    texts
+        .iter()
+        .map(|text| (text.chars().count() as u64).div_ceil(4).max(1))
+        .sum()
+}
+
+/// Waits until a request costing `tokens` can be sent with `api_key` without going over its
+/// requests or tokens per minute
+pub async fn acquire(api_key: &str, limits: RateLimits, tokens: u64) {
+    let limiter = limiter_for(api_key, limits);
+    loop {
+        let result = limiter.lock().unwrap().try_acquire(tokens as f64);
+        match result {
+            Ok(()) => return,
+            Err(wait) => tokio::time::sleep(wait).await,
+        }
+    }
+}
+
+/// Pauses every request for `api_key` for `retry_after`, as asked by the provider in a 429
+pub async fn pause(api_key: &str, limits: RateLimits, retry_after: Duration) {
+    let limiter = limiter_for(api_key, limits);
+    let mut limiter = limiter.lock().unwrap();
+    let until = Instant::now() + retry_after;
+    if limiter.paused_until.map_or(true, |current| current < until) {
+        limiter.paused_until = Some(until);
+    }
+}
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use fastembed::{FlagEmbedding, InitOptions};
+use once_cell::sync::Lazy;
+use tiktoken_rs::CoreBPE;
+
+use crate::embeddings::models::{EmbeddingModels, FastEmbedModels};
+use crate::embeddings::rate_limit::estimate_tokens;
+
+/// Kept free when cutting text to a token budget. Pieces are cut on character boundaries and the
+/// tokens either side of a cut can merge differently once the text is split, so a piece can come
+/// out a few tokens longer than counted.
+const SPLIT_MARGIN_TOKENS: usize = 16;
+
+/// Where FastEmbed keeps downloaded models when no `cache_dir` is given, which is how the models
+/// are loaded for embedding
+const FASTEMBED_CACHE_DIR: &str = "local_cache";
+
+/// Part of a text that fits in the model's context, with its length in the model's tokens
+#[derive(Debug, Clone)]
+pub struct TextPiece {
+    pub text: String,
+    pub tokens: usize,
+}
+
+enum Encoder {
+    /// OpenAI models, all of which use `cl100k_base`
+    Tiktoken(CoreBPE),
+    /// FastEmbed models, using the tokenizer shipped with the model
+    HuggingFace(Box<tokenizers::Tokenizer>),
+}
+
+/// Counts and cuts text the same way the embedding model will
+pub struct ModelTokenizer {
+    encoder: Encoder,
+    max_tokens: usize,
+}
+
+impl ModelTokenizer {
+    /// Byte offset in `text` at which each token ends
+    fn token_ends(&self, text: &str) -> Result<Vec<usize>> {
+        match &self.encoder {
+            Encoder::Tiktoken(bpe) => Ok(bpe
+                .encode_ordinary(text)
+                .into_iter()
+                .scan(0, |end, token| {
+                    *end += bpe._decode_native(&[token]).len();
+                    Some(*end)
+                })
+                .collect()),
+            Encoder::HuggingFace(tokenizer) => Ok(tokenizer
+                .encode(text, false)
+                .map_err(|e| anyhow!("Could not tokenize text. Error: {}", e))?
+                .get_offsets()
+                .iter()
+                .map(|(_, end)| *end)
+                .collect()),
+        }
+    }
+
+    pub fn max_tokens(&self) -> usize {
+        self.max_tokens
+    }
+
+    pub fn count(&self, text: &str) -> Result<usize> {
+        Ok(self.token_ends(text)?.len())
+    }
+
+    /// Cuts `text` into pieces that each fit in the model's context. Text that already fits comes
+    /// back as a single piece.
+    pub fn split(&self, text: &str) -> Result<Vec<TextPiece>> {
+        let token_ends = self.token_ends(text)?;
+        if token_ends.len() <= self.max_tokens {
+            return Ok(vec![TextPiece {
+                text: text.to_string(),
+                tokens: token_ends.len(),
+            }]);
+        }
+        let budget = self.max_tokens.saturating_sub(SPLIT_MARGIN_TOKENS).max(1);
+        let mut pieces: Vec<TextPiece> = vec![];
+        let mut start = 0;
+        for window in token_ends.chunks(budget) {
+            let mut end = window[window.len() - 1].min(text.len());
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            if end <= start {
+                continue;
+            }
+            let piece = text[start..end].trim();
+            if !piece.is_empty() {
+                pieces.push(TextPiece {
+                    text: piece.to_string(),
+                    tokens: window.len(),
+                });
+            }
+            start = end;
+        }
+        // Whatever follows the last token, typically whitespace, belongs to the last piece
+        let rest = text[start..].trim();
+        match pieces.last_mut() {
+            Some(last) if !rest.is_empty() => {
+                last.text.push_str(&text[start..]);
+                last.text = last.text.trim_end().to_string();
+            }
+            None if !rest.is_empty() => pieces.push(TextPiece {
+                text: rest.to_string(),
+                tokens: token_ends.len(),
+            }),
+            _ => {}
+        }
+        Ok(pieces)
+    }
+
+    /// The start of `text` that fits in the model's context
+    pub fn truncate(&self, text: &str) -> Result<TextPiece> {
+        let mut pieces = self.split(text)?;
+        Ok(pieces.remove(0))
+    }
+}
+
+fn load_tokenizer(model_name: &str) -> Result<ModelTokenizer> {
+    let model = EmbeddingModels::from(model_name.to_string());
+    let max_tokens = model
+        .max_input_tokens()
+        .ok_or(anyhow!("No tokenizer known for model: {}", model_name))?;
+    let encoder = match model {
+        EmbeddingModels::OAI_ADA | EmbeddingModels::OAI_SMALL | EmbeddingModels::OAI_LARGE => {
+            Encoder::Tiktoken(tiktoken_rs::cl100k_base()?)
+        }
+        _ => {
+            let translation = FastEmbedModels::from(model_name.to_string())
+                .translate()
+                .ok_or(anyhow!("No tokenizer known for model: {}", model_name))?;
+            let tokenizer_path = Path::new(FASTEMBED_CACHE_DIR)
+                .join(translation.to_string())
+                .join("tokenizer.json");
+            let mut tokenizer = if tokenizer_path.exists() {
+                tokenizers::Tokenizer::from_file(&tokenizer_path)
+                    .map_err(|e| anyhow!("Could not load tokenizer. Error: {}", e))?
+            } else {
+                // The model has not been downloaded yet. Loading it downloads it, and its
+                // tokenizer comes with it.
+                FlagEmbedding::try_new(InitOptions {
+                    model_name: translation,
+                    show_download_message: false,
+                    ..Default::default()
+                })
+                .map(|model| tokenizers::Tokenizer::from(model.tokenizer))?
+            };
+            tokenizer
+                .with_truncation(None)
+                .map_err(|e| anyhow!("Could not load tokenizer. Error: {}", e))?;
+            tokenizer.with_padding(None);
+            Encoder::HuggingFace(Box::new(tokenizer))
+        }
+    };
+    Ok(ModelTokenizer {
+        encoder,
+        max_tokens,
+    })
+}
+
+static TOKENIZERS: Lazy<Mutex<HashMap<String, Arc<ModelTokenizer>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Tokenizer for the embedding model, loaded on first use and kept for the life of the process
+pub async fn tokenizer_for(model_name: &str) -> Result<Arc<ModelTokenizer>> {
+    if let Some(tokenizer) = TOKENIZERS.lock().unwrap().get(model_name) {
+        return Ok(Arc::clone(tokenizer));
+    }
+    let name = model_name.to_string();
+    let tokenizer = Arc::new(tokio::task::spawn_blocking(move || load_tokenizer(&name)).await??);
+    TOKENIZERS
+        .lock()
+        .unwrap()
+        .insert(model_name.to_string(), Arc::clone(&tokenizer));
+    Ok(tokenizer)
+}
+
+/// Total length of `texts` in the model's tokens, estimated from their length if the model's
+/// tokenizer is not available
+pub async fn count_tokens(model_name: &str, texts: &[&String]) -> u64 {
+    count_tokens_each(model_name, texts).await.iter().sum()
+}
+
+/// Length of each of `texts` in the model's tokens, estimated from their length if the model's
+/// tokenizer is not available. Tokenizing is CPU bound so it is kept off the async worker threads.
+pub async fn count_tokens_each(model_name: &str, texts: &[&String]) -> Vec<u64> {
+    let estimated = || texts.iter().map(|text| estimate_tokens(&[text])).collect();
+    let Ok(tokenizer) = tokenizer_for(model_name).await else {
+        return estimated();
+    };
+    let owned: Vec<String> = texts.iter().map(|text| text.to_string()).collect();
+    tokio::task::spawn_blocking(move || {
+        owned
+            .iter()
+            .map(|text| tokenizer.count(text).map(|count| count as u64))
+            .collect::<Result<Vec<u64>>>()
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|counts| counts)
+    .unwrap_or_else(|_| estimated())
+}
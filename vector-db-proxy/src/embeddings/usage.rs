@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use mongodb::bson::oid::ObjectId;
+use mongodb::Database;
+use once_cell::sync::Lazy;
+use tokio::sync::RwLock;
+
+use crate::adaptors::mongo::models::Model;
+use crate::adaptors::mongo::queries::increment_embedding_usage;
+use crate::embeddings::models::EmbeddingModels;
+
+/// Usage is summed into hourly buckets, which is the finest time range it can be queried by
+const USAGE_PERIOD_MILLIS: i64 = 60 * 60 * 1000;
+
+/// Start of the usage bucket `millis` (since the epoch) falls in
+pub fn period_start(millis: i64) -> i64 {
+    millis - millis.rem_euclid(USAGE_PERIOD_MILLIS)
+}
+
+/// An embedding call that failed after the provider accepted the request, so it was billed even
+/// though no embeddings came back
+#[derive(Debug)]
+pub struct BilledFailure(pub anyhow::Error);
+
+impl fmt::Display for BilledFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for BilledFailure {}
+
+/// Which usage bucket an embedding call is counted in
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UsageKey {
+    pub team_id: ObjectId,
+    pub org_id: ObjectId,
+    pub datasource_id: Option<ObjectId>,
+    pub model: String,
+    /// Start of the hour, in milliseconds since the epoch
+    pub period_start: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UsageTotals {
+    pub calls: i64,
+    pub failed_calls: i64,
+    pub items: i64,
+    pub input_tokens: i64,
+    pub latency_ms: i64,
+    pub estimated_cost: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, other: &UsageTotals) {
+        self.calls += other.calls;
+        self.failed_calls += other.failed_calls;
+        self.items += other.items;
+        self.input_tokens += other.input_tokens;
+        self.latency_ms += other.latency_ms;
+        self.estimated_cost += other.estimated_cost;
+    }
+}
+
+/// Usage that has not been written to Mongo yet. Like record counts it is buffered so a write per
+/// embedding call stays off the hot path.
+static PENDING_USAGE: Lazy<Mutex<HashMap<UsageKey, UsageTotals>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn add_usage(key: UsageKey, totals: &UsageTotals) {
+    PENDING_USAGE
+        .lock()
+        .unwrap()
+        .entry(key)
+        .or_default()
+        .add(totals);
+}
+
+/// Records a call to the embedding model against the model's team and org, and `datasource_id`
+/// if the call was made for one. Failed calls are counted too, and their tokens are only counted
+/// if `billed` is set.
+pub fn record_embedding_usage(
+    model: &Model,
+    datasource_id: Option<&str>,
+    items: usize,
+    input_tokens: u64,
+    latency: Duration,
+    failed: bool,
+    billed: bool,
+) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as i64)
+        .unwrap_or_default();
+    let cost_per_million_tokens =
+        EmbeddingModels::from(model.model.clone()).cost_per_million_tokens();
+    let input_tokens = if billed { input_tokens } else { 0 };
+    add_usage(
+        UsageKey {
+            team_id: model.teamId,
+            org_id: model.orgId,
+            datasource_id: datasource_id.and_then(|id| ObjectId::from_str(id).ok()),
+            model: model.model.clone(),
+            period_start: period_start(now),
+        },
+        &UsageTotals {
+            calls: 1,
+            failed_calls: failed as i64,
+            items: if billed { items as i64 } else { 0 },
+            input_tokens: input_tokens as i64,
+            latency_ms: latency.as_millis() as i64,
+            estimated_cost: input_tokens as f64 * cost_per_million_tokens / 1_000_000.0,
+        },
+    );
+}
+
+/// Writes all buffered usage to Mongo. Usage that fails to write is put back so it is retried on
+/// the next flush.
+pub async fn flush_embedding_usage(db: &Database) {
+    let pending = mem::take(&mut *PENDING_USAGE.lock().unwrap());
+    for (key, totals) in pending {
+        if let Err(e) = increment_embedding_usage(db, &key, &totals).await {
+            log::error!(
+                "Could not flush embedding usage for team: {}. Error: {}",
+                key.team_id,
+                e
+            );
+            add_usage(key, &totals);
+        }
+    }
+}
+
+pub async fn flush_embedding_usage_periodically(
+    mongo_conn: Arc<RwLock<Database>>,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let mongo = mongo_conn.read().await;
+        flush_embedding_usage(&mongo).await;
+    }
+}
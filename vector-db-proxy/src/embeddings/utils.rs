@@ -1,15 +1,27 @@
-use crate::adaptors::mongo::models::{DataSources, Model};
-use crate::adaptors::mongo::queries::increment_by_one;
+use crate::adaptors::mongo::models::{DataSources, EmbeddingOverflowPolicy, Model};
+use crate::data::content_hash::CONTENT_HASH_PAYLOAD_FIELD;
+use crate::data::documents::point_ids;
+use crate::data::helpers::{
+    chunk_point_id, part_point_id, section_id, CHUNK_INDEX_PAYLOAD_FIELD,
+    DOCUMENT_ID_PAYLOAD_FIELD, NEXT_CHUNK_ID_PAYLOAD_FIELD, PARENT_ID_PAYLOAD_FIELD,
+    PART_COUNT_PAYLOAD_FIELD, PART_INDEX_PAYLOAD_FIELD, PREVIOUS_CHUNK_ID_PAYLOAD_FIELD,
+    SECTION_ID_PAYLOAD_FIELD,
+};
+use crate::data::record_counts::increment_record_count;
 use crate::data::unstructuredio::models::UnstructuredIOResponse;
+use crate::embeddings::cache::embedding_cache;
 use crate::embeddings::helpers::clean_text;
 use crate::embeddings::models::{EmbeddingModels, FastEmbedModels};
+use crate::embeddings::openai::openai_embeddings;
+use crate::embeddings::tokenizer::{count_tokens, tokenizer_for, TextPiece};
+use crate::embeddings::usage::{record_embedding_usage, BilledFailure};
 use crate::init::env_variables::GLOBAL_DATA;
 use crate::vector_databases::helpers::check_byo_vector_database;
 use crate::vector_databases::models::{Point, SearchRequest, SearchType, VectorDatabaseStatus};
-use crate::vector_databases::vector_database::default_vector_db_client;
+use crate::vector_databases::vector_database::{default_vector_db_client, VectorDatabase};
 use anyhow::{anyhow, Result};
-use async_openai::config::OpenAIConfig;
-use async_openai::types::CreateEmbeddingRequestArgs;
+use backoff::backoff::Backoff;
+use backoff::ExponentialBackoff;
 use fastembed::{EmbeddingBase, FlagEmbedding, InitOptions};
 use mongodb::Database;
 use ort::{
@@ -18,14 +30,13 @@ use ort::{
 };
 use serde_json::Value;
 use std::collections::HashMap;
-use std::sync::Arc as arc;
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use tokio::task;
 use uuid::Uuid;
 
-async fn fastembed_models(
+fn fastembed_models(
     model: &FastEmbedModels,
     use_gpu: &str,
     text: Vec<&String>,
@@ -129,7 +140,85 @@ async fn fastembed_models(
     }
 }
 
-pub async fn embed_text(text: Vec<&String>, model: &Model) -> Result<Vec<Vec<f32>>> {
+/// Embeds `text` with `model`, reusing embeddings from the embedding cache where possible. Only
+/// the texts that miss the cache are sent to the model, and only those count towards the usage
+/// recorded for the team and `datasource_id`.
+pub async fn embed_text(
+    text: Vec<&String>,
+    model: &Model,
+    datasource_id: Option<&str>,
+) -> Result<Vec<Vec<f32>>> {
+    let Some(cache) = embedding_cache().await else {
+        return embed_text_metered(text, model, datasource_id).await;
+    };
+    let keys: Vec<String> = text.iter().map(|t| cache.key(model, t)).collect();
+    let mut embeddings = cache.get_many(&keys).await;
+    let missing: Vec<usize> = embeddings
+        .iter()
+        .enumerate()
+        .filter(|(_, embedding)| embedding.is_none())
+        .map(|(i, _)| i)
+        .collect();
+    if !missing.is_empty() {
+        let computed = embed_text_metered(
+            missing.iter().map(|&i| text[i]).collect(),
+            model,
+            datasource_id,
+        )
+        .await?;
+        if computed.len() != missing.len() {
+            return Err(anyhow!(
+                "Expected {} embeddings from the model but got {}",
+                missing.len(),
+                computed.len()
+            ));
+        }
+        cache
+            .put_many(
+                missing
+                    .iter()
+                    .map(|&i| keys[i].clone())
+                    .zip(computed.iter().cloned())
+                    .collect(),
+            )
+            .await;
+        for (i, embedding) in missing.into_iter().zip(computed) {
+            embeddings[i] = Some(embedding);
+        }
+    }
+    Ok(embeddings.into_iter().flatten().collect())
+}
+
+async fn embed_text_metered(
+    text: Vec<&String>,
+    model: &Model,
+    datasource_id: Option<&str>,
+) -> Result<Vec<Vec<f32>>> {
+    let input_tokens = count_tokens(&model.model, &text).await;
+    let items = text.len();
+    let started = Instant::now();
+    let result = embed_text_uncached(text, model, input_tokens).await;
+    let (failed, billed) = match &result {
+        Ok(_) => (false, true),
+        Err(e) => (true, e.downcast_ref::<BilledFailure>().is_some()),
+    };
+    record_embedding_usage(
+        model,
+        datasource_id,
+        items,
+        input_tokens,
+        started.elapsed(),
+        failed,
+        billed,
+    );
+    result
+}
+
+async fn embed_text_uncached(
+    text: Vec<&String>,
+    model: &Model,
+    input_tokens: u64,
+) -> Result<Vec<Vec<f32>>> {
     let model_name = model.clone().model;
     match EmbeddingModels::from(model_name.clone()) {
         EmbeddingModels::UNKNOWN => Err(anyhow!("This is an unknown model type!")),
@@ -140,73 +229,298 @@ pub async fn embed_text(text: Vec<&String>, model: &Model) -> Result<Vec<Vec<f32
         | EmbeddingModels::BAAI_BGE_BASE_EN_V1_5
         | EmbeddingModels::ENTENCE_TRANSFORMERS_ALL_MINILM_L6_V2
         | EmbeddingModels::XENOVA_FAST_MULTILINGUAL_E5_LARGE => {
-            let global_data = GLOBAL_DATA.read().await;
-            let model = FastEmbedModels::from(model_name);
-            fastembed_models(&model, global_data.use_gpu.as_str(), text).await
+            let use_gpu = GLOBAL_DATA.read().await.use_gpu.clone();
+            let text: Vec<String> = text.into_iter().cloned().collect();
+            // FastEmbed inference is CPU bound so it is kept off the async worker threads
+            task::spawn_blocking(move || {
+                let model = FastEmbedModels::from(model_name);
+                fastembed_models(&model, use_gpu.as_str(), text.iter().collect())
+            })
+            .await?
         }
         // Assume OAI models for now...
-        _ => {
-            let model_clone = model.clone();
-            // initiate variables
-            let backoff = backoff::ExponentialBackoffBuilder::new()
-                .with_max_elapsed_time(Some(std::time::Duration::from_secs(60)))
-                .build();
-            if let Some(api_key) = model_clone.config.api_key {
-                let mut config = OpenAIConfig::new().with_api_key(api_key);
-                if let Some(org_id) = model_clone.config.org_id {
-                    config = config.with_org_id(org_id)
-                }
-                let client = async_openai::Client::with_config(config).with_backoff(backoff);
-                let request = CreateEmbeddingRequestArgs::default()
-                    .model(model_name.clone())
-                    .input(text)
-                    .build()?;
-                let response = client.embeddings().create(request).await?;
-                let embedding: Vec<Vec<f32>> = response
-                    .data
-                    .iter()
-                    .map(|data| data.clone().embedding)
-                    .collect();
-                Ok(embedding)
-            } else {
-                Err(anyhow!("Model missing api key"))
+        _ => match &model.config.api_key {
+            Some(api_key) => {
+                openai_embeddings(&model_name, api_key, &model.config, text, input_tokens).await
             }
+            None => Err(anyhow!("Model missing api key")),
+        },
+    }
+}
+
+/// Number of chunks sent to the model in one request
+const EMBEDDING_BATCH_SIZE: usize = 64;
+
+/// Times a batch is sent before its error fails the whole call
+const EMBEDDING_BATCH_ATTEMPTS: u32 = 3;
+
+async fn embed_batch_with_retry(
+    batch: Vec<String>,
+    model: Model,
+    datasource_id: Option<String>,
+) -> Result<Vec<Vec<f32>>> {
+    let mut backoff = ExponentialBackoff {
+        initial_interval: Duration::from_secs(1),
+        max_elapsed_time: None,
+        ..Default::default()
+    };
+    let mut attempt = 1;
+    loop {
+        match embed_text(batch.iter().collect(), &model, datasource_id.as_deref()).await {
+            Ok(embeddings) => return Ok(embeddings),
+            Err(e) if attempt < EMBEDDING_BATCH_ATTEMPTS => {
+                let delay = backoff.next_backoff().unwrap_or(backoff.max_interval);
+                log::warn!(
+                    "Could not embed batch of {} chunks (attempt {} of {}), retrying in {}ms. \
+                    Error: {}",
+                    batch.len(),
+                    attempt,
+                    EMBEDDING_BATCH_ATTEMPTS,
+                    delay.as_millis(),
+                    e
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
         }
     }
 }
 
+/// Embeds chunks in batches, running the batches concurrently. The rate limiter paces the requests
+/// so this does not flood the provider, and the embeddings come back in the same order as the chunks.
+/// A failing batch is retried, and if it still fails every batch is waited for before the error is
+/// returned, so the embeddings that were made are in the embedding cache for the next attempt.
 pub async fn embed_text_chunks_async(
     table_chunks: Vec<String>,
     model: &Model,
+    datasource_id: Option<&str>,
 ) -> Result<Vec<Vec<f32>>> {
-    let mut list_of_embeddings: Vec<Vec<f32>> = vec![];
+    let tasks: Vec<_> = table_chunks
+        .chunks(EMBEDDING_BATCH_SIZE)
+        .map(|batch| {
+            task::spawn(embed_batch_with_retry(
+                batch.to_vec(),
+                model.clone(),
+                datasource_id.map(str::to_string),
+            ))
+        })
+        .collect();
 
-    let (tx, mut rx) = mpsc::channel(table_chunks.len()); // Channel with enough capacity
-
-    for item in table_chunks {
-        let tx = tx.clone(); // Clone the transmitter for each task
-        let item = arc::new(item); // Use Arc to share ownership across tasks, avoiding cloning large data
-        let model_clone = model.clone();
-        task::spawn(async move {
-            tx.send(embed_text(vec![&item], &model_clone).await)
-                .await
-                .expect("Failed to send processed item"); // Send back the result
-        });
+    let mut list_of_embeddings: Vec<Vec<f32>> = Vec::with_capacity(table_chunks.len());
+    let mut first_error = None;
+    for task in tasks {
+        match task.await? {
+            Ok(embeddings) => list_of_embeddings.extend(embeddings),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
     }
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(list_of_embeddings),
+    }
+}
 
-    // Drop the original transmitter so the receiver knows when all tasks are done
-    drop(tx);
+/// Embedding of one input text. Texts split under `EmbeddingOverflowPolicy::Split` have one entry
+/// per piece; otherwise there is a single entry holding the whole text.
+#[derive(Debug, Clone)]
+pub struct TextEmbedding {
+    pub text: String,
+    pub vector: Vec<f32>,
+}
 
-    // Collect the results
-    while let Some(processed_item) = rx.recv().await {
-        if let Ok(embed) = processed_item {
-            list_of_embeddings.push(embed[0].clone())
+/// Token weighted mean of the piece embeddings. With `normalize` the mean is scaled back to unit
+/// length, to match models whose embeddings are.
+fn average_embeddings(pieces: &[TextPiece], vectors: &[Vec<f32>], normalize: bool) -> Vec<f32> {
+    let dimensions = vectors.first().map_or(0, |vector| vector.len());
+    let mut average = vec![0.0; dimensions];
+    for (piece, vector) in pieces.iter().zip(vectors) {
+        for (total, value) in average.iter_mut().zip(vector) {
+            *total += value * piece.tokens.max(1) as f32;
+        }
+    }
+    if !normalize {
+        return average;
+    }
+    let norm = average
+        .iter()
+        .map(|value| value * value)
+        .sum::<f32>()
+        .sqrt();
+    if norm > 0.0 {
+        average.iter_mut().for_each(|value| *value /= norm);
+    }
+    average
+}
+
+/// Embeds `texts`, dealing with texts longer than the model's context according to `policy`.
+/// Returns the embeddings for each text in the same order as `texts`.
+pub async fn embed_text_with_overflow_policy(
+    texts: Vec<String>,
+    model: &Model,
+    policy: EmbeddingOverflowPolicy,
+    datasource_id: Option<&str>,
+) -> Result<Vec<Vec<TextEmbedding>>> {
+    let tokenizer = tokenizer_for(&model.model).await?;
+    let normalize = EmbeddingModels::from(model.model.clone()).normalizes_output();
+    let pieces_per_text: Vec<Vec<TextPiece>> = {
+        let texts = texts.clone();
+        task::spawn_blocking(move || {
+            texts
+                .iter()
+                .map(|text| match policy {
+                    EmbeddingOverflowPolicy::Truncate => {
+                        tokenizer.truncate(text).map(|piece| vec![piece])
+                    }
+                    EmbeddingOverflowPolicy::Average | EmbeddingOverflowPolicy::Split => {
+                        tokenizer.split(text)
+                    }
+                })
+                .collect::<Result<_>>()
+        })
+        .await??
+    };
+    let all_pieces: Vec<String> = pieces_per_text
+        .iter()
+        .flatten()
+        .map(|piece| piece.text.clone())
+        .collect();
+    let mut vectors = embed_text_chunks_async(all_pieces, model, datasource_id)
+        .await?
+        .into_iter();
+    let mut embeddings = Vec::with_capacity(texts.len());
+    for (text, pieces) in texts.into_iter().zip(pieces_per_text) {
+        let piece_vectors: Vec<Vec<f32>> = vectors.by_ref().take(pieces.len()).collect();
+        if piece_vectors.len() != pieces.len() {
+            return Err(anyhow!("Model returned fewer embeddings than texts sent"));
         }
+        let text_embeddings = match policy {
+            EmbeddingOverflowPolicy::Split if pieces.len() > 1 => pieces
+                .into_iter()
+                .zip(piece_vectors)
+                .map(|(piece, vector)| TextEmbedding {
+                    text: piece.text,
+                    vector,
+                })
+                .collect(),
+            _ => vec![TextEmbedding {
+                vector: average_embeddings(&pieces, &piece_vectors, normalize),
+                text,
+            }],
+        };
+        embeddings.push(text_embeddings);
     }
+    Ok(embeddings)
+}
 
-    Ok(list_of_embeddings)
+/// Points for a text that was split because it is longer than the embedding model accepts. Each
+/// part is a point of its own holding its piece of the text, linked to the others by the point ID
+/// the whole text would have had.
+pub fn split_text_points(
+    hashing_salt: &str,
+    index: Option<Value>,
+    mut payload: HashMap<String, Value>,
+    parts: Vec<TextEmbedding>,
+) -> Vec<Point> {
+    let parent_id = match index {
+        Some(Value::String(id)) => id,
+        Some(id) => id.to_string().trim_matches('"').to_string(),
+        None => Uuid::new_v4().to_string(),
+    };
+    let is_keyed = payload.contains_key("index");
+    // The content hash only describes whole rows, see `reuse_unchanged_embedding`
+    payload.remove(CONTENT_HASH_PAYLOAD_FIELD);
+    let part_count = parts.len();
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(part, embedding)| {
+            let id = part_point_id(hashing_salt, &parent_id, part);
+            let mut payload = payload.clone();
+            payload.insert("page_content".to_string(), Value::String(embedding.text));
+            if is_keyed {
+                payload.insert("index".to_string(), Value::String(id.clone()));
+            }
+            payload.insert(
+                PARENT_ID_PAYLOAD_FIELD.to_string(),
+                Value::String(parent_id.clone()),
+            );
+            payload.insert(PART_INDEX_PAYLOAD_FIELD.to_string(), Value::from(part));
+            payload.insert(
+                PART_COUNT_PAYLOAD_FIELD.to_string(),
+                Value::from(part_count),
+            );
+            Point::new(Some(Value::String(id)), embedding.vector, Some(payload))
+        })
+        .collect()
 }
 
+/// IDs of the points a row is stored as from chunk `first_chunk` on, along with their parts.
+/// Chunk IDs follow from the row's ID, so the chunks are found by looking up one ID after another
+/// until one is missing.
+pub async fn row_point_ids(
+    vector_database_client: &dyn VectorDatabase,
+    search_request: &SearchRequest,
+    hashing_salt: &str,
+    row_id: &str,
+    first_chunk: usize,
+) -> Vec<String> {
+    let mut point_ids = vec![];
+    for chunk in first_chunk.. {
+        let chunk_id = chunk_point_id(hashing_salt, row_id, chunk);
+        let point = match vector_database_client
+            .get_point(search_request.clone(), chunk_id.clone(), false)
+            .await
+        {
+            Ok(Some(point)) => point,
+            _ => break,
+        };
+        let part_count = point
+            .payload
+            .and_then(|payload| payload.get(PART_COUNT_PAYLOAD_FIELD)?.as_u64())
+            .unwrap_or(1) as usize;
+        point_ids.extend((1..part_count).map(|part| part_point_id(hashing_salt, &chunk_id, part)));
+        point_ids.push(chunk_id);
+    }
+    point_ids
+}
+
+/// Deletes the chunks a row was previously written as beyond the `chunk_count` it is now chunked
+/// into, along with their parts
+async fn remove_stale_chunks(
+    vector_database_client: &dyn VectorDatabase,
+    search_request: &SearchRequest,
+    hashing_salt: &str,
+    row_id: &str,
+    chunk_count: usize,
+) {
+    let stale_ids = row_point_ids(
+        vector_database_client,
+        search_request,
+        hashing_salt,
+        row_id,
+        chunk_count.max(1),
+    )
+    .await;
+    if stale_ids.is_empty() {
+        return;
+    }
+    if let Err(e) = vector_database_client
+        .delete_points_by_ids(search_request.clone(), stale_ids)
+        .await
+    {
+        log::warn!(
+            "Could not remove old chunks of row: {}. Error: {}",
+            row_id,
+            e
+        );
+    }
+}
+
+/// Embeds the chunks of a document and writes them as points. Returns the IDs of the points
+/// written, or `None` if they could not be.
 pub async fn embed_bulk_insert_unstructured_response(
     documents: Vec<UnstructuredIOResponse>,
     datasource: DataSources,
@@ -214,12 +528,41 @@ pub async fn embed_bulk_insert_unstructured_response(
     embedding_model: Model,
     metadata: Option<HashMap<String, Value>>,
     search_type: SearchType,
-) {
+) -> Option<Vec<String>> {
     let mongo_connection = mongo_client.read().await;
     let list_of_text: Vec<String> = documents.iter().map(|doc| doc.text.clone()).collect();
     let datasource_id = datasource.id.to_string();
-    match embed_text_chunks_async(list_of_text.clone(), &embedding_model).await {
+    match embed_text_with_overflow_policy(
+        list_of_text,
+        &embedding_model,
+        datasource.overflow_policy.unwrap_or_default(),
+        Some(&datasource_id),
+    )
+    .await
+    {
         Ok(embeddings) => {
+            let hashing_salt = GLOBAL_DATA.read().await.hashing_salt.clone();
+            // Chunks of a row are keyed on the row's ID, those of a file get random IDs. Either
+            // way they are known up front so each chunk can point at its neighbours.
+            let row_id = metadata
+                .as_ref()
+                .and_then(|metadata| metadata.get("index"))
+                .map(|id| match id {
+                    Value::String(s) => s.clone(),
+                    _ => id.to_string().trim_matches('"').to_string(),
+                });
+            let chunk_ids: Vec<String> = (0..documents.len())
+                .map(|i| match &row_id {
+                    Some(row_id) => chunk_point_id(&hashing_salt, row_id, i),
+                    None => Uuid::new_v4().to_string(),
+                })
+                .collect();
+            let document_id = metadata
+                .as_ref()
+                .and_then(|metadata| metadata.get(DOCUMENT_ID_PAYLOAD_FIELD))
+                .and_then(Value::as_str)
+                .map(String::from)
+                .or(row_id.clone());
             let mut search_request = SearchRequest::new(
                 search_type.clone(),
                 datasource
@@ -250,22 +593,51 @@ pub async fn embed_bulk_insert_unstructured_response(
                     point_metadata.extend(existing_metadata);
                 }
 
-                let embedding_vector = embeddings.get(i);
-                if let Some(vector) = embedding_vector {
-                    let point = Point::new(
-                        point_metadata.get("index").map_or_else(
-                            || Some(Value::String(Uuid::new_v4().to_string())),
-                            |id| match id {
-                                Value::String(s) => Some(Value::String(s.clone())),
-                                _ => Some(Value::String(
-                                    id.to_string().trim_matches('"').to_string(),
-                                )),
-                            },
-                        ),
-                        vector.to_vec(),
-                        Some(point_metadata),
+                if let Some(document_id) = &document_id {
+                    // A title opens the section the elements after it fall under
+                    let title_id = match document.field_type.as_str() {
+                        "Title" => Some(document.element_id.as_str()),
+                        _ => document.metadata.parent_id.as_deref(),
+                    };
+                    point_metadata.insert(
+                        DOCUMENT_ID_PAYLOAD_FIELD.to_string(),
+                        Value::String(document_id.clone()),
+                    );
+                    point_metadata.insert(
+                        SECTION_ID_PAYLOAD_FIELD.to_string(),
+                        Value::String(section_id(&hashing_salt, document_id, title_id)),
+                    );
+                }
+                point_metadata.insert(CHUNK_INDEX_PAYLOAD_FIELD.to_string(), Value::from(i));
+                if let Some(previous_id) = i.checked_sub(1).map(|previous| &chunk_ids[previous]) {
+                    point_metadata.insert(
+                        PREVIOUS_CHUNK_ID_PAYLOAD_FIELD.to_string(),
+                        Value::String(previous_id.clone()),
                     );
-                    points_to_upload.push(point)
+                }
+                if let Some(next_id) = chunk_ids.get(i + 1) {
+                    point_metadata.insert(
+                        NEXT_CHUNK_ID_PAYLOAD_FIELD.to_string(),
+                        Value::String(next_id.clone()),
+                    );
+                }
+                if row_id.is_some() {
+                    point_metadata.insert("index".to_string(), Value::String(chunk_ids[i].clone()));
+                }
+
+                if let Some(parts) = embeddings.get(i) {
+                    let index = Some(Value::String(chunk_ids[i].clone()));
+                    if let [part] = parts.as_slice() {
+                        let point = Point::new(index, part.vector.to_vec(), Some(point_metadata));
+                        points_to_upload.push(point)
+                    } else {
+                        points_to_upload.extend(split_text_points(
+                            &hashing_salt,
+                            index,
+                            point_metadata,
+                            parts.to_vec(),
+                        ))
+                    }
                 }
             }
 
@@ -277,6 +649,7 @@ pub async fn embed_bulk_insert_unstructured_response(
             let vector_database = Arc::clone(&vector_database_client);
             let vector_database_client = vector_database.read().await;
 
+            let point_ids = point_ids(&points_to_upload);
             if let Ok(bulk_insert_status) = vector_database_client
                 .bulk_insert_points(search_request.clone(), points_to_upload)
                 .await
@@ -284,21 +657,26 @@ pub async fn embed_bulk_insert_unstructured_response(
                 match bulk_insert_status {
                     VectorDatabaseStatus::Ok => {
                         log::debug!("points uploaded successfully!");
-
-                        increment_by_one(&mongo_connection, &datasource_id, "recordCount.success")
-                            .await
-                            .unwrap();
+                        // A row chunked into fewer chunks than before leaves the rest behind
+                        if let Some(row_id) = &row_id {
+                            remove_stale_chunks(
+                                &*vector_database_client,
+                                &search_request,
+                                &hashing_salt,
+                                row_id,
+                                chunk_ids.len(),
+                            )
+                            .await;
+                        }
+                        increment_record_count(&datasource_id, "recordCount.success");
+                        return Some(point_ids);
                     }
                     VectorDatabaseStatus::Failure | VectorDatabaseStatus::NotFound => {
-                        increment_by_one(&mongo_connection, &datasource_id, "recordCount.failure")
-                            .await
-                            .unwrap();
+                        increment_record_count(&datasource_id, "recordCount.failure");
                         log::warn!("Could not find collection :{}", datasource_id);
                     }
                     VectorDatabaseStatus::Error(e) => {
-                        increment_by_one(&mongo_connection, &datasource_id, "recordCount.failure")
-                            .await
-                            .unwrap();
+                        increment_record_count(&datasource_id, "recordCount.failure");
                         log::error!(
                             "An error occurred while attempting point insert operation. Error: {:?}",
                             e
@@ -309,4 +687,5 @@ pub async fn embed_bulk_insert_unstructured_response(
         }
         Err(e) => log::error!("An error occurred while embedding text. Error: {}", e),
     }
+    None
 }
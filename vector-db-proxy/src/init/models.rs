@@ -12,6 +12,13 @@ pub struct GlobalData {
     pub rabbitmq_routing_key: String,
     pub rabbitmq_username: String,
     pub rabbitmq_password: String,
+    pub rabbitmq_prefetch_count: u16,
+    pub pubsub_topic: String,
+    pub pubsub_subscription: String,
+    pub pubsub_concurrent_handlers: usize,
+    pub pubsub_max_outstanding_messages: i64,
+    pub pubsub_max_outstanding_bytes: i64,
+    pub pubsub_ack_deadline_secs: i32,
     pub mongo_uri: String,
     pub mongo_db_name: String,
     pub qdrant_host: String,
@@ -20,17 +27,37 @@ pub struct GlobalData {
     pub webapp_port: String,
     pub redis_host: String,
     pub redis_port: String,
+    pub redis_password: String,
     pub thread_percentage_utilisation: f64,
     pub number_of_threads: f64,
+    pub ingestion_queue_capacity: usize,
+    pub scheduler_team_weights: String,
+    pub scheduler_default_team_weight: u32,
+    pub scheduler_interactive_weight: u32,
+    pub shutdown_grace_period_secs: u64,
+    pub record_count_flush_interval_secs: u64,
+    pub sync_swap_quiet_period_secs: u64,
     pub use_gpu: String,
     pub logging_level: String,
     pub message_queue_provider: String,
     pub unstructuredio_url: String,
     pub unstructuredio_api_key: String,
+    pub unstructuredio_max_concurrent_requests: usize,
+    pub unstructuredio_pdf_pages_per_request: usize,
+    pub unstructuredio_timeout_secs: u64,
     pub vector_database: String,
     pub vector_database_api_key: String,
     pub vector_database_url: String,
     pub hashing_salt: String,
+    pub embedding_cache: String,
+    pub embedding_cache_capacity: usize,
+    pub embedding_cache_dir: String,
+    pub embedding_cache_ttl_secs: u64,
+    pub embedding_cache_disk_max_mb: u64,
+    pub embedding_cache_isolation: String,
+    pub embedding_cache_isolated_teams: String,
+    pub embedding_requests_per_minute: u64,
+    pub embedding_tokens_per_minute: u64,
 }
 
 impl GlobalData {
@@ -49,6 +76,33 @@ impl GlobalData {
             rabbitmq_username: dotenv::var("RABBITMQ_USERNAME").unwrap_or("agentcloud".to_string()),
             rabbitmq_password: dotenv::var("RABBITMQ_PASSWORD")
                 .unwrap_or("alphanumeric123".to_string()),
+            rabbitmq_prefetch_count: dotenv::var("RABBITMQ_PREFETCH_COUNT")
+                .unwrap_or("100".to_string())
+                .parse()
+                .unwrap_or(100),
+            // Fall back to the RabbitMQ stream name so existing deployments keep working
+            pubsub_topic: dotenv::var("PUBSUB_TOPIC")
+                .or_else(|_| dotenv::var("RABBITMQ_STREAM"))
+                .unwrap_or("streaming".to_string()),
+            pubsub_subscription: dotenv::var("PUBSUB_SUBSCRIPTION")
+                .or_else(|_| dotenv::var("RABBITMQ_STREAM"))
+                .unwrap_or("streaming".to_string()),
+            pubsub_concurrent_handlers: dotenv::var("PUBSUB_CONCURRENT_HANDLERS")
+                .unwrap_or("10".to_string())
+                .parse()
+                .unwrap_or(10),
+            pubsub_max_outstanding_messages: dotenv::var("PUBSUB_MAX_OUTSTANDING_MESSAGES")
+                .unwrap_or("1000".to_string())
+                .parse()
+                .unwrap_or(1000),
+            pubsub_max_outstanding_bytes: dotenv::var("PUBSUB_MAX_OUTSTANDING_BYTES")
+                .unwrap_or("1000000000".to_string())
+                .parse()
+                .unwrap_or(1_000_000_000),
+            pubsub_ack_deadline_secs: dotenv::var("PUBSUB_ACK_DEADLINE_SECS")
+                .unwrap_or("60".to_string())
+                .parse()
+                .unwrap_or(60),
             mongo_uri: dotenv::var("MONGO_URI").unwrap_or("mongodb://localhost:27017".to_string()),
             mongo_db_name: dotenv::var("MONGO_DB_NAME").unwrap_or("agentcloud".to_string()),
             qdrant_host: dotenv::var("QDRANT_HOST").unwrap_or("http://localhost".to_string()),
@@ -57,6 +111,7 @@ impl GlobalData {
             webapp_port: dotenv::var("WEBAPP_PORT").unwrap_or("3000".to_string()),
             redis_host: dotenv::var("REDIS_HOST").unwrap_or("localhost".to_string()),
             redis_port: dotenv::var("REDIS_PORT").unwrap_or("6379".to_string()),
+            redis_password: dotenv::var("REDIS_PASS").unwrap_or_default(),
             thread_percentage_utilisation: dotenv::var("THREAD_PERCENTAGE_UTILISATION")
                 .unwrap_or("1".to_string())
                 .parse()
@@ -64,17 +119,87 @@ impl GlobalData {
             number_of_threads: available_parallelism()
                 .map(|t| t.get() as f64)
                 .unwrap_or(12.0),
+            ingestion_queue_capacity: dotenv::var("INGESTION_QUEUE_CAPACITY")
+                .unwrap_or("100".to_string())
+                .parse()
+                .unwrap_or(100),
+            scheduler_team_weights: dotenv::var("SCHEDULER_TEAM_WEIGHTS").unwrap_or_default(),
+            scheduler_default_team_weight: dotenv::var("SCHEDULER_DEFAULT_TEAM_WEIGHT")
+                .unwrap_or("1".to_string())
+                .parse()
+                .unwrap_or(1),
+            scheduler_interactive_weight: dotenv::var("SCHEDULER_INTERACTIVE_WEIGHT")
+                .unwrap_or("4".to_string())
+                .parse()
+                .unwrap_or(4),
+            shutdown_grace_period_secs: dotenv::var("SHUTDOWN_GRACE_PERIOD_SECS")
+                .unwrap_or("30".to_string())
+                .parse()
+                .unwrap_or(30),
+            record_count_flush_interval_secs: dotenv::var("RECORD_COUNT_FLUSH_INTERVAL_SECS")
+                .unwrap_or("2".to_string())
+                .parse()
+                .unwrap_or(2),
+            sync_swap_quiet_period_secs: dotenv::var("SYNC_SWAP_QUIET_PERIOD_SECS")
+                .unwrap_or("30".to_string())
+                .parse()
+                .unwrap_or(30),
             use_gpu: dotenv::var("USE_GPU").unwrap_or("false".to_string()),
             logging_level: dotenv::var("LOGGING_LEVEL").unwrap_or("debug".to_string()),
             message_queue_provider: dotenv::var("MESSAGE_QUEUE_PROVIDER")
                 .unwrap_or("rabbitmq".to_string()),
+            // Several endpoints may be given separated by commas, later ones are used when the
+            // ones before them are unavailable
             unstructuredio_url: dotenv::var("UNSTRUCTURED_API_URL")
                 .unwrap_or("http://localhost:9500/general/v0/general".to_string()),
             unstructuredio_api_key: dotenv::var("UNSTRUCTURED_API_KEY").unwrap_or(String::new()),
+            unstructuredio_max_concurrent_requests: dotenv::var(
+                "UNSTRUCTURED_API_MAX_CONCURRENT_REQUESTS",
+            )
+            .unwrap_or("4".to_string())
+            .parse()
+            .unwrap_or(4),
+            unstructuredio_pdf_pages_per_request: dotenv::var(
+                "UNSTRUCTURED_API_PDF_PAGES_PER_REQUEST",
+            )
+            .unwrap_or("20".to_string())
+            .parse()
+            .unwrap_or(20),
+            unstructuredio_timeout_secs: dotenv::var("UNSTRUCTURED_API_TIMEOUT_SECS")
+                .unwrap_or("600".to_string())
+                .parse()
+                .unwrap_or(600),
             vector_database: dotenv::var("VECTOR_DATABASE").unwrap_or("qdrant".to_string()),
             vector_database_api_key: dotenv::var("VECTOR_DATABASE_API_KEY").unwrap_or_default(),
             vector_database_url: dotenv::var("VECTOR_DATABASE_URL").unwrap_or_default(),
             hashing_salt: dotenv::var("HASHING_SALT").unwrap_or("something_secretive".to_string()),
+            embedding_cache: dotenv::var("EMBEDDING_CACHE").unwrap_or("memory".to_string()),
+            embedding_cache_capacity: dotenv::var("EMBEDDING_CACHE_CAPACITY")
+                .unwrap_or("10000".to_string())
+                .parse()
+                .unwrap_or(10000),
+            embedding_cache_dir: dotenv::var("EMBEDDING_CACHE_DIR")
+                .unwrap_or("embedding_cache".to_string()),
+            embedding_cache_ttl_secs: dotenv::var("EMBEDDING_CACHE_TTL_SECS")
+                .unwrap_or("604800".to_string())
+                .parse()
+                .unwrap_or(604800),
+            embedding_cache_disk_max_mb: dotenv::var("EMBEDDING_CACHE_DISK_MAX_MB")
+                .unwrap_or("1024".to_string())
+                .parse()
+                .unwrap_or(1024),
+            embedding_cache_isolation: dotenv::var("EMBEDDING_CACHE_ISOLATION")
+                .unwrap_or("shared".to_string()),
+            embedding_cache_isolated_teams: dotenv::var("EMBEDDING_CACHE_ISOLATED_TEAMS")
+                .unwrap_or_default(),
+            embedding_requests_per_minute: dotenv::var("EMBEDDING_RATE_LIMIT_RPM")
+                .unwrap_or("3000".to_string())
+                .parse()
+                .unwrap_or(3000),
+            embedding_tokens_per_minute: dotenv::var("EMBEDDING_RATE_LIMIT_TPM")
+                .unwrap_or("1000000".to_string())
+                .parse()
+                .unwrap_or(1000000),
         }
     }
 }
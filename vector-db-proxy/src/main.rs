@@ -4,14 +4,12 @@
 #![allow(unused_assignments)]
 
 use std::sync::Arc;
-use std::thread;
+use std::time::Duration;
 
 use actix_cors::Cors;
 use actix_web::{middleware::Logger, web, App, HttpServer};
 use anyhow::Context;
-use crossbeam::channel;
 use env_logger::Env;
-use tokio::signal;
 use tokio::sync::RwLock;
 
 use routes::apis::{
@@ -19,13 +17,20 @@ use routes::apis::{
     get_collection_info, health_check, list_collections, upsert_data_point_to_collection,
 };
 
-use crate::adaptors::mongo::models::DataSources;
 use crate::data::processing_incoming_messages::process_incoming_messages;
+use crate::data::record_counts::{flush_record_counts, flush_record_counts_periodically};
+use crate::embeddings::usage::{flush_embedding_usage, flush_embedding_usage_periodically};
 use crate::init::env_variables::set_all_env_vars;
 use crate::init::env_variables::GLOBAL_DATA;
+use crate::init::shutdown::{wait_for_shutdown_signal, Shutdown};
 use crate::messages::models::{MessageQueue, MessageQueueProvider};
+use crate::messages::scheduler::{FairScheduler, SchedulerConfig};
 use crate::messages::tasks::get_message_queue;
-use crate::routes::apis::{create_collection, get_storage_size, scroll_data};
+use crate::routes::apis::{
+    chunk_preview, create_collection, delete_datasource_document, get_embedding_cache_metrics,
+    get_storage_size, get_team_embedding_usage, list_documents, migrate_datasource_point_ids,
+    scroll_data, sync_complete,
+};
 use adaptors::mongo::client::start_mongo_connection;
 
 mod adaptors;
@@ -37,6 +42,8 @@ mod routes;
 mod utils;
 mod vector_databases;
 
+const MAX_CHUNK_PREVIEW_BODY_BYTES: usize = 64 * 1024 * 1024;
+
 pub fn init(config: &mut web::ServiceConfig) {
     let cors = Cors::default()
         .allow_any_origin()
@@ -56,11 +63,24 @@ pub fn init(config: &mut web::ServiceConfig) {
             .service(bulk_upsert_data_to_collection)
             .service(scroll_data)
             .service(get_collection_info)
-            .service(get_storage_size),
+            .service(get_storage_size)
+            .service(get_team_embedding_usage)
+            .service(sync_complete)
+            .service(migrate_datasource_point_ids)
+            .service(get_embedding_cache_metrics)
+            .service(list_documents)
+            .service(delete_datasource_document)
+            // Files to preview the chunks of are sent base64 encoded in the body, so only this
+            // route takes bodies larger than the default limit
+            .service(
+                web::resource("/chunk-preview")
+                    .app_data(web::JsonConfig::default().limit(MAX_CHUNK_PREVIEW_BODY_BYTES))
+                    .route(web::post().to(chunk_preview)),
+            ),
     );
 }
 
-#[actix_web::main]
+#[tokio::main]
 async fn main() -> std::io::Result<()> {
     log::info!("Starting Vector DB Proxy APP...");
     let global_data = GLOBAL_DATA.read().await;
@@ -84,9 +104,14 @@ async fn main() -> std::io::Result<()> {
     // qdrant_client implements VectorDatabase
     let mongo_client_for_streaming = Arc::clone(&app_mongo_client);
 
-    // Clones of the receiver and sender so that they can be sent to the right threads
-    let (s, r) = channel::unbounded::<(DataSources, Option<String>, String)>();
-    let sender_clone = s.clone();
+    // Bounded, fair scheduler between the message queue consumers and the workers. When it is full
+    // the consumers stop pulling messages so memory usage stays flat during large syncs
+    let scheduler = Arc::new(FairScheduler::new(SchedulerConfig::from_global_data(
+        &global_data,
+    )));
+    let scheduler_for_streaming = Arc::clone(&scheduler);
+    let shutdown = Shutdown::new();
+    let shutdown_for_streaming = shutdown.clone();
 
     // This is to allow the use of multiple message queues
     let message_queue_provider =
@@ -100,30 +125,39 @@ async fn main() -> std::io::Result<()> {
                 connection.clone(),
                 //vector_database_for_streaming,
                 mongo_client_for_streaming,
-                sender_clone,
+                scheduler_for_streaming,
+                shutdown_for_streaming,
             )
             .await;
     });
     // Figure out how many threads are available on the machine and the percentage of those that the user would like to use when syncing data
     let number_of_workers =
-        (global_data.number_of_threads * global_data.thread_percentage_utilisation) as i32;
-    println!("{} threads available for work", number_of_workers);
-    // Thread for receiving messages in channel and processing them across workers
-    // Spawn multiple threads to process messages
-    let mut handles = vec![];
-    for _ in 0..(number_of_workers * 10) {
-        // let receiver_clone = receiver.clone();
-        //let vector_database_client_clone = Arc::clone(&vector_database_client);
-        let mongo_client_clone = Arc::clone(&app_mongo_client);
-        let receiver = r.clone();
-        let handle = thread::spawn(move || {
-            let rt = tokio::runtime::Runtime::new().unwrap();
-            rt.block_on(async {
-                process_incoming_messages(receiver, mongo_client_clone).await;
-            });
-        });
-        handles.push(handle);
-    }
+        (global_data.number_of_threads * global_data.thread_percentage_utilisation) as usize;
+    println!("{} workers available for work", number_of_workers);
+    // Task for receiving messages from the queue and processing them across the worker pool
+    let mongo_client_for_workers = Arc::clone(&app_mongo_client);
+    let shutdown_for_workers = shutdown.clone();
+    let process_messages = tokio::spawn(async move {
+        process_incoming_messages(
+            scheduler,
+            mongo_client_for_workers,
+            number_of_workers,
+            shutdown_for_workers,
+        )
+        .await;
+    });
+    // Record counters and embedding usage are buffered in memory and written to Mongo in batches
+    let flush_interval = Duration::from_secs(global_data.record_count_flush_interval_secs.max(1));
+    let flush_counts = tokio::spawn(flush_record_counts_periodically(
+        Arc::clone(&app_mongo_client),
+        flush_interval,
+    ));
+    let flush_usage = tokio::spawn(flush_embedding_usage_periodically(
+        Arc::clone(&app_mongo_client),
+        flush_interval,
+    ));
+    let grace_period = Duration::from_secs(global_data.shutdown_grace_period_secs);
+    drop(global_data);
 
     // Set the default logging level
     env_logger::Builder::from_env(Env::default().default_filter_or(logging_level)).init();
@@ -144,9 +178,22 @@ async fn main() -> std::io::Result<()> {
     tokio::select! {
         _ = web_task => log::info!("Web server task completed"),
         _ = subscribe_to_message_stream => log::info!("Message stream task completed"),
-        _ = signal::ctrl_c() => {
-            log::info!("Received Ctrl+C, shutting down");
-        }
+        _ = wait_for_shutdown_signal() => {}
+    }
+
+    // Stop consuming, let in-flight work finish (or return it to the queue once the grace period
+    // is up) and then write out whatever record counts are still buffered
+    shutdown.trigger();
+    if tokio::time::timeout(grace_period + Duration::from_secs(5), process_messages)
+        .await
+        .is_err()
+    {
+        log::warn!("Ingestion workers did not stop in time");
     }
+    flush_counts.abort();
+    flush_usage.abort();
+    flush_record_counts(&*app_mongo_client.read().await).await;
+    flush_embedding_usage(&*app_mongo_client.read().await).await;
+    log::info!("Shutdown complete");
     Ok(())
 }
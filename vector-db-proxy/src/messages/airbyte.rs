@@ -0,0 +1,146 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// Field the Airbyte PubSub destination wraps each row in
+const AIRBYTE_DATA_FIELD: &str = "_airbyte_data";
+
+/// The Airbyte protocol messages a destination receives. Any other message type coming through the
+/// stream is ignored.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AirbyteMessage {
+    Record { record: AirbyteRecord },
+    State { state: AirbyteState },
+    Trace { trace: AirbyteTrace },
+    Log { log: Value },
+    Control { control: Value },
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AirbyteRecord {
+    #[serde(default)]
+    pub stream: Option<String>,
+    #[serde(default)]
+    pub namespace: Option<String>,
+    pub data: Map<String, Value>,
+    #[serde(default)]
+    pub emitted_at: Option<i64>,
+}
+
+/// A checkpoint emitted by the source. Everything the source sent before it has to be written
+/// before it can be persisted, otherwise a resumed sync would skip rows that were never embedded.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AirbyteState {
+    /// One of `STREAM`, `GLOBAL` or `LEGACY`. Legacy states have no type.
+    #[serde(rename = "type", default)]
+    pub state_type: Option<String>,
+    #[serde(default)]
+    pub stream: Option<Value>,
+    #[serde(flatten)]
+    pub extra_fields: Map<String, Value>,
+}
+
+impl AirbyteState {
+    /// Per-stream states are stored under the stream name, global and legacy states under their type
+    pub fn key(&self) -> String {
+        self.stream
+            .as_ref()
+            .and_then(|stream| stream.pointer("/stream_descriptor/name"))
+            .and_then(|name| name.as_str())
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| {
+                self.state_type
+                    .as_deref()
+                    .unwrap_or("LEGACY")
+                    .to_lowercase()
+            })
+    }
+
+    pub fn to_value(&self) -> Value {
+        let mut state = self.extra_fields.clone();
+        if let Some(state_type) = &self.state_type {
+            state.insert("type".to_string(), Value::String(state_type.clone()));
+        }
+        if let Some(stream) = &self.stream {
+            state.insert("stream".to_string(), stream.clone());
+        }
+        Value::Object(state)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AirbyteTrace {
+    Error { error: AirbyteErrorTrace },
+    Estimate { estimate: AirbyteEstimateTrace },
+    StreamStatus { stream_status: Value },
+    Analytics { analytics: Value },
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AirbyteErrorTrace {
+    pub message: String,
+    #[serde(default)]
+    pub internal_message: Option<String>,
+    #[serde(default)]
+    pub failure_type: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct AirbyteEstimateTrace {
+    pub name: String,
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// `STREAM` for an estimate of a single stream, `SYNC` for the whole sync
+    #[serde(rename = "type")]
+    pub estimate_type: String,
+    #[serde(default)]
+    pub row_estimate: Option<i64>,
+    #[serde(default)]
+    pub byte_estimate: Option<i64>,
+}
+
+impl AirbyteMessage {
+    /// Reads a message coming from an Airbyte stream. Producers that forward the full protocol send
+    /// typed messages, while the PubSub destination wraps the row in `_airbyte_data` and the
+    /// RabbitMQ destination sends the row as is. Only a `type` naming a message type along with
+    /// the object of that type, such as `{"type": "STATE", "state": {..}}`, makes a protocol
+    /// message. Anything else is treated as a row, so rows with a `type` column of their own keep
+    /// working.
+    pub fn from_stream_record(body: Value) -> Option<Self> {
+        if is_protocol_message(&body) {
+            if let Ok(message) = serde_json::from_value::<AirbyteMessage>(body.clone()) {
+                return Some(message);
+            }
+        }
+        let Value::Object(mut data) = body else {
+            return None;
+        };
+        if let Some(Value::Object(airbyte_data)) = data.get(AIRBYTE_DATA_FIELD) {
+            data = airbyte_data.clone();
+        }
+        Some(AirbyteMessage::Record {
+            record: AirbyteRecord {
+                stream: None,
+                namespace: None,
+                data,
+                emitted_at: None,
+            },
+        })
+    }
+}
+
+fn is_protocol_message(body: &Value) -> bool {
+    let Some(message_type) = body.get("type").and_then(Value::as_str) else {
+        return false;
+    };
+    let expected_field = match message_type {
+        "RECORD" => "record",
+        "STATE" => "state",
+        "TRACE" => "trace",
+        "LOG" => "log",
+        "CONTROL" => "control",
+        _ => return false,
+    };
+    body.get(expected_field).is_some_and(Value::is_object)
+}
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error as ThisError;
+use uuid::Uuid;
+
+/// Current version of the typed ingestion envelope.
+pub const ENVELOPE_VERSION: u8 = 1;
+/// Header used by producers to signal that the message body is a typed envelope.
+pub const ENVELOPE_VERSION_HEADER: &str = "envelope_version";
+/// Header that carries the trace ID when producers send the legacy header format.
+pub const TRACE_ID_HEADER: &str = "trace_id";
+
+// Legacy headers. RabbitMQ producers send `stream`, PubSub producers send `_stream`.
+const LEGACY_STREAM_HEADERS: [&str; 2] = ["stream", "_stream"];
+const LEGACY_FILE_TYPE_HEADER: &str = "type";
+
+#[derive(ThisError, Debug, Clone, PartialEq)]
+pub enum EnvelopeError {
+    #[error("message body is not valid UTF-8")]
+    NonUtf8Body,
+    #[error("message body is not valid JSON: {0}")]
+    InvalidBody(String),
+    #[error("unsupported envelope version: {0}")]
+    UnsupportedVersion(u8),
+    #[error("no stream header present in message headers")]
+    MissingStreamHeader,
+    #[error("stream '{0}' does not contain a stream config key")]
+    MissingStreamConfigKey(String),
+    #[error("'{0}' is not a valid datasource ID")]
+    InvalidDatasourceId(String),
+    #[error("file message for datasource '{0}' has no file reference")]
+    MissingFileReference(String),
+    #[error("stream record message for datasource '{0}' has no record")]
+    MissingRecord(String),
+}
+
+/// Where an ingestion message originated from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    /// A file uploaded through the webapp that needs to be read, partitioned and chunked
+    File,
+    /// A single row coming from an Airbyte sync
+    StreamRecord,
+}
+
+/// Location of an uploaded file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileReference {
+    /// Storage provider, one of `google` or `local`
+    pub provider: String,
+    /// Provider specific location, e.g. `{"bucket": ..., "filename": ...}` or `{"file": ...}`
+    pub location: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IngestionEnvelope {
+    pub version: u8,
+    pub datasource_id: String,
+    #[serde(default)]
+    pub stream_key: Option<String>,
+    pub source: SourceKind,
+    #[serde(default)]
+    pub file: Option<FileReference>,
+    #[serde(default)]
+    pub record: Option<Value>,
+    #[serde(default = "new_trace_id")]
+    pub trace_id: String,
+}
+
+fn new_trace_id() -> String {
+    Uuid::new_v4().to_string()
+}
+
+impl IngestionEnvelope {
+    /// Parses a message coming off the queue. Messages that carry the `envelope_version` header
+    /// are deserialized as a typed envelope, everything else is parsed from the legacy
+    /// `stream`/`_stream` and `type` headers.
+    pub fn parse(headers: &HashMap<String, String>, body: &[u8]) -> Result<Self, EnvelopeError> {
+        let body = std::str::from_utf8(body).map_err(|_| EnvelopeError::NonUtf8Body)?;
+        let envelope = match headers.get(ENVELOPE_VERSION_HEADER) {
+            Some(_) => serde_json::from_str::<IngestionEnvelope>(body)
+                .map_err(|e| EnvelopeError::InvalidBody(e.to_string()))?,
+            None => Self::from_legacy_headers(headers, body)?,
+        };
+        envelope.validate()
+    }
+
+    fn from_legacy_headers(
+        headers: &HashMap<String, String>,
+        body: &str,
+    ) -> Result<Self, EnvelopeError> {
+        let stream = LEGACY_STREAM_HEADERS
+            .iter()
+            .find_map(|h| headers.get(*h))
+            .map(|s| s.trim_matches('"').to_string())
+            .ok_or(EnvelopeError::MissingStreamHeader)?;
+        let body: Value =
+            serde_json::from_str(body).map_err(|e| EnvelopeError::InvalidBody(e.to_string()))?;
+        let trace_id = headers
+            .get(TRACE_ID_HEADER)
+            .cloned()
+            .unwrap_or_else(new_trace_id);
+
+        // If the type header is present the message is a file upload, otherwise it is a row
+        // coming from Airbyte with a stream name of the form `<datasource_id>_<stream_key>`
+        match headers.get(LEGACY_FILE_TYPE_HEADER) {
+            Some(provider) => {
+                let datasource_id = stream
+                    .split_once('_')
+                    .map_or(stream.as_str(), |(id, _)| id)
+                    .to_string();
+                Ok(IngestionEnvelope {
+                    version: ENVELOPE_VERSION,
+                    datasource_id,
+                    stream_key: None,
+                    source: SourceKind::File,
+                    file: Some(FileReference {
+                        provider: provider.trim_matches('"').to_string(),
+                        location: body,
+                    }),
+                    record: None,
+                    trace_id,
+                })
+            }
+            None => {
+                let (datasource_id, stream_key) = stream
+                    .split_once('_')
+                    .filter(|(_, key)| !key.is_empty())
+                    .ok_or_else(|| EnvelopeError::MissingStreamConfigKey(stream.clone()))?;
+                Ok(IngestionEnvelope {
+                    version: ENVELOPE_VERSION,
+                    datasource_id: datasource_id.to_string(),
+                    stream_key: Some(stream_key.to_string()),
+                    source: SourceKind::StreamRecord,
+                    file: None,
+                    record: Some(body),
+                    trace_id,
+                })
+            }
+        }
+    }
+
+    fn validate(self) -> Result<Self, EnvelopeError> {
+        if self.version != ENVELOPE_VERSION {
+            return Err(EnvelopeError::UnsupportedVersion(self.version));
+        }
+        if ObjectId::from_str(self.datasource_id.as_str()).is_err() {
+            return Err(EnvelopeError::InvalidDatasourceId(self.datasource_id));
+        }
+        match self.source {
+            SourceKind::File if self.file.is_none() => {
+                Err(EnvelopeError::MissingFileReference(self.datasource_id))
+            }
+            SourceKind::StreamRecord if self.record.is_none() => {
+                Err(EnvelopeError::MissingRecord(self.datasource_id))
+            }
+            SourceKind::StreamRecord if self.stream_key.is_none() => {
+                Err(EnvelopeError::MissingStreamConfigKey(self.datasource_id))
+            }
+            _ => Ok(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATASOURCE_ID: &str = "65f1c0ffee0123456789abcd";
+
+    fn headers(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_reads_legacy_stream_records() {
+        let stream = format!("\"{}_users\"", DATASOURCE_ID);
+        let headers = headers(&[("_stream", stream.as_str()), (TRACE_ID_HEADER, "trace")]);
+        let envelope = IngestionEnvelope::parse(&headers, br#"{"id": 1}"#).unwrap();
+        assert_eq!(envelope.datasource_id, DATASOURCE_ID);
+        assert_eq!(envelope.stream_key.as_deref(), Some("users"));
+        assert_eq!(envelope.source, SourceKind::StreamRecord);
+        assert_eq!(envelope.record, Some(serde_json::json!({"id": 1})));
+        assert_eq!(envelope.trace_id, "trace");
+    }
+
+    #[test]
+    fn parse_reads_legacy_file_uploads() {
+        let headers = headers(&[
+            ("stream", DATASOURCE_ID),
+            (LEGACY_FILE_TYPE_HEADER, "local"),
+        ]);
+        let envelope = IngestionEnvelope::parse(&headers, br#"{"file": "a.pdf"}"#).unwrap();
+        assert_eq!(envelope.source, SourceKind::File);
+        assert_eq!(
+            envelope.file,
+            Some(FileReference {
+                provider: "local".to_string(),
+                location: serde_json::json!({"file": "a.pdf"}),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_reads_typed_envelopes() {
+        let headers = headers(&[(ENVELOPE_VERSION_HEADER, "1")]);
+        let body = serde_json::json!({
+            "version": 1,
+            "datasource_id": DATASOURCE_ID,
+            "stream_key": "users",
+            "source": "stream_record",
+            "record": {"id": 1},
+            "trace_id": "trace",
+        });
+        let envelope = IngestionEnvelope::parse(&headers, body.to_string().as_bytes()).unwrap();
+        assert_eq!(envelope.version, ENVELOPE_VERSION);
+        assert_eq!(envelope.stream_key.as_deref(), Some("users"));
+        assert_eq!(envelope.record, Some(serde_json::json!({"id": 1})));
+        assert_eq!(envelope.trace_id, "trace");
+    }
+
+    #[test]
+    fn parse_rejects_unknown_versions() {
+        let headers = headers(&[(ENVELOPE_VERSION_HEADER, "2")]);
+        let body = serde_json::json!({
+            "version": 2,
+            "datasource_id": DATASOURCE_ID,
+            "source": "file",
+            "file": {"provider": "local", "location": {"file": "a.pdf"}},
+        });
+        assert_eq!(
+            IngestionEnvelope::parse(&headers, body.to_string().as_bytes()),
+            Err(EnvelopeError::UnsupportedVersion(2))
+        );
+    }
+
+    #[test]
+    fn parse_rejects_missing_datasource_ids() {
+        let typed = headers(&[(ENVELOPE_VERSION_HEADER, "1")]);
+        let body = serde_json::json!({"version": 1, "source": "stream_record", "record": {}});
+        assert!(matches!(
+            IngestionEnvelope::parse(&typed, body.to_string().as_bytes()),
+            Err(EnvelopeError::InvalidBody(_))
+        ));
+        assert_eq!(
+            IngestionEnvelope::parse(&headers(&[]), b"{}"),
+            Err(EnvelopeError::MissingStreamHeader)
+        );
+        let stream = headers(&[("stream", "_users")]);
+        assert_eq!(
+            IngestionEnvelope::parse(&stream, b"{}"),
+            Err(EnvelopeError::InvalidDatasourceId(String::new()))
+        );
+    }
+}
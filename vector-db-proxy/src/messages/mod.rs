@@ -1,3 +1,6 @@
+pub mod airbyte;
+pub mod envelope;
 pub mod models;
+pub mod scheduler;
 pub mod tasks;
 pub mod task_handoff;
@@ -1,14 +1,18 @@
 use std::sync::Arc;
+use std::time::Duration;
 
-use amqprs::channel::Channel;
-use crossbeam::channel::Sender;
+use amqprs::channel::{BasicAckArguments, BasicNackArguments, Channel};
+use google_cloud_pubsub::subscriber::ReceivedMessage;
 use google_cloud_pubsub::subscription::MessageStream;
 use mongodb::Database;
 use tokio::sync::{Mutex, RwLock};
+use tokio::task::AbortHandle;
+use tokio::time::interval;
 
 use crate::adaptors::gcp::models::pubsub_consume;
-use crate::adaptors::mongo::models::DataSources;
 use crate::adaptors::rabbitmq::models::rabbit_consume;
+use crate::init::shutdown::Shutdown;
+use crate::messages::scheduler::FairScheduler;
 
 #[derive(Clone, Copy, Debug)]
 pub enum MessageQueueProvider {
@@ -51,14 +55,15 @@ impl MessageQueue for QueueConnectionTypes {
         streaming_queue: Self::Queue,
         //vector_database_client: Arc<RwLock<dyn VectorDatabase>>,
         mongo_client: Arc<RwLock<Database>>,
-        sender: Sender<(DataSources, Option<String>, String)>,
+        scheduler: Arc<FairScheduler>,
+        shutdown: Shutdown,
     ) {
         match streaming_queue {
             QueueConnectionTypes::PubSub(stream) => {
-                pubsub_consume(&stream, mongo_client, sender).await;
+                pubsub_consume(&stream, mongo_client, scheduler, shutdown).await;
             }
             QueueConnectionTypes::RabbitMQ(channel) => {
-                rabbit_consume(&channel, mongo_client, sender).await;
+                rabbit_consume(&channel, mongo_client, scheduler, shutdown).await;
             }
         }
     }
@@ -74,6 +79,108 @@ pub trait MessageQueue {
         streaming_queue: Self::Queue,
         //vector_database_client: Arc<RwLock<dyn VectorDatabase>>,
         mongo_client: Arc<RwLock<Database>>,
-        sender: Sender<(DataSources, Option<String>, String)>,
+        scheduler: Arc<FairScheduler>,
+        shutdown: Shutdown,
     );
 }
+
+/// Lets a message be settled with the queue it came from once the work it describes has finished,
+/// rather than when it is received, so unfinished work is redelivered after a restart.
+pub enum AckHandle {
+    RabbitMQ {
+        channel: Channel,
+        delivery_tag: u64,
+    },
+    PubSub {
+        message: Arc<ReceivedMessage>,
+        /// Keeps extending the ack deadline while the message is queued or being processed
+        lease_extension: AbortHandle,
+    },
+    /// Not tied to a queue, for tasks built in tests
+    #[cfg(test)]
+    Detached,
+}
+
+impl AckHandle {
+    /// Wraps a PubSub message and extends its ack deadline every half deadline until it is acked
+    /// or nacked, so long running file processing does not cause the message to be redelivered
+    pub fn pubsub(message: ReceivedMessage, ack_deadline_seconds: i32) -> Self {
+        let message = Arc::new(message);
+        let message_to_extend = Arc::clone(&message);
+        let extension_interval = Duration::from_secs((ack_deadline_seconds / 2).max(1) as u64);
+        let lease_extension = tokio::spawn(async move {
+            let mut ticker = interval(extension_interval);
+            // The first tick completes immediately and the deadline has only just been set
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(e) = message_to_extend
+                    .modify_ack_deadline(ack_deadline_seconds)
+                    .await
+                {
+                    log::warn!("Could not extend PubSub ack deadline. Error: {}", e);
+                }
+            }
+        })
+        .abort_handle();
+        AckHandle::PubSub {
+            message,
+            lease_extension,
+        }
+    }
+
+    pub async fn ack(self) {
+        match self {
+            AckHandle::RabbitMQ {
+                channel,
+                delivery_tag,
+            } => {
+                if let Err(e) = channel
+                    .basic_ack(BasicAckArguments::new(delivery_tag, false))
+                    .await
+                {
+                    log::error!("Could not ack RabbitMQ message. Error: {}", e);
+                }
+            }
+            AckHandle::PubSub {
+                message,
+                lease_extension,
+            } => {
+                lease_extension.abort();
+                if let Err(e) = message.ack().await {
+                    log::error!("Could not ack PubSub message. Error: {}", e);
+                }
+            }
+            #[cfg(test)]
+            AckHandle::Detached => {}
+        }
+    }
+
+    /// Returns the message to the queue so it is redelivered
+    pub async fn nack(self) {
+        match self {
+            AckHandle::RabbitMQ {
+                channel,
+                delivery_tag,
+            } => {
+                if let Err(e) = channel
+                    .basic_nack(BasicNackArguments::new(delivery_tag, false, true))
+                    .await
+                {
+                    log::error!("Could not nack RabbitMQ message. Error: {}", e);
+                }
+            }
+            AckHandle::PubSub {
+                message,
+                lease_extension,
+            } => {
+                lease_extension.abort();
+                if let Err(e) = message.nack().await {
+                    log::error!("Could not nack PubSub message. Error: {}", e);
+                }
+            }
+            #[cfg(test)]
+            AckHandle::Detached => {}
+        }
+    }
+}
@@ -0,0 +1,418 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use tokio::sync::{Notify, Semaphore};
+
+use crate::adaptors::mongo::models::DataSources;
+use crate::init::models::GlobalData;
+use crate::messages::envelope::{IngestionEnvelope, SourceKind};
+use crate::messages::models::AckHandle;
+
+pub struct IngestionTask {
+    pub datasource: DataSources,
+    pub envelope: IngestionEnvelope,
+    /// Settles the originating message once the task has been processed
+    pub ack: AckHandle,
+    /// Position of an Airbyte record among the messages received for its datasource, used to hold
+    /// back sync checkpoints until the record has been written
+    pub sequence: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SchedulerConfig {
+    /// Maximum number of tasks held across all sub-queues
+    pub capacity: usize,
+    /// Number of consecutive tasks a team is served per round when it has no explicit weight
+    pub default_team_weight: u32,
+    /// Per-team weights keyed by `DataSources.team_id`
+    pub team_weights: HashMap<String, u32>,
+    /// Number of file uploads served for every bulk stream row when both are waiting
+    pub interactive_weight: u32,
+}
+
+impl SchedulerConfig {
+    pub fn from_global_data(global_data: &GlobalData) -> Self {
+        // Team weights are configured as `<team_id>:<weight>,<team_id>:<weight>`
+        let team_weights = global_data
+            .scheduler_team_weights
+            .split(',')
+            .filter_map(|pair| {
+                let (team_id, weight) = pair.trim().split_once(':')?;
+                match weight.trim().parse::<u32>() {
+                    Ok(weight) => Some((team_id.trim().to_string(), weight.max(1))),
+                    Err(_) => {
+                        log::warn!("Ignoring invalid scheduler weight for team: {}", team_id);
+                        None
+                    }
+                }
+            })
+            .collect();
+        SchedulerConfig {
+            capacity: global_data.ingestion_queue_capacity.max(1),
+            default_team_weight: global_data.scheduler_default_team_weight.max(1),
+            team_weights,
+            interactive_weight: global_data.scheduler_interactive_weight.max(1),
+        }
+    }
+
+    fn weight_for(&self, team_id: &str) -> u32 {
+        self.team_weights
+            .get(team_id)
+            .copied()
+            .unwrap_or(self.default_team_weight)
+    }
+}
+
+/// Tasks for a single team, kept in one FIFO sub-queue per datasource. Datasources are served
+/// round robin so one large sync can not hold up the team's other datasources.
+#[derive(Default)]
+struct TeamQueue {
+    datasources: HashMap<String, VecDeque<IngestionTask>>,
+    order: VecDeque<String>,
+}
+
+impl TeamQueue {
+    fn push(&mut self, datasource_id: String, task: IngestionTask) {
+        let queue = self.datasources.entry(datasource_id.clone()).or_default();
+        if queue.is_empty() {
+            self.order.push_back(datasource_id);
+        }
+        queue.push_back(task);
+    }
+
+    fn pop(&mut self) -> Option<IngestionTask> {
+        let datasource_id = self.order.pop_front()?;
+        let queue = self.datasources.get_mut(&datasource_id)?;
+        let task = queue.pop_front();
+        if queue.is_empty() {
+            self.datasources.remove(&datasource_id);
+        } else {
+            self.order.push_back(datasource_id);
+        }
+        task
+    }
+
+    fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = IngestionTask> + '_ {
+        self.order.clear();
+        self.datasources.drain().flat_map(|(_, queue)| queue)
+    }
+}
+
+/// A weighted round robin across teams. The team at the front of the rotation is served up to
+/// its weight in consecutive tasks before moving to the back.
+#[derive(Default)]
+struct PriorityLane {
+    teams: HashMap<String, TeamQueue>,
+    order: VecDeque<String>,
+    served_in_turn: u32,
+}
+
+impl PriorityLane {
+    fn push(&mut self, team_id: String, datasource_id: String, task: IngestionTask) {
+        let team = self.teams.entry(team_id.clone()).or_default();
+        if team.is_empty() {
+            self.order.push_back(team_id);
+        }
+        team.push(datasource_id, task);
+    }
+
+    fn pop(&mut self, config: &SchedulerConfig) -> Option<IngestionTask> {
+        let team_id = self.order.front()?.clone();
+        let team = self.teams.get_mut(&team_id)?;
+        let task = team.pop();
+        self.served_in_turn += 1;
+        if team.is_empty() {
+            self.teams.remove(&team_id);
+            self.order.pop_front();
+            self.served_in_turn = 0;
+        } else if self.served_in_turn >= config.weight_for(&team_id) {
+            self.order.rotate_left(1);
+            self.served_in_turn = 0;
+        }
+        task
+    }
+
+    fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    fn drain(&mut self) -> Vec<IngestionTask> {
+        self.order.clear();
+        self.served_in_turn = 0;
+        self.teams
+            .drain()
+            .flat_map(|(_, mut team)| team.drain().collect::<Vec<_>>())
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    interactive: PriorityLane,
+    bulk: PriorityLane,
+    interactive_streak: u32,
+    closed: bool,
+}
+
+/// Sits between the message queue consumers and the worker pool. File uploads go into the
+/// interactive lane and are preferred over Airbyte rows in the bulk lane, while a bulk task is
+/// still let through every `interactive_weight` tasks so syncs keep making progress.
+pub struct FairScheduler {
+    config: SchedulerConfig,
+    state: Mutex<SchedulerState>,
+    capacity: Semaphore,
+    notify: Notify,
+}
+
+impl FairScheduler {
+    pub fn new(config: SchedulerConfig) -> Self {
+        FairScheduler {
+            capacity: Semaphore::new(config.capacity),
+            config,
+            state: Mutex::new(SchedulerState::default()),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Adds a task to its team and datasource sub-queue. Waits while the scheduler is at capacity,
+    /// which in turn pauses consumption from the message queue.
+    pub async fn push(&self, task: IngestionTask) -> Result<(), IngestionTask> {
+        match self.capacity.acquire().await {
+            Ok(permit) => permit.forget(),
+            Err(_) => return Err(task),
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.closed {
+                return Err(task);
+            }
+            let team_id = task.datasource.team_id.to_hex();
+            let datasource_id = task.datasource.id.to_hex();
+            match task.envelope.source {
+                SourceKind::File => state.interactive.push(team_id, datasource_id, task),
+                SourceKind::StreamRecord => state.bulk.push(team_id, datasource_id, task),
+            }
+        }
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    /// Waits for the next task to process. Returns `None` once the scheduler has been closed.
+    pub async fn pop(&self) -> Option<IngestionTask> {
+        loop {
+            let notified = self.notify.notified();
+            match self.try_pop() {
+                Ok(Some(task)) => {
+                    self.capacity.add_permits(1);
+                    return Some(task);
+                }
+                Ok(None) => notified.await,
+                Err(()) => return None,
+            }
+        }
+    }
+
+    /// Stops accepting tasks and hands back everything still queued so it can be returned to the
+    /// message queue. Consumers waiting on capacity get their task back from `push`.
+    pub fn close(&self) -> Vec<IngestionTask> {
+        let remaining = {
+            let mut state = self.state.lock().unwrap();
+            state.closed = true;
+            let mut remaining = state.interactive.drain();
+            remaining.extend(state.bulk.drain());
+            remaining
+        };
+        self.capacity.close();
+        self.notify.notify_waiters();
+        remaining
+    }
+
+    fn try_pop(&self) -> Result<Option<IngestionTask>, ()> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(());
+        }
+        let prefer_interactive =
+            state.bulk.is_empty() || state.interactive_streak < self.config.interactive_weight;
+        if prefer_interactive && !state.interactive.is_empty() {
+            state.interactive_streak += 1;
+            return Ok(state.interactive.pop(&self.config));
+        }
+        state.interactive_streak = 0;
+        Ok(state.bulk.pop(&self.config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use mongodb::bson::{doc, oid::ObjectId};
+
+    use super::*;
+    use crate::messages::envelope::ENVELOPE_VERSION;
+
+    fn object_id(n: u8) -> ObjectId {
+        ObjectId::from_bytes([n; 12])
+    }
+
+    fn config(team_weights: &[(u8, u32)], interactive_weight: u32) -> SchedulerConfig {
+        SchedulerConfig {
+            capacity: 16,
+            default_team_weight: 1,
+            team_weights: team_weights
+                .iter()
+                .map(|(team, weight)| (object_id(*team).to_hex(), *weight))
+                .collect(),
+            interactive_weight,
+        }
+    }
+
+    /// A task labelled with `label` through its trace ID
+    fn task(team: u8, datasource: u8, source: SourceKind, label: &str) -> IngestionTask {
+        let datasource: DataSources = mongodb::bson::from_document(doc! {
+            "_id": object_id(datasource),
+            "orgId": object_id(0),
+            "teamId": object_id(team),
+            "name": "",
+            "originalName": "",
+            "sourceType": "",
+            "status": "",
+        })
+        .unwrap();
+        IngestionTask {
+            envelope: IngestionEnvelope {
+                version: ENVELOPE_VERSION,
+                datasource_id: datasource.id.to_hex(),
+                stream_key: None,
+                source,
+                file: None,
+                record: None,
+                trace_id: label.to_string(),
+            },
+            datasource,
+            ack: AckHandle::Detached,
+            sequence: None,
+        }
+    }
+
+    async fn push_all(scheduler: &FairScheduler, tasks: Vec<IngestionTask>) {
+        for task in tasks {
+            assert!(scheduler.push(task).await.is_ok());
+        }
+    }
+
+    async fn pop_labels(scheduler: &FairScheduler, count: usize) -> Vec<String> {
+        let mut labels = Vec::with_capacity(count);
+        for _ in 0..count {
+            labels.push(scheduler.pop().await.unwrap().envelope.trace_id);
+        }
+        labels
+    }
+
+    #[tokio::test]
+    async fn teams_are_served_up_to_their_weight_in_turn() {
+        let scheduler = FairScheduler::new(config(&[(1, 2)], 1));
+        let bulk = SourceKind::StreamRecord;
+        push_all(
+            &scheduler,
+            vec![
+                task(1, 10, bulk, "a1"),
+                task(1, 10, bulk, "a2"),
+                task(1, 10, bulk, "a3"),
+                task(1, 10, bulk, "a4"),
+                task(2, 20, bulk, "b1"),
+                task(2, 20, bulk, "b2"),
+                task(2, 20, bulk, "b3"),
+            ],
+        )
+        .await;
+        assert_eq!(
+            pop_labels(&scheduler, 7).await,
+            ["a1", "a2", "b1", "a3", "a4", "b2", "b3"]
+        );
+    }
+
+    #[tokio::test]
+    async fn datasources_of_a_team_take_turns() {
+        let scheduler = FairScheduler::new(config(&[(1, 10)], 1));
+        let bulk = SourceKind::StreamRecord;
+        push_all(
+            &scheduler,
+            vec![
+                task(1, 10, bulk, "x1"),
+                task(1, 10, bulk, "x2"),
+                task(1, 10, bulk, "x3"),
+                task(1, 11, bulk, "y1"),
+            ],
+        )
+        .await;
+        assert_eq!(pop_labels(&scheduler, 4).await, ["x1", "y1", "x2", "x3"]);
+    }
+
+    #[tokio::test]
+    async fn a_bulk_task_goes_through_every_interactive_weight_tasks() {
+        let scheduler = FairScheduler::new(config(&[], 2));
+        push_all(
+            &scheduler,
+            vec![
+                task(1, 10, SourceKind::StreamRecord, "r1"),
+                task(1, 10, SourceKind::StreamRecord, "r2"),
+                task(1, 11, SourceKind::File, "f1"),
+                task(1, 11, SourceKind::File, "f2"),
+                task(1, 11, SourceKind::File, "f3"),
+            ],
+        )
+        .await;
+        assert_eq!(
+            pop_labels(&scheduler, 5).await,
+            ["f1", "f2", "r1", "f3", "r2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn push_waits_for_capacity() {
+        let scheduler = FairScheduler::new(SchedulerConfig {
+            capacity: 1,
+            ..config(&[], 1)
+        });
+        push_all(&scheduler, vec![task(1, 10, SourceKind::File, "f1")]).await;
+        let blocked = scheduler.push(task(1, 10, SourceKind::File, "f2"));
+        tokio::pin!(blocked);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), &mut blocked)
+                .await
+                .is_err()
+        );
+        assert_eq!(pop_labels(&scheduler, 1).await, ["f1"]);
+        assert!(blocked.await.is_ok());
+        assert_eq!(pop_labels(&scheduler, 1).await, ["f2"]);
+    }
+
+    #[tokio::test]
+    async fn close_hands_back_queued_tasks_and_refuses_new_ones() {
+        let scheduler = FairScheduler::new(config(&[], 1));
+        push_all(
+            &scheduler,
+            vec![
+                task(1, 10, SourceKind::File, "f1"),
+                task(2, 20, SourceKind::StreamRecord, "r1"),
+            ],
+        )
+        .await;
+        let mut remaining: Vec<String> = scheduler
+            .close()
+            .into_iter()
+            .map(|task| task.envelope.trace_id)
+            .collect();
+        remaining.sort();
+        assert_eq!(remaining, ["f1", "r1"]);
+        let refused = scheduler.push(task(1, 10, SourceKind::File, "f2")).await;
+        assert!(refused.is_err_and(|task| task.envelope.trace_id == "f2"));
+        assert!(scheduler.pop().await.is_none());
+    }
+}
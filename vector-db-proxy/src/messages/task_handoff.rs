@@ -1,14 +1,16 @@
-use crate::adaptors::mongo::models::DataSources;
-use crossbeam::channel::Sender;
+use crate::messages::scheduler::{FairScheduler, IngestionTask};
+use std::sync::Arc;
 
-/// Adds the incoming task to the execution Queue to be processes when threads are available
-pub async fn send_task(
-    sender: Sender<(DataSources, Option<String>, String)>,
-    params: (DataSources, Option<String>, String),
-) {
-    let (dataset_id, stream_config_key, message) = params;
-    // Instantiate a new instance of the MyQueue
-    let _ = sender
-        .send((dataset_id, stream_config_key, message))
-        .map_err(|err| log::error!("An error occurred while sending task to channel: {}", err));
+/// Adds the incoming task to the scheduler to be processed when workers are available.
+/// The scheduler is bounded, so this waits (and in turn pauses queue consumption) while it is full.
+/// If the scheduler has been closed for shutdown the message is returned to the queue.
+pub async fn send_task(scheduler: Arc<FairScheduler>, task: IngestionTask) {
+    if let Err(task) = scheduler.push(task).await {
+        log::warn!(
+            "Could not schedule task with trace ID: {} for datasource: {}. Returning it to the queue",
+            task.envelope.trace_id,
+            task.datasource.id
+        );
+        task.ack.nack().await;
+    }
 }
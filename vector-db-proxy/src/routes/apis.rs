@@ -10,15 +10,27 @@ use crate::routes;
 
 use crate::adaptors::mongo::client::start_mongo_connection;
 use crate::adaptors::mongo::models::Model;
-use crate::adaptors::mongo::queries::{get_datasource, get_model, get_team_datasources};
+use crate::adaptors::mongo::queries::{
+    delete_datasource_documents, delete_point_cursors, get_datasource, get_datasource_documents,
+    get_embedding_usage, get_model, get_team_datasources,
+};
+use crate::data::chunk_preview::preview_chunks;
+use crate::data::documents::delete_document;
+use crate::data::point_id_migration::migrate_point_ids;
+use crate::data::sync_modes::promote_staging_collection_when_idle;
+use crate::embeddings::cache::embedding_cache_metrics;
 use crate::routes::helpers::format_error_message;
-use crate::routes::models::CollectionStorageSizeResponse;
+use crate::routes::models::{
+    ChunkPreviewRequest, CollectionStorageSizeResponse, UsageQuery, UsageTotalsResponse,
+};
 use crate::vector_databases::error::VectorDatabaseError;
 use crate::vector_databases::helpers::check_byo_vector_database;
 use crate::vector_databases::models::{
     CollectionCreate, Point, Region, SearchRequest, SearchType, VectorDatabaseStatus,
 };
 use crate::vector_databases::vector_database::{default_vector_db_client, VectorDatabase};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use routes::models::{ResponseBody, Status};
 use serde_json::json;
 use std::vec;
@@ -569,13 +581,22 @@ pub async fn delete_collection(
         .delete_collection(search_request)
         .await
     {
-        Ok(VectorDatabaseStatus::Ok) => Ok(HttpResponse::Ok()
-            .content_type(ContentType::json())
-            .json(json!(ResponseBody {
-                status: Status::Success,
-                data: None,
-                error_message: None
-            }))),
+        Ok(VectorDatabaseStatus::Ok) => {
+            // The registered documents went with the collection
+            if let Err(e) = delete_datasource_documents(&mongodb_connection, &dataset_id).await {
+                log::warn!("{}", e);
+            }
+            if let Err(e) = delete_point_cursors(&mongodb_connection, &dataset_id).await {
+                log::warn!("{}", e);
+            }
+            Ok(HttpResponse::Ok()
+                .content_type(ContentType::json())
+                .json(json!(ResponseBody {
+                    status: Status::Success,
+                    data: None,
+                    error_message: None
+                })))
+        }
         Err(e) => {
             let error_message_json = format_error_message(e.clone());
             Ok(HttpResponse::InternalServerError()
@@ -791,3 +812,245 @@ pub async fn get_storage_size(Path(team_id): Path<String>) -> Result<impl Respon
             error_message: None
         })))
 }
+
+/// Embedding usage of a team, per datasource and model, optionally limited to a time range with
+/// `from` and `to` (milliseconds since the epoch) and to one datasource with `datasource_id`
+#[wherr]
+#[get("/usage/{team_id}")]
+pub async fn get_team_embedding_usage(
+    Path(team_id): Path<String>,
+    query: web::Query<UsageQuery>,
+) -> Result<impl Responder> {
+    let mongodb_connection = start_mongo_connection().await?;
+    let usage = get_embedding_usage(
+        &mongodb_connection,
+        team_id.as_str(),
+        query.from,
+        query.to,
+        query.datasource_id.as_deref(),
+    )
+    .await?;
+    let totals = usage
+        .iter()
+        .fold(UsageTotalsResponse::default(), |mut totals, usage| {
+            totals.calls += usage.calls;
+            totals.items += usage.items;
+            totals.input_tokens += usage.input_tokens;
+            totals.estimated_cost += usage.estimated_cost;
+            totals
+        });
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .json(json!(ResponseBody {
+            status: Status::Success,
+            data: Some(json!({
+                "from": query.from,
+                "to": query.to,
+                "totals": totals,
+                "usage": usage
+            })),
+            error_message: None
+        })))
+}
+
+/// Called by the webapp when Airbyte reports a successful sync. The staging collection an overwrite
+/// sync wrote to replaces the live collection once the remaining rows have been embedded.
+#[wherr]
+#[post("/sync-complete/{datasource_id}")]
+pub async fn sync_complete(Path(datasource_id): Path<String>) -> Result<impl Responder> {
+    let mongodb_connection = start_mongo_connection().await?;
+    match get_datasource(&mongodb_connection, datasource_id.as_str()).await? {
+        Some(datasource) => {
+            let staging_collection = datasource.staging_collection.clone();
+            if staging_collection.is_some() {
+                tokio::spawn(async move {
+                    promote_staging_collection_when_idle(&mongodb_connection, &datasource_id).await;
+                });
+            }
+            Ok(HttpResponse::Ok()
+                .content_type(ContentType::json())
+                .json(json!(ResponseBody {
+                    status: Status::Success,
+                    data: Some(json!({"staging_collection": staging_collection})),
+                    error_message: None
+                })))
+        }
+        None => Ok(HttpResponse::NotFound()
+            .content_type(ContentType::json())
+            .json(json!(ResponseBody {
+                status: Status::NotFound,
+                data: None,
+                error_message: Some(json!({
+                    "errorMessage": format!("Datasource: '{}' does not exist", datasource_id)
+                }))
+            }))),
+    }
+}
+
+/// Re-keys the points of a datasource that were written with the legacy point ID scheme. Should be
+/// run for every datasource keyed on a primary key before upgrading the Rust toolchain.
+#[wherr]
+#[post("/migrate-point-ids/{datasource_id}")]
+pub async fn migrate_datasource_point_ids(
+    Path(datasource_id): Path<String>,
+) -> Result<impl Responder> {
+    let mongodb_connection = start_mongo_connection().await?;
+    match migrate_point_ids(&mongodb_connection, datasource_id.as_str()).await {
+        Ok(report) => Ok(HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .json(json!(ResponseBody {
+                status: Status::Success,
+                data: Some(json!(report)),
+                error_message: None
+            }))),
+        Err(e) => Ok(HttpResponse::InternalServerError()
+            .content_type(ContentType::json())
+            .json(json!(ResponseBody {
+                status: Status::Failure,
+                data: None,
+                error_message: Some(json!({"errorMessage": e.to_string()}))
+            }))),
+    }
+}
+
+/// Files ingested into a datasource, with how many points each one was stored as
+#[wherr]
+#[get("/documents/{datasource_id}")]
+pub async fn list_documents(Path(datasource_id): Path<String>) -> Result<impl Responder> {
+    let mongodb_connection = start_mongo_connection().await?;
+    let documents = get_datasource_documents(&mongodb_connection, datasource_id.as_str()).await?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .json(json!(ResponseBody {
+            status: Status::Success,
+            data: Some(json!({"documents": documents})),
+            error_message: None
+        })))
+}
+
+/// Removes a file from a datasource by deleting every point it was stored as
+#[wherr]
+#[delete("/document/{datasource_id}/{document_id}")]
+pub async fn delete_datasource_document(
+    Path((datasource_id, document_id)): Path<(String, String)>,
+) -> Result<impl Responder> {
+    let mongodb_connection = start_mongo_connection().await?;
+    match delete_document(
+        &mongodb_connection,
+        datasource_id.as_str(),
+        document_id.as_str(),
+    )
+    .await
+    {
+        Ok(Some(deleted_points)) => {
+            Ok(HttpResponse::Ok()
+                .content_type(ContentType::json())
+                .json(json!(ResponseBody {
+                    status: Status::Success,
+                    data: Some(json!({"deleted_points": deleted_points})),
+                    error_message: None
+                })))
+        }
+        Ok(None) => Ok(HttpResponse::NotFound()
+            .content_type(ContentType::json())
+            .json(json!(ResponseBody {
+                status: Status::NotFound,
+                data: None,
+                error_message: Some(json!({
+                    "errorMessage": format!("Document: '{}' does not exist in datasource: '{}'",
+                        document_id, datasource_id)
+                }))
+            }))),
+        Err(e) => Ok(HttpResponse::InternalServerError()
+            .content_type(ContentType::json())
+            .json(json!(ResponseBody {
+                status: Status::Failure,
+                data: None,
+                error_message: Some(json!({
+                    "errorMessage": format!("Could not delete document: '{}'. Error: {}",
+                        document_id, e)
+                }))
+            }))),
+    }
+}
+
+/// Hit and miss counts of the embedding cache since the process started
+#[wherr]
+#[get("/embedding-cache/metrics")]
+pub async fn get_embedding_cache_metrics() -> Result<impl Responder> {
+    let metrics = embedding_cache_metrics().await;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .json(json!(ResponseBody {
+            status: Status::Success,
+            data: Some(json!(metrics)),
+            error_message: None
+        })))
+}
+
+/// Chunks a file or text with the datasource's chunking settings, or the ones given, and returns
+/// the chunks with their sizes, token counts, metadata and what embedding them would cost.
+/// Nothing is embedded or stored. Served at `/chunk-preview`, registered in `main` with its own
+/// body size limit.
+#[wherr]
+pub async fn chunk_preview(data: web::Json<ChunkPreviewRequest>) -> Result<impl Responder> {
+    let request = data.into_inner();
+    let file = match (request.file, request.text) {
+        (Some(file), _) => BASE64.decode(file.trim()).map_err(|e| e.to_string()),
+        (None, Some(text)) => Ok(text.into_bytes()),
+        (None, None) => Err("Either a file or text must be given".to_string()),
+    };
+    let file = match file {
+        Ok(file) => file,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest()
+                .content_type(ContentType::json())
+                .json(json!(ResponseBody {
+                    status: Status::Failure,
+                    data: None,
+                    error_message: Some(json!({"errorMessage": e}))
+                })))
+        }
+    };
+    let mongodb_connection = start_mongo_connection().await?;
+    let datasource = get_datasource(&mongodb_connection, request.datasource_id.as_str()).await?;
+    let model = get_model(&mongodb_connection, request.datasource_id.as_str()).await?;
+    let (Some(datasource), Some(model)) = (datasource, model) else {
+        return Ok(HttpResponse::NotFound()
+            .content_type(ContentType::json())
+            .json(json!(ResponseBody {
+                status: Status::NotFound,
+                data: None,
+                error_message: Some(json!({
+                    "errorMessage": format!("Datasource: '{}' or its embedding model does not exist",
+                        request.datasource_id)
+                }))
+            })));
+    };
+    match preview_chunks(
+        &datasource,
+        &model,
+        file,
+        request.file_name,
+        request.chunking_config,
+    )
+    .await
+    {
+        Ok(preview) => Ok(HttpResponse::Ok()
+            .content_type(ContentType::json())
+            .json(json!(ResponseBody {
+                status: Status::Success,
+                data: Some(json!(preview)),
+                error_message: None
+            }))),
+        Err(e) => Ok(HttpResponse::UnprocessableEntity()
+            .content_type(ContentType::json())
+            .json(json!(ResponseBody {
+                status: Status::Failure,
+                data: None,
+                error_message: Some(json!({
+                    "errorMessage": format!("Could not chunk file. Error: {}", e)
+                }))
+            }))),
+    }
+}
@@ -1,3 +1,4 @@
+use crate::adaptors::mongo::models::UnstructuredChunkingConfig;
 use crate::vector_databases::models::StorageSize;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -23,3 +24,31 @@ pub struct CollectionStorageSizeResponse {
     pub total_size: f64,
     pub total_points: u64,
 }
+
+/// Time range of a usage query, in milliseconds since the epoch. Either end can be left open
+#[derive(Deserialize, Debug)]
+pub struct UsageQuery {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub datasource_id: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct UsageTotalsResponse {
+    pub calls: i64,
+    pub items: i64,
+    pub input_tokens: i64,
+    pub estimated_cost: f64,
+}
+
+/// A file or text to chunk as ingesting it into the datasource would. Files are sent base64
+/// encoded, and `chunking_config` takes the place of the datasource's own if given.
+#[derive(Deserialize, Debug)]
+pub struct ChunkPreviewRequest {
+    pub datasource_id: String,
+    pub file: Option<String>,
+    pub text: Option<String>,
+    /// Used to tell the file type when the content does not
+    pub file_name: Option<String>,
+    pub chunking_config: Option<UnstructuredChunkingConfig>,
+}
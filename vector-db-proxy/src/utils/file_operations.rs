@@ -3,8 +3,10 @@ use crate::data::models::FileType;
 use crate::utils::models::FileSources;
 use serde_json::Value;
 use std::fs::File;
-use std::io::Write;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
 use tokio::fs;
+use zip::ZipArchive;
 
 pub async fn save_file_to_disk(content: Vec<u8>, file_name: &str) -> anyhow::Result<()> {
     let file_path = file_name.trim_matches('"');
@@ -14,15 +16,136 @@ pub async fn save_file_to_disk(content: Vec<u8>, file_name: &str) -> anyhow::Res
     Ok(())
 }
 
+/// File type going by the extension of `file_path` alone
 pub fn determine_file_type(file_path: &str) -> FileType {
-    let file_path = format!("{}", file_path);
-    let file_path_split: Vec<&str> = file_path.split(".").collect();
-    let file_extension = file_path_split.to_vec()[1]
-        .to_string()
-        .trim_end_matches('"')
-        .to_string();
-    let file_type = FileType::from(file_extension);
-    file_type
+    Path::new(file_path.trim_matches('"'))
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(FileType::UNKNOWN, |extension| {
+            FileType::from(extension.to_string())
+        })
+}
+
+/// Tells apart the formats that are zip archives by the files inside them
+fn zip_file_type(content: &[u8]) -> Option<FileType> {
+    let mut archive = ZipArchive::new(Cursor::new(content)).ok()?;
+    for (entry, file_type) in [
+        ("word/document.xml", FileType::DOCX),
+        ("xl/workbook.xml", FileType::XLSX),
+        ("ppt/presentation.xml", FileType::PPTX),
+    ] {
+        if archive.by_name(entry).is_ok() {
+            return Some(file_type);
+        }
+    }
+    let mut mimetype = String::new();
+    archive
+        .by_name("mimetype")
+        .ok()?
+        .read_to_string(&mut mimetype)
+        .ok()?;
+    match mimetype.trim() {
+        "application/epub+zip" => Some(FileType::EPUB),
+        "application/vnd.oasis.opendocument.text" => Some(FileType::DOCX),
+        "application/vnd.oasis.opendocument.spreadsheet" => Some(FileType::XLSX),
+        "application/vnd.oasis.opendocument.presentation" => Some(FileType::PPTX),
+        _ => None,
+    }
+}
+
+/// Extension of the image format `content` is in, going by its magic bytes. Formats with
+/// signatures too short to tell them apart from text, such as BMP, are only known by extension.
+pub fn image_extension(content: &[u8]) -> Option<&'static str> {
+    let signatures: [(&[u8], &str); 6] = [
+        (b"\x89PNG\r\n\x1a\n", "png"),
+        (b"\xff\xd8\xff", "jpg"),
+        (b"GIF87a", "gif"),
+        (b"GIF89a", "gif"),
+        (b"II*\0", "tiff"),
+        (b"MM\0*", "tiff"),
+    ];
+    if let Some((_, extension)) = signatures
+        .iter()
+        .find(|(signature, _)| content.starts_with(signature))
+    {
+        return Some(extension);
+    }
+    if content.starts_with(b"RIFF") && content.get(8..12) == Some(b"WEBP") {
+        return Some("webp");
+    }
+    if matches!(content.get(4..12), Some(b"ftypheic") | Some(b"ftypmif1")) {
+        return Some("heic");
+    }
+    None
+}
+
+/// Recognises binary formats by their magic bytes. Compound files (legacy Word documents, Outlook
+/// messages and the like) all share one signature, so they are left to their extension.
+fn magic_file_type(content: &[u8]) -> Option<FileType> {
+    if content.starts_with(b"%PDF-") {
+        Some(FileType::PDF)
+    } else if content.starts_with(b"PK\x03\x04") {
+        zip_file_type(content)
+    } else if content.starts_with(b"{\\rtf") {
+        Some(FileType::RTF)
+    } else if image_extension(content).is_some() {
+        Some(FileType::IMAGE)
+    } else {
+        None
+    }
+}
+
+/// Recognises text formats that can be told apart by their content
+fn text_file_type(content: &[u8]) -> Option<FileType> {
+    let text = std::str::from_utf8(content).ok()?;
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    let head = text.chars().take(512).collect::<String>().to_lowercase();
+    if head.starts_with("<!doctype html") || head.starts_with("<html") {
+        return Some(FileType::HTML);
+    }
+    if text.starts_with('{') || text.starts_with('[') {
+        if serde_json::from_str::<Value>(text).is_ok() {
+            return Some(FileType::JSON);
+        }
+        let mut lines = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .peekable();
+        if lines.peek().is_some() && lines.all(|line| serde_json::from_str::<Value>(line).is_ok()) {
+            return Some(FileType::JSONL);
+        }
+    }
+    // Each message in a mailbox starts with a "From " line, followed by its headers
+    if text.starts_with("From ") && text.contains("\nFrom:") {
+        return Some(FileType::MBOX);
+    }
+    let headers = text
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .filter(|line| {
+            let name = line.split(':').next().unwrap_or_default().to_lowercase();
+            matches!(
+                name.as_str(),
+                "from" | "to" | "subject" | "date" | "message-id" | "mime-version" | "received"
+            )
+        })
+        .count();
+    if headers >= 3 {
+        return Some(FileType::EML);
+    }
+    Some(FileType::TXT)
+}
+
+/// Works out the file type from the file's content, going by its magic bytes first and its
+/// extension for formats without any. Text files with no extension we recognise are sniffed.
+pub fn detect_file_type(content: &[u8], file_path: &str) -> FileType {
+    if let Some(file_type) = magic_file_type(content) {
+        return file_type;
+    }
+    match determine_file_type(file_path) {
+        FileType::UNKNOWN => text_file_type(content).unwrap_or(FileType::UNKNOWN),
+        file_type => file_type,
+    }
 }
 
 pub async fn read_file_from_source(
@@ -38,7 +161,7 @@ pub async fn read_file_from_source(
                         match get_object_from_gcs(bucket_name.as_str()?, file_name.as_str()?).await
                         {
                             Ok(file) => {
-                                let file_type = determine_file_type(file_name.as_str()?);
+                                let file_type = detect_file_type(&file, file_name.as_str()?);
                                 let result = (file_type, file, file_name.to_string());
                                 Some(result)
                             }
@@ -60,7 +183,7 @@ pub async fn read_file_from_source(
                 if let Some(file_path) = message_data.get("file") {
                     match fs::read(file_path.as_str()?).await {
                         Ok(file) => {
-                            let file_type = determine_file_type(file_path.as_str()?);
+                            let file_type = detect_file_type(&file, file_path.as_str()?);
                             let results = (file_type, file, file_path.to_string());
                             Some(results)
                         }
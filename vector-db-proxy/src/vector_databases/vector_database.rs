@@ -6,6 +6,8 @@ use crate::vector_databases::models::*;
 use async_trait::async_trait;
 use pinecone_sdk::pinecone::PineconeClient;
 use qdrant_client::client::QdrantClient;
+use serde_json::Value;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -55,6 +57,37 @@ pub trait VectorDatabase: Send + Sync {
         search_request: SearchRequest,
         points: Vec<Point>,
     ) -> Result<VectorDatabaseStatus, VectorDatabaseError>;
+    async fn delete_points_by_ids(
+        &self,
+        search_request: SearchRequest,
+        ids: Vec<String>,
+    ) -> Result<VectorDatabaseStatus, VectorDatabaseError>;
+    /// Replaces the payload of an existing point, keeping its vector
+    async fn update_point_payload(
+        &self,
+        search_request: SearchRequest,
+        id: String,
+        payload: HashMap<String, Value>,
+    ) -> Result<VectorDatabaseStatus, VectorDatabaseError>;
+    /// Looks up a single point by ID, returning its payload and, if asked for, its vector
+    async fn get_point(
+        &self,
+        search_request: SearchRequest,
+        id: String,
+        with_vector: bool,
+    ) -> Result<Option<SearchResult>, VectorDatabaseError>;
+    /// Whether a collection can be referred to by an alias that can be repointed atomically, which
+    /// is what overwrite syncs need to stage into a fresh collection
+    fn supports_collection_aliases(&self) -> bool;
+    /// Atomically points the alias `search_request.collection` at `collection` and removes
+    /// whatever the alias pointed to before. Returns the alias `collection` is served under, which
+    /// is a new one when `search_request.collection` is a regular collection that can not be
+    /// aliased. That collection is left in place for the caller to remove.
+    async fn swap_collection_alias(
+        &self,
+        search_request: SearchRequest,
+        collection: String,
+    ) -> Result<String, VectorDatabaseError>;
     async fn get_collection_info(
         &self,
         search_request: SearchRequest,
@@ -68,6 +101,14 @@ pub trait VectorDatabase: Send + Sync {
         &self,
         search_request: SearchRequest,
     ) -> Result<Vec<ScrollResults>, VectorDatabaseError>;
+    /// Reads up to `limit` points with their payloads and vectors starting at `offset`, returning
+    /// the offset of the next page if there is one
+    async fn scroll_page(
+        &self,
+        search_request: SearchRequest,
+        offset: Option<String>,
+        limit: u32,
+    ) -> Result<(Vec<SearchResult>, Option<String>), VectorDatabaseError>;
 
     async fn similarity_search(
         &self,
@@ -3,7 +3,7 @@ import LoadingBar from 'components/LoadingBar';
 import { DatasourceStatus, datasourceStatusColors } from 'struct/datasource';
 
 function DatasourceStatusIndicator({ datasource }) {
-	const { total, success, failure, lastUpdated } = datasource?.recordCount || {};
+	const { total, success, failure, skipped, lastUpdated } = datasource?.recordCount || {};
 	const lastUpdatedAgo = lastUpdated ? Date.now() - lastUpdated : null;
 	const successPercentage = (total != null ? (success / total) * 100 : 0) || 0;
 	return (
@@ -11,7 +11,7 @@ function DatasourceStatusIndicator({ datasource }) {
 			{total > 0 && (
 				<span className='tooltiptext text-sm capitalize !w-[150px] !-ml-[75px] whitespace-pre'>
 					{total &&
-						`${(success || 0) + (failure || 0)}/${total} (${successPercentage.toFixed(1)}%)\nsuccess: ${success || 0}\nfailure: ${failure || 0}`}
+						`${(success || 0) + (failure || 0) + (skipped || 0)}/${total} (${successPercentage.toFixed(1)}%)\nsuccess: ${success || 0}\nfailure: ${failure || 0}\nskipped: ${skipped || 0}`}
 				</span>
 			)}
 			{DatasourceStatus.EMBEDDING === datasource.status && lastUpdatedAgo < 30000 ? (
@@ -153,10 +153,11 @@ export default function DatasourceTable({
 						.filter(d => d?.status !== DatasourceStatus.DRAFT) //Note: filtering drafts until we have a way to complete them ref #292
 						.map(datasource => {
 							//Note: please don't remove this without asking why first
-							const { total, success, failure, lastUpdated } = datasource?.recordCount || {};
+							const { total, success, failure, skipped, lastUpdated } = datasource?.recordCount || {};
 							const lastUpdatedAgo = lastUpdated ? Date.now() - lastUpdated : null;
 							const finished =
-								((success || 0) + (failure || 0) >= total || lastUpdatedAgo > 30000) && total > 0;
+								((success || 0) + (failure || 0) + (skipped || 0) >= total || lastUpdatedAgo > 30000) &&
+								total > 0;
 							finished && (datasource.status = DatasourceStatus.READY);
 							return (
 								<tr
@@ -17,6 +17,7 @@ import debug from 'debug';
 import * as airbyteSetup from 'lib/airbyte/setup';
 import posthog from 'lib/posthog';
 import { chainValidations } from 'lib/utils/validationutils';
+import VectorDBProxyClient from 'lib/vectorproxy/client';
 import toObjectId from 'misc/toobjectid';
 import { DatasourceStatus } from 'struct/datasource';
 import { CollectionName } from 'struct/db';
@@ -232,6 +233,10 @@ export async function handleSuccessfulSyncWebhook(req, res, next) {
 				incrementDatasourceTotalRecordCount(datasource.teamId, datasourceId, recordsLoaded)
 			]);
 			io.to(datasource.teamId.toString()).emit('notification', notification);
+			// Overwrite syncs write to a staging collection that replaces the live one from here
+			await VectorDBProxyClient.syncComplete(datasourceId).catch(e =>
+				warn('syncComplete failed for datasource %s: %O', datasourceId, e)
+			);
 		}
 	} else {
 		warn(`No match found in sync-success webhook body: ${JSON.stringify(req.body)}`);
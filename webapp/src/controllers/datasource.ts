@@ -36,8 +36,10 @@ import MessageQueueProviderFactory from 'queue/index';
 import StorageProviderFactory from 'storage/index';
 import { pricingMatrix } from 'struct/billing';
 import {
+	ChunkingBackendSet,
 	DatasourceScheduleType,
 	DatasourceStatus,
+	EmbeddingOverflowPolicySet,
 	getMetadataFieldInfo,
 	StreamConfig,
 	StreamConfigMap,
@@ -343,6 +345,7 @@ export async function addDatasourceApi(req, res, next) {
 		timeUnit,
 		chunkingConfig,
 		enableConnectorChunking,
+		overflowPolicy,
 		vectorDbId,
 		byoVectorDb,
 		collectionName,
@@ -386,6 +389,14 @@ export async function addDatasourceApi(req, res, next) {
 		return dynamicResponse(req, res, 400, { error: validationError });
 	}
 
+	if (overflowPolicy && !EmbeddingOverflowPolicySet.has(overflowPolicy)) {
+		return dynamicResponse(req, res, 400, { error: 'Invalid overflow policy' });
+	}
+
+	if (chunkingConfig?.backend && !ChunkingBackendSet.has(chunkingConfig.backend)) {
+		return dynamicResponse(req, res, 400, { error: 'Invalid chunking backend' });
+	}
+
 	if (!byoVectorDb && (!region || region === '')) {
 		return dynamicResponse(req, res, 400, { error: 'Region is required' });
 	}
@@ -456,7 +467,8 @@ export async function addDatasourceApi(req, res, next) {
 		similarity_threshold,
 		overlap,
 		overlap_all,
-		file_type
+		file_type,
+		backend
 	} = chunkingConfig || {};
 
 	// Update the datasource with the connection settings and sync date
@@ -478,9 +490,11 @@ export async function addDatasourceApi(req, res, next) {
 					overlap: parseInt(overlap),
 					similarity_threshold: parseFloat(similarity_threshold),
 					overlap_all: overlap_all === 'true',
-					file_type
+					file_type,
+					backend
 				}
 			: null, //TODO: validation
+		overflowPolicy,
 		vectorDbId: toObjectId(vectorDbId),
 		byoVectorDb,
 		region,
@@ -944,7 +958,12 @@ export async function uploadFileApi(req, res, next) {
 		new_after_n_chars,
 		overlap,
 		similarity_threshold,
-		overlap_all
+		overlap_all,
+		chunkingBackend,
+		overflowPolicy,
+		embeddingColumn,
+		embeddingTemplate,
+		primaryKey
 	} = req.body;
 
 	let validationError = chainValidations(
@@ -986,6 +1005,27 @@ export async function uploadFileApi(req, res, next) {
 		return dynamicResponse(req, res, 400, { error: validationError });
 	}
 
+	if (overflowPolicy && !EmbeddingOverflowPolicySet.has(overflowPolicy)) {
+		return dynamicResponse(req, res, 400, { error: 'Invalid overflow policy' });
+	}
+
+	if (chunkingBackend && !ChunkingBackendSet.has(chunkingBackend)) {
+		return dynamicResponse(req, res, 400, { error: 'Invalid chunking backend' });
+	}
+
+	//Note: multipart forms send the primary key as a comma separated string
+	const primaryKeyColumns = (
+		Array.isArray(primaryKey) ? primaryKey : (primaryKey || '').split(',')
+	)
+		.map(column => typeof column === 'string' && column.trim())
+		.filter(column => column);
+	if (
+		(embeddingColumn && typeof embeddingColumn !== 'string') ||
+		(embeddingTemplate && typeof embeddingTemplate !== 'string')
+	) {
+		return dynamicResponse(req, res, 400, { error: 'Invalid embedding column or template' });
+	}
+
 	const validMetadata = (req.body?.retriever_config?.metadata_field_info || []).every(obj => {
 		return (
 			typeof obj?.name === 'string' &&
@@ -1076,7 +1116,14 @@ export async function uploadFileApi(req, res, next) {
 			new_after_n_chars: new_after_n_chars ? parseInt(new_after_n_chars) : parseInt(max_characters),
 			overlap: parseInt(overlap),
 			similarity_threshold: parseFloat(similarity_threshold),
-			overlap_all: overlap_all === 'true'
+			overlap_all: overlap_all === 'true',
+			backend: chunkingBackend
+		},
+		overflowPolicy,
+		structuredConfig: {
+			embeddingColumn: embeddingColumn || undefined,
+			embeddingTemplate: embeddingTemplate || undefined,
+			primaryKey: primaryKeyColumns
 		}
 	});
 
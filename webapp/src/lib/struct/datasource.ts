@@ -51,6 +51,7 @@ export type DatasourceRecordCount = {
 	total?: number;
 	success?: number;
 	failure?: number;
+	skipped?: number; //unchanged rows that were not re-embedded
 };
 
 export const SyncModes = [
@@ -117,6 +118,12 @@ export type UnstructuredPartitioningStrategy =
 export const UnstructuredChunkingStrategySet = new Set(UnstructuredChunkingStrategyValues);
 export const UnstructuredPartitioningStrategySet = new Set(UnstructuredPartitioningStrategyValues);
 
+// Native chunking handles text, markdown and csv in the vector proxy, anything else is sent to unstructured.
+// PDFs are only parsed natively when 'native' is chosen explicitly, since scanned pages need unstructured's OCR
+export const ChunkingBackendValues = ['native', 'unstructured'] as const;
+export type ChunkingBackend = (typeof ChunkingBackendValues)[number];
+export const ChunkingBackendSet = new Set(ChunkingBackendValues);
+
 export type UnstructuredChunkingConfig = {
 	partitioning: UnstructuredPartitioningStrategy;
 	strategy: UnstructuredChunkingStrategy;
@@ -126,6 +133,18 @@ export type UnstructuredChunkingConfig = {
 	similarity_threshold: number; // between 0.0 and 1.0
 	overlap_all: boolean;
 	file_type?: 'txt' | 'markdown'; //Note: only used for connectors
+	backend?: ChunkingBackend;
+};
+
+export const EmbeddingOverflowPolicyValues = ['truncate', 'average', 'split'] as const;
+export type EmbeddingOverflowPolicy = (typeof EmbeddingOverflowPolicyValues)[number];
+export const EmbeddingOverflowPolicySet = new Set(EmbeddingOverflowPolicyValues);
+
+// Rows of uploaded csv, spreadsheet and jsonl files are embedded one point per row
+export type StructuredFileConfig = {
+	embeddingColumn?: string;
+	embeddingTemplate?: string; //e.g. "{name}: {description}", takes precedence over embeddingColumn
+	primaryKey?: string[]; //columns identifying a row, re-uploads replace rows with the same key
 };
 
 export interface Datasource {
@@ -149,6 +168,8 @@ export interface Datasource {
 	status?: DatasourceStatus;
 	discoveredSchema?: any;
 	chunkingConfig?: UnstructuredChunkingConfig;
+	overflowPolicy?: EmbeddingOverflowPolicy; //text longer than the embedding model accepts, defaults to truncate
+	structuredConfig?: StructuredFileConfig;
 	embeddingField?: string;
 	timeWeightField?: string;
 	modelId?: Types.ObjectId; //model id of embedding model in models collection
@@ -182,6 +203,8 @@ const datasourceSchema = new Schema<Datasource>(
 		status: String,
 		discoveredSchema: Object,
 		chunkingConfig: Object,
+		overflowPolicy: String,
+		structuredConfig: Object,
 		embeddingField: String,
 		timeWeightField: String,
 		modelId: { type: Schema.Types.ObjectId, ref: 'Model' },
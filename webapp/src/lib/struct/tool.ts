@@ -38,7 +38,15 @@ export type MultiQueryRetrieverConfig = {
 	//TODO: any specific configs?
 };
 
-export type RetrieverConfig = SelfQueryRetrieverConfig & TimeWeightedRetrieverConfig;
+// Matches on small chunks but returns their whole section or a window of neighbouring chunks
+export type ExpansionRetrieverConfig = {
+	expand?: 'section' | 'window';
+	window_size?: number;
+};
+
+export type RetrieverConfig = SelfQueryRetrieverConfig &
+	TimeWeightedRetrieverConfig &
+	ExpansionRetrieverConfig;
 
 export enum ToolState {
 	PENDING = 'pending',
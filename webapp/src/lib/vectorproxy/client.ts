@@ -8,6 +8,7 @@ dotenv.config({ path: '.env' });
 import { unsafeGetDatasourceById } from 'db/datasource';
 import { IdOrStr } from 'db/index';
 import { getModelById } from 'db/model';
+import { UnstructuredChunkingConfig } from 'struct/datasource';
 import { CollectionCreateBody, Distance, VectorResponseBody } from 'struct/vectorproxy';
 
 class VectorDBProxyClient {
@@ -77,6 +78,27 @@ class VectorDBProxyClient {
 		});
 	}
 
+	// Method to get the embedding usage for the team, optionally within a time range
+	static async getEmbeddingUsageForTeam(
+		teamId: IdOrStr,
+		range: { from?: Date; to?: Date; datasourceId?: IdOrStr } = {}
+	): Promise<VectorResponseBody> {
+		log('getEmbeddingUsageForTeam %s %O', teamId, range);
+		const params = new URLSearchParams();
+		if (range.from) {
+			params.set('from', range.from.getTime().toString());
+		}
+		if (range.to) {
+			params.set('to', range.to.getTime().toString());
+		}
+		if (range.datasourceId) {
+			params.set('datasource_id', range.datasourceId.toString());
+		}
+		return fetch(`${process.env.VECTOR_APP_URL}/api/v1/usage/${teamId}?${params}`).then(res => {
+			return res.json();
+		});
+	}
+
 	// Method to delete a collection
 	static async deleteCollection(collectionId: IdOrStr): Promise<VectorResponseBody> {
 		log('deleteCollection %s', collectionId);
@@ -84,6 +106,58 @@ class VectorDBProxyClient {
 			method: 'DELETE'
 		}).then(res => res.json());
 	}
+
+	// Method to promote the collection an overwrite sync was written to once the sync has finished
+	static async syncComplete(datasourceId: IdOrStr): Promise<VectorResponseBody> {
+		log('syncComplete %s', datasourceId);
+		return fetch(`${process.env.VECTOR_APP_URL}/api/v1/sync-complete/${datasourceId}`, {
+			method: 'POST'
+		}).then(res => res.json());
+	}
+
+	// Method to list the files ingested into a datasource
+	static async listDocuments(datasourceId: IdOrStr): Promise<VectorResponseBody> {
+		log('listDocuments %s', datasourceId);
+		return fetch(`${process.env.VECTOR_APP_URL}/api/v1/documents/${datasourceId}`).then(res => {
+			return res.json();
+		});
+	}
+
+	// Method to delete one file of a datasource along with its points
+	static async deleteDocument(
+		datasourceId: IdOrStr,
+		documentId: IdOrStr
+	): Promise<VectorResponseBody> {
+		log('deleteDocument %s %s', datasourceId, documentId);
+		return fetch(
+			`${process.env.VECTOR_APP_URL}/api/v1/document/${datasourceId}/${documentId}`,
+			{ method: 'DELETE' }
+		).then(res => res.json());
+	}
+
+	// Method to chunk a file or text with a chunking config without embedding or storing anything
+	static async previewChunks(
+		datasourceId: IdOrStr,
+		input: {
+			file?: Buffer;
+			text?: string;
+			fileName?: string;
+			chunkingConfig?: UnstructuredChunkingConfig;
+		}
+	): Promise<VectorResponseBody> {
+		log('previewChunks %s %s', datasourceId, input.fileName);
+		return fetch(`${process.env.VECTOR_APP_URL}/api/v1/chunk-preview`, {
+			method: 'POST',
+			headers: { 'Content-Type': 'application/json' },
+			body: JSON.stringify({
+				datasource_id: datasourceId.toString(),
+				file: input.file?.toString('base64'),
+				text: input.text,
+				file_name: input.fileName,
+				chunking_config: input.chunkingConfig
+			})
+		}).then(res => res.json());
+	}
 }
 
 export default VectorDBProxyClient;